@@ -0,0 +1,85 @@
+//! Ingestion throughput of a single core on realistic Caddy access logs.
+//!
+//! Run with `cargo bench --bench parse` (add `--features simd` for the SIMD parser),
+//! the reported throughput is in lines per second.
+
+use std::time::SystemTime;
+
+use caddy_alog_clickhouse_sink::{
+    config::Config,
+    log::{
+        db::{DbAccessLogEntry, DbAccessLogRow},
+        LineParser, LogLine,
+    },
+    visitors::VisitorKeys,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use klickhouse::{IndexMap, Row};
+
+/// Mix of browser, API (reverse proxied) and bot requests, one entry per line
+const FIXTURES: &str = include_str!("fixtures/caddy_access.jsonl");
+
+fn bench_config() -> Config {
+    for (key, value) in [
+        ("BIND_TO", "127.0.0.1:0"),
+        ("CH_HOSTS", "127.0.0.1:9000"),
+        ("CH_USER", "default"),
+        ("CH_PASSWORD", ""),
+        ("CH_DATABASE", "default"),
+        ("SERVICE_NAME", "caddy"),
+        ("ENVIRONMENT", "bench"),
+    ] {
+        std::env::set_var(key, value);
+    }
+
+    Config::load().expect("bench config is valid")
+}
+
+fn throughput(c: &mut Criterion) {
+    let config = bench_config();
+    let lines = FIXTURES.lines().collect::<Vec<_>>();
+    let mut parser = LineParser::default();
+
+    let mut group = c.benchmark_group("lines");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+
+    // JSON parsing only
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for line in &lines {
+                let mut line = line.as_bytes().to_vec();
+                black_box(parser.parse(&mut line).unwrap());
+            }
+        })
+    });
+
+    // everything, that happens to a line before it's sent to Clickhouse
+    group.bench_function("ingest", |b| {
+        let type_hints = IndexMap::new();
+        let visitors = VisitorKeys::default();
+
+        b.iter(|| {
+            for line in &lines {
+                let mut line = line.as_bytes().to_vec();
+                let LogLine::Access(entry) = parser.parse(&mut line).unwrap() else {
+                    panic!("fixtures are access log entries");
+                };
+                let row = DbAccessLogEntry::new(
+                    uuid::Uuid::now_v7(),
+                    &config,
+                    SystemTime::now(),
+                    &visitors,
+                    false,
+                    entry,
+                )
+                .unwrap();
+                black_box(DbAccessLogRow(row).serialize_row(&type_hints).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+-- Reverse proxy upstream info.
+--
+-- Filled from the fields added to the access log with Caddy's `log_append`, e.g.:
+--
+--   log_append upstream_addr {http.reverse_proxy.upstream.hostport}
+--   log_append upstream_status {http.reverse_proxy.status_code}
+--   log_append upstream_latency_ms {http.reverse_proxy.upstream.latency_ms}
+--
+-- or from the response headers, configured with UPSTREAM_*_HEADER and UPSTREAM_SERVER_TIMING_METRIC.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS upstream_addr LowCardinality(Nullable(String)),
+    ADD COLUMN IF NOT EXISTS upstream_status Nullable(UInt16),
+    ADD COLUMN IF NOT EXISTS upstream_latency Nullable(Float64);
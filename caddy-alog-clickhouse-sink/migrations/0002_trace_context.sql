@@ -0,0 +1,15 @@
+-- W3C trace context and request id, to join access log rows with traces.
+--
+-- Taken from `traceparent`/`tracestate` request (or response) headers,
+-- falling back to TRACE_ID_HEADERS, SPAN_ID_HEADERS; request id comes from REQUEST_ID_HEADERS.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS trace_id Nullable(String),
+    ADD COLUMN IF NOT EXISTS span_id Nullable(String),
+    ADD COLUMN IF NOT EXISTS trace_state Nullable(String),
+    ADD COLUMN IF NOT EXISTS request_id Nullable(String),
+    ADD INDEX IF NOT EXISTS idx_trace_id trace_id TYPE bloom_filter(0.01) GRANULARITY 4,
+    ADD INDEX IF NOT EXISTS idx_request_id request_id TYPE bloom_filter(0.01) GRANULARITY 4;
+
+-- Builds the indexes for the already existing parts
+ALTER TABLE access_log MATERIALIZE INDEX idx_trace_id;
+ALTER TABLE access_log MATERIALIZE INDEX idx_request_id;
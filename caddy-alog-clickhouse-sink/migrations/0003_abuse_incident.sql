@@ -0,0 +1,16 @@
+-- Scanner and brute-force incidents, recorded when an IP gets banned (ABUSE_DETECTION=true).
+CREATE TABLE IF NOT EXISTS abuse_incident
+(
+    id UUID,
+    detected_at DateTime64(3, 'UTC'),
+    service LowCardinality(String),
+    environment LowCardinality(String),
+    ip String,
+    host LowCardinality(String),
+    reason LowCardinality(String),
+    hits UInt32,
+    sample_uri String,
+    banned_until DateTime64(3, 'UTC')
+)
+ENGINE = MergeTree
+ORDER BY (service, detected_at, ip);
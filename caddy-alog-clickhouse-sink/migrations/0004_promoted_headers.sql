@@ -0,0 +1,14 @@
+-- Commonly queried headers, moved out of the `headers`/`response_headers` maps.
+--
+-- Columns match the default PROMOTED_HEADERS and PROMOTED_RESPONSE_HEADERS,
+-- other promoted headers need a `header_<name>`/`response_header_<name>` String column
+-- (lowercased, non-alphanumeric characters replaced with `_`). Missing headers are empty strings,
+-- repeated ones are joined with `, `.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS header_referer String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS header_user_agent LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS header_content_type LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS header_accept_language LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS header_x_forwarded_for String DEFAULT '',
+    ADD COLUMN IF NOT EXISTS response_header_content_type LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS response_header_cache_control LowCardinality(String) DEFAULT '';
@@ -0,0 +1,52 @@
+-- Unique visitors per day and host, without storing identities.
+--
+-- `visitor_key` is a hash of the host, client IP and User-Agent, keyed with a random salt
+-- of the day (UTC) the entry was logged on, which never leaves the sink's memory, so keys
+-- of different days can't be linked. A day's salt is dropped VISITOR_MAX_LAG_SECS after its end,
+-- entries arriving later have no key. Every sink instance (and every restart) has its own salt,
+-- so a visitor may be counted more than once a day in such cases.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS visitor_key UInt64 DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS visitors_daily
+(
+    day Date,
+    service LowCardinality(String),
+    environment LowCardinality(String),
+    host LowCardinality(String),
+    visitors AggregateFunction(uniq, UInt64),
+    requests SimpleAggregateFunction(sum, UInt64)
+)
+ENGINE = AggregatingMergeTree
+ORDER BY (service, environment, host, day);
+
+-- Successful GET requests only, rows inserted before the column was added have no key
+CREATE MATERIALIZED VIEW IF NOT EXISTS visitors_daily_mv TO visitors_daily AS
+SELECT
+    toDate(logger_timestamp) AS day,
+    service,
+    environment,
+    host,
+    uniqState(visitor_key) AS visitors,
+    toUInt64(count()) AS requests
+FROM access_log
+WHERE visitor_key != 0 AND method = 'GET' AND status < 400
+GROUP BY day, service, environment, host;
+
+-- Entries routed to another table (ROUTES) aren't seen by this view, such a table needs its own,
+-- e.g. for `table = "access_log_errors"`:
+--
+--   CREATE MATERIALIZED VIEW IF NOT EXISTS visitors_daily_access_log_errors_mv TO visitors_daily AS
+--   SELECT toDate(logger_timestamp) AS day, service, environment, host,
+--       uniqState(visitor_key) AS visitors, toUInt64(count()) AS requests
+--   FROM access_log_errors
+--   WHERE visitor_key != 0 AND method = 'GET' AND status < 400
+--   GROUP BY day, service, environment, host;
+
+-- Approximate unique visitors, e.g.:
+--
+--   SELECT day, host, uniqMerge(visitors) AS visitors, sum(requests) AS requests
+--   FROM visitors_daily
+--   WHERE service = 'caddy' AND environment = 'production'
+--   GROUP BY day, host
+--   ORDER BY day, host
@@ -0,0 +1,8 @@
+-- Ingestion metadata, set by the sink.
+--
+-- `ingested_at` is the time the sink received the entry, `ingested_at - logger_timestamp`
+-- is the delivery lag (negative if the clock of the Caddy host is ahead).
+-- `sink_instance` is the INSTANCE_ID of the receiving sink, the host name by default.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS sink_instance LowCardinality(String) DEFAULT '',
+    ADD COLUMN IF NOT EXISTS ingested_at DateTime64(3, 'UTC') DEFAULT logger_timestamp;
@@ -0,0 +1,8 @@
+-- Microsecond precision of the log timestamps.
+--
+-- Existing values are converted in place. If `logger_timestamp` is a part of the sorting
+-- or partition key, Clickhouse refuses to modify it: create a new table with the same schema
+-- and `DateTime64(6, 'UTC')`, copy the rows with `INSERT INTO ... SELECT` and swap the tables
+-- with `EXCHANGE TABLES`.
+ALTER TABLE access_log
+    MODIFY COLUMN logger_timestamp DateTime64(6, 'UTC');
@@ -0,0 +1,25 @@
+-- Logs of Caddy's other loggers: handler errors (`http.log.error`), reverse proxy, TLS/ACME,
+-- admin API, etc. Access logs (`http.log.access*`) still go to `access_log`.
+--
+-- Only the fields common to all loggers are columns, the rest is kept in `fields` as a JSON object,
+-- e.g. failed certificate renewals:
+--
+--   SELECT logger_timestamp, JSONExtractString(fields, 'identifier') AS identifier, message
+--   FROM caddy_log
+--   WHERE logger LIKE 'tls.%' AND level = 'error'
+--   ORDER BY logger_timestamp DESC
+CREATE TABLE IF NOT EXISTS caddy_log
+(
+    id UUID,
+    service LowCardinality(String),
+    environment LowCardinality(String),
+    sink_instance LowCardinality(String),
+    ingested_at DateTime64(3, 'UTC'),
+    level LowCardinality(String),
+    logger_timestamp DateTime64(6, 'UTC'),
+    logger LowCardinality(String),
+    message String,
+    fields String
+)
+ENGINE = MergeTree
+ORDER BY (service, environment, logger, logger_timestamp);
@@ -0,0 +1,41 @@
+-- Syslog messages (RFC 5424 and the legacy RFC 3164) of other devices, received on SYSLOG_BIND_TO.
+--
+-- `timestamp` is the sender's time, or the receive time if it has none. Messages with an implausible
+-- timestamp (e.g. of a device without a real-time clock) are rejected. RFC 3164 timestamps have
+-- no time zone and are taken as UTC.
+-- `structured_data` is a JSON object of the elements' parameters, keyed by the element ids, e.g.:
+--
+--   SELECT timestamp, hostname, message
+--   FROM syslog
+--   WHERE severity <= 3 AND JSONExtractString(structured_data, 'origin', 'software') = 'dnsmasq'
+--   ORDER BY timestamp DESC
+CREATE TABLE IF NOT EXISTS syslog
+(
+    id UUID,
+    service LowCardinality(String),
+    environment LowCardinality(String),
+    sink_instance LowCardinality(String),
+    ingested_at DateTime64(3, 'UTC'),
+    remote_ip String,
+    facility UInt8,
+    severity UInt8,
+    facility_name LowCardinality(String) ALIAS arrayElement(
+        ['kern', 'user', 'mail', 'daemon', 'auth', 'syslog', 'lpr', 'news', 'uucp', 'cron', 'authpriv',
+         'ftp', 'ntp', 'security', 'console', 'solaris-cron', 'local0', 'local1', 'local2', 'local3',
+         'local4', 'local5', 'local6', 'local7'],
+        facility + 1
+    ),
+    severity_name LowCardinality(String) ALIAS arrayElement(
+        ['emerg', 'alert', 'crit', 'err', 'warning', 'notice', 'info', 'debug'],
+        severity + 1
+    ),
+    timestamp DateTime64(6, 'UTC'),
+    hostname LowCardinality(String),
+    app_name LowCardinality(String),
+    proc_id String,
+    msg_id LowCardinality(String),
+    structured_data String,
+    message String
+)
+ENGINE = MergeTree
+ORDER BY (service, environment, hostname, timestamp);
@@ -0,0 +1,7 @@
+-- Lines with invalid UTF-8 are stored with the invalid sequences replaced by U+FFFD,
+-- `lossy_utf8` marks such entries.
+ALTER TABLE access_log
+    ADD COLUMN IF NOT EXISTS lossy_utf8 Bool DEFAULT false;
+
+ALTER TABLE caddy_log
+    ADD COLUMN IF NOT EXISTS lossy_utf8 Bool DEFAULT false;
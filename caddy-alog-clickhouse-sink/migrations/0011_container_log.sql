@@ -0,0 +1,28 @@
+-- Logs of Docker containers, tailed from the json-file driver's files matching DOCKER_LOG_PATHS
+-- (e.g. /var/lib/docker/containers/*/*-json.log).
+--
+-- `container_id` is taken from the file name, `container_name` from the container's config.v2.json
+-- next to it (the short id if there's none). Messages Docker split into 16 KiB parts are joined.
+-- `attrs` is a JSON object of the attributes added with the `labels`/`env` log options, e.g.:
+--
+--   SELECT timestamp, container_name, message
+--   FROM container_log
+--   WHERE stream = 'stderr' AND JSONExtractString(attrs, 'com.docker.compose.project') = 'media'
+--   ORDER BY timestamp DESC
+CREATE TABLE IF NOT EXISTS container_log
+(
+    id UUID,
+    service LowCardinality(String),
+    environment LowCardinality(String),
+    sink_instance LowCardinality(String),
+    ingested_at DateTime64(3, 'UTC'),
+    container_id String,
+    container_name LowCardinality(String),
+    stream LowCardinality(String),
+    timestamp DateTime64(6, 'UTC'),
+    message String,
+    attrs String,
+    lossy_utf8 Bool
+)
+ENGINE = MergeTree
+ORDER BY (service, environment, container_name, timestamp);
@@ -0,0 +1,236 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use arc_swap::ArcSwap;
+use eyre::Result;
+use tracing::warn;
+
+use crate::{config::Config, log::db::DbAccessLogEntry};
+
+mod blocklist;
+pub mod db;
+
+use self::{blocklist::Blocklist, db::DbAbuseIncident};
+
+/// Why an IP was considered abusive
+#[derive(Clone, Copy, Debug)]
+enum Reason {
+    /// Too many requests to the well-known probe paths
+    Probe,
+    /// Too many 404 responses
+    NotFoundBurst,
+    /// Too many 401 responses
+    UnauthorizedBurst,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Probe => "probe",
+            Self::NotFoundBurst => "not_found_burst",
+            Self::UnauthorizedBurst => "unauthorized_burst",
+        }
+    }
+}
+
+/// Recent suspicious requests of a single IP
+#[derive(Default)]
+struct IpWindows {
+    probes: VecDeque<Instant>,
+    not_found: VecDeque<Instant>,
+    unauthorized: VecDeque<Instant>,
+}
+
+impl IpWindows {
+    fn is_empty(&self) -> bool {
+        self.probes.is_empty() && self.not_found.is_empty() && self.unauthorized.is_empty()
+    }
+
+    fn prune(&mut self, since: Instant) {
+        for window in [
+            &mut self.probes,
+            &mut self.not_found,
+            &mut self.unauthorized,
+        ] {
+            while window.front().is_some_and(|hit| *hit < since) {
+                window.pop_front();
+            }
+        }
+    }
+}
+
+/// Thresholds of the detector, can be changed at runtime
+#[derive(Debug)]
+struct AbuseRules {
+    enabled: bool,
+    window: Duration,
+    ban_duration: Duration,
+    probe_paths: Vec<String>,
+    probe_threshold: usize,
+    not_found_threshold: usize,
+    unauthorized_threshold: usize,
+}
+
+impl AbuseRules {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            enabled: *config.abuse_detection(),
+            window: config.abuse_window(),
+            ban_duration: config.abuse_ban_duration(),
+            probe_paths: config
+                .abuse_probe_paths()
+                .iter()
+                .map(|probe| probe.to_ascii_lowercase())
+                .collect(),
+            probe_threshold: *config.abuse_probe_threshold(),
+            not_found_threshold: *config.abuse_not_found_threshold(),
+            unauthorized_threshold: *config.abuse_unauthorized_threshold(),
+        }
+    }
+
+    fn is_probe(&self, uri: &str) -> bool {
+        let path = uri
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        self.probe_paths
+            .iter()
+            .any(|probe| path.contains(probe.as_str()))
+    }
+}
+
+/// Streaming detector of scanners and brute-forcers.
+///
+/// Tracks suspicious requests per client IP over a sliding window
+/// and bans the IP once any of the thresholds is reached.
+pub struct AbuseDetector {
+    rules: ArcSwap<AbuseRules>,
+    windows: Mutex<HashMap<IpAddr, IpWindows>>,
+    blocklist: Blocklist,
+}
+
+impl AbuseDetector {
+    pub fn new(config: &Config) -> Result<Self> {
+        let blocklist = Blocklist::load(
+            config.blocklist_path().clone(),
+            config.blocklist_matcher().clone(),
+        )?;
+
+        Ok(Self {
+            rules: ArcSwap::from_pointee(AbuseRules::from_config(config)),
+            windows: Mutex::new(HashMap::new()),
+            blocklist,
+        })
+    }
+
+    /// Applies the new thresholds, tracked windows and bans are kept
+    pub fn reload(&self, config: &Config) {
+        self.rules.store(Arc::new(AbuseRules::from_config(config)));
+    }
+
+    /// Accounts the entry, returns an incident if its IP has just been banned
+    pub fn observe(&self, entry: &DbAccessLogEntry) -> Option<DbAbuseIncident> {
+        let rules = self.rules.load();
+        if !rules.enabled {
+            return None;
+        }
+
+        let is_probe = rules.is_probe(entry.uri());
+        let status = *entry.status();
+        if !is_probe && status != 404 && status != 401 {
+            return None;
+        }
+
+        let ip: IpAddr = entry.client_addr().parse().ok()?;
+        if self.blocklist.is_banned(&ip) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let (reason, hits) = {
+            let mut windows = self.windows.lock().unwrap();
+            let ip_windows = windows.entry(ip).or_default();
+            ip_windows.prune(now.checked_sub(rules.window).unwrap_or(now));
+
+            if is_probe {
+                ip_windows.probes.push_back(now);
+            }
+            match status {
+                404 => ip_windows.not_found.push_back(now),
+                401 => ip_windows.unauthorized.push_back(now),
+                _ => {}
+            }
+
+            let exceeded = [
+                (
+                    Reason::Probe,
+                    ip_windows.probes.len(),
+                    rules.probe_threshold,
+                ),
+                (
+                    Reason::NotFoundBurst,
+                    ip_windows.not_found.len(),
+                    rules.not_found_threshold,
+                ),
+                (
+                    Reason::UnauthorizedBurst,
+                    ip_windows.unauthorized.len(),
+                    rules.unauthorized_threshold,
+                ),
+            ]
+            .into_iter()
+            .find(|(_, hits, threshold)| hits >= threshold)?;
+
+            windows.remove(&ip);
+            (exceeded.0, exceeded.1)
+        };
+
+        let detected_at = SystemTime::now();
+        let banned_until = detected_at + rules.ban_duration;
+        self.blocklist.ban(ip, banned_until, reason.as_str());
+        warn!(%ip, reason = reason.as_str(), hits, "Banned abusive client");
+
+        Some(DbAbuseIncident::new(
+            entry.service(),
+            entry.environment(),
+            ip.to_string(),
+            entry.host().clone(),
+            reason.as_str(),
+            hits as u32,
+            entry.uri().clone(),
+            detected_at,
+            banned_until,
+        ))
+    }
+
+    /// Drops the windows of IPs, which haven't made suspicious requests lately
+    fn sweep(&self, window: Duration) {
+        let now = Instant::now();
+        let since = now.checked_sub(window).unwrap_or(now);
+
+        self.windows.lock().unwrap().retain(|_, ip_windows| {
+            ip_windows.prune(since);
+            !ip_windows.is_empty()
+        });
+    }
+
+    /// Background maintenance: expires bans, keeps the blocklist file updated
+    /// and forgets the IPs, which calmed down
+    pub async fn run(&self) {
+        let sweep = async {
+            loop {
+                let window = self.rules.load().window;
+                tokio::time::sleep(window).await;
+                self.sweep(window);
+            }
+        };
+
+        tokio::join!(self.blocklist.run(Duration::from_secs(60)), sweep);
+    }
+}
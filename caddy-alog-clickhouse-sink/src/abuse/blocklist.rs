@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{Result, WrapErr};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+/// Prefix of the comment lines, which keep the ban expiry in the blocklist file
+const BAN_COMMENT_PREFIX: &str = "# ban ";
+
+struct Ban {
+    until: SystemTime,
+    reason: String,
+}
+
+/// Set of banned IPs with expiry, mirrored to a Caddyfile snippet with a named `remote_ip` matcher.
+///
+/// The file can be imported into a site block and used like this:
+///
+/// ```caddyfile
+/// import /etc/caddy/blocklist.caddy
+/// abort @abusers
+/// ```
+pub struct Blocklist {
+    path: Option<PathBuf>,
+    matcher: String,
+    bans: Mutex<BTreeMap<IpAddr, Ban>>,
+    changed: Notify,
+}
+
+impl Blocklist {
+    /// Creates the blocklist, restoring the not yet expired bans from the file, if it exists
+    pub fn load(path: Option<PathBuf>, matcher: String) -> Result<Self> {
+        let mut bans = BTreeMap::new();
+
+        if let Some(path) = path.as_deref().filter(|path| path.exists()) {
+            let contents = std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read blocklist {}", path.display()))?;
+            let now = SystemTime::now();
+
+            for line in contents.lines() {
+                let Some((ip, ban)) = parse_ban_comment(line) else {
+                    continue;
+                };
+                if ban.until > now {
+                    bans.insert(ip, ban);
+                }
+            }
+            info!(
+                bans = bans.len(),
+                "Restored blocklist from {}",
+                path.display()
+            );
+        }
+
+        Ok(Self {
+            path,
+            matcher,
+            bans: Mutex::new(bans),
+            changed: Notify::new(),
+        })
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.bans
+            .lock()
+            .unwrap()
+            .get(ip)
+            .is_some_and(|ban| ban.until > SystemTime::now())
+    }
+
+    /// Bans the IP (or extends an existing ban), the file is rewritten in background
+    pub fn ban(&self, ip: IpAddr, until: SystemTime, reason: &str) {
+        let mut bans = self.bans.lock().unwrap();
+        let ban = bans.entry(ip).or_insert_with(|| Ban {
+            until,
+            reason: reason.to_string(),
+        });
+        if ban.until < until {
+            ban.until = until;
+            ban.reason = reason.to_string();
+        }
+        drop(bans);
+
+        self.changed.notify_one();
+    }
+
+    /// Removes the expired bans, returns whether anything was removed
+    fn sweep(&self) -> bool {
+        let now = SystemTime::now();
+        let mut bans = self.bans.lock().unwrap();
+        let before = bans.len();
+        bans.retain(|_, ban| ban.until > now);
+
+        bans.len() != before
+    }
+
+    fn render(&self) -> String {
+        let bans = self.bans.lock().unwrap();
+        let mut out =
+            String::from("# Managed by caddy-alog-clickhouse-sink, changes will be overwritten\n");
+
+        for (ip, ban) in bans.iter() {
+            let until = ban.until.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(
+                out,
+                "{BAN_COMMENT_PREFIX}{ip} {} {}",
+                until.as_secs(),
+                ban.reason
+            )
+            .unwrap();
+        }
+
+        if bans.is_empty() {
+            // `remote_ip` requires at least one range, so an always-false matcher is used instead
+            writeln!(out, "@{} expression false", self.matcher).unwrap();
+        } else {
+            let ips = bans.keys().map(ToString::to_string).collect::<Vec<_>>();
+            writeln!(out, "@{} remote_ip {}", self.matcher, ips.join(" ")).unwrap();
+        }
+
+        out
+    }
+
+    async fn write(&self, path: &Path) -> Result<()> {
+        // written to a temporary file first, so Caddy never sees a partially written one
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, self.render())
+            .await
+            .wrap_err_with(|| format!("Failed to write {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .wrap_err_with(|| format!("Failed to replace {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Keeps the file in sync with the bans: rewrites it on every change and expires old bans
+    pub async fn run(&self, sweep_interval: Duration) {
+        let mut sweep = tokio::time::interval(sweep_interval);
+        // makes sure the file exists, so that Caddy can import it
+        self.changed.notify_one();
+
+        loop {
+            let changed = tokio::select! {
+                _ = self.changed.notified() => true,
+                _ = sweep.tick() => self.sweep(),
+            };
+            if !changed {
+                continue;
+            }
+
+            let Some(path) = &self.path else {
+                continue;
+            };
+            match self.write(path).await {
+                Ok(()) => debug!("Blocklist updated"),
+                Err(e) => warn!("Failed to update blocklist: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Parses `# ban <ip> <expires at, unix seconds> <reason>`
+fn parse_ban_comment(line: &str) -> Option<(IpAddr, Ban)> {
+    let mut parts = line.strip_prefix(BAN_COMMENT_PREFIX)?.splitn(3, ' ');
+    let ip = parts.next()?.parse().ok()?;
+    let until = UNIX_EPOCH + Duration::from_secs(parts.next()?.parse().ok()?);
+    let reason = parts.next().unwrap_or_default().to_string();
+
+    Some((ip, Ban { until, reason }))
+}
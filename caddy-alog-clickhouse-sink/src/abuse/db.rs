@@ -0,0 +1,51 @@
+use std::time::SystemTime;
+
+use derive_getters::Getters;
+use klickhouse::{DateTime64, Row, Uuid};
+use serde::{Deserialize, Serialize};
+
+use crate::clickhouse::to_datetime64;
+
+#[derive(Serialize, Deserialize, Row, Getters, Clone, Debug)]
+pub struct DbAbuseIncident {
+    id: Uuid,
+    detected_at: DateTime64<3>,
+    service: String,
+    environment: String,
+    ip: String,
+    host: String,
+    reason: String,
+    /// Number of matching requests in the detection window
+    hits: u32,
+    /// URI of the request that triggered the incident
+    sample_uri: String,
+    banned_until: DateTime64<3>,
+}
+
+impl DbAbuseIncident {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        service: &str,
+        environment: &str,
+        ip: String,
+        host: String,
+        reason: &str,
+        hits: u32,
+        sample_uri: String,
+        detected_at: SystemTime,
+        banned_until: SystemTime,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::now_v7(),
+            detected_at: to_datetime64(detected_at),
+            service: service.to_string(),
+            environment: environment.to_string(),
+            ip,
+            host,
+            reason: reason.to_string(),
+            hits,
+            sample_uri,
+            banned_until: to_datetime64(banned_until),
+        }
+    }
+}
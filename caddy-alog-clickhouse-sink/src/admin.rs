@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+
+use crate::{
+    app_state::AppState,
+    control::{ConnectionInfo, PauseMode, Peer},
+    telemetry,
+};
+
+/// Flushes the server-side buffers of the asynchronous inserts
+const FLUSH_QUERY: &str = "SYSTEM FLUSH ASYNC INSERT QUEUE";
+
+/// Admin interface of the running sink, for local use only, as there's no authentication:
+///
+/// - `GET /status`: whether ingestion is paused and the number of open connections
+/// - `POST /pause?mode=buffer|reject` and `POST /resume`
+/// - `POST /flush`: flushes Clickhouse's asynchronous insert queues
+/// - `GET /connections` and `POST /disconnect?peer=<ip>[:<port>]`
+/// - `GET /log-filter` and `PUT /log-filter` with `RUST_LOG` directives as the body
+pub async fn serve(app_state: Arc<AppState>, bind_to: &str) -> Result<()> {
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/flush", post(flush))
+        .route("/connections", get(connections))
+        .route("/disconnect", post(disconnect))
+        .route("/log-filter", get(log_filter).put(set_log_filter))
+        .with_state(app_state);
+
+    let listener = TcpListener::bind(bind_to)
+        .await
+        .wrap_err_with(|| format!("Failed to bind admin server to address {}", bind_to))?;
+    if !listener.local_addr()?.ip().is_loopback() {
+        warn!("Admin server isn't bound to a loopback address, it has no authentication");
+    }
+    info!("Admin server listening on {}", bind_to);
+
+    axum::serve(listener, app)
+        .await
+        .wrap_err("Admin server failed")
+}
+
+#[derive(Serialize, Debug)]
+struct Status {
+    paused: Option<PauseMode>,
+    connections: usize,
+}
+
+async fn status(State(app_state): State<Arc<AppState>>) -> Json<Status> {
+    let control = app_state.control();
+
+    Json(Status {
+        paused: control.paused(),
+        connections: control.connections().len(),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct PauseQuery {
+    #[serde(default)]
+    mode: PauseMode,
+}
+
+#[tracing::instrument(skip(app_state))]
+async fn pause(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<PauseQuery>,
+) -> Json<Status> {
+    app_state.control().pause(query.mode);
+
+    status(State(app_state)).await
+}
+
+#[tracing::instrument(skip(app_state))]
+async fn resume(State(app_state): State<Arc<AppState>>) -> Json<Status> {
+    app_state.control().resume();
+
+    status(State(app_state)).await
+}
+
+/// Result of the flush on every host, `ok` or the error
+#[tracing::instrument(skip(app_state))]
+async fn flush(State(app_state): State<Arc<AppState>>) -> Response {
+    let results = app_state.clickhouse().execute_on_all(FLUSH_QUERY).await;
+    let failed = results.iter().any(|(_, result)| result.is_err());
+    let results = results
+        .into_iter()
+        .map(|(host, result)| {
+            let result = match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => {
+                    error!(ch_host = host, "Failed to flush: {}", e);
+                    e.to_string()
+                }
+            };
+
+            (host.to_string(), result)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let status = if failed {
+        StatusCode::BAD_GATEWAY
+    } else {
+        StatusCode::OK
+    };
+
+    (status, Json(results)).into_response()
+}
+
+async fn connections(State(app_state): State<Arc<AppState>>) -> Json<Vec<ConnectionInfo>> {
+    Json(app_state.control().connections())
+}
+
+#[derive(Deserialize, Debug)]
+struct DisconnectQuery {
+    peer: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Disconnected {
+    disconnected: usize,
+}
+
+#[tracing::instrument(skip(app_state))]
+async fn disconnect(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<DisconnectQuery>,
+) -> Response {
+    let Ok(peer) = query.peer.parse::<Peer>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid peer address").into_response();
+    };
+
+    Json(Disconnected {
+        disconnected: app_state.control().disconnect(peer),
+    })
+    .into_response()
+}
+
+async fn log_filter() -> Response {
+    match telemetry::log_filter() {
+        Ok(filter) => filter.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")).into_response(),
+    }
+}
+
+#[tracing::instrument]
+async fn set_log_filter(directives: String) -> Response {
+    match telemetry::set_log_filter(directives.trim()) {
+        Ok(()) => {
+            info!("Log filter changed");
+            log_filter().await
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response(),
+    }
+}
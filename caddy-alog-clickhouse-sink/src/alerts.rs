@@ -0,0 +1,362 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwap;
+use eyre::{Result, WrapErr};
+use tracing::{error, info, warn};
+
+use crate::{config::Config, log::db::DbAccessLogEntry};
+
+mod stats;
+mod webhook;
+
+use self::{
+    stats::{HostStats, BUCKET_SECS},
+    webhook::{Notification, Webhook, DEFAULT_TEMPLATE},
+};
+
+/// Thresholds of the alerting rules, evaluated per host over a sliding window
+#[derive(Debug)]
+pub struct AlertRules {
+    window: Duration,
+    /// Minimal number of requests in the window to evaluate ratio and latency rules
+    min_requests: u64,
+    /// 5xx responses share, disabled if not positive
+    error_ratio: f64,
+    /// 95th percentile of the request duration in seconds, disabled if not positive
+    p95_duration: f64,
+    /// Whether to alert when a host with regular traffic stops receiving requests
+    no_traffic: bool,
+    /// Minimal time between two notifications about the same rule firing
+    cooldown: Duration,
+    /// Hosts to evaluate, all if empty
+    hosts: Vec<String>,
+}
+
+impl AlertRules {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            window: config.alert_window(),
+            min_requests: *config.alert_min_requests(),
+            error_ratio: *config.alert_error_ratio(),
+            p95_duration: *config.alert_p95_duration_secs(),
+            no_traffic: *config.alert_no_traffic(),
+            cooldown: config.alert_cooldown(),
+            hosts: config.alert_hosts().clone(),
+        }
+    }
+
+    fn window_buckets(&self) -> u64 {
+        self.window.as_secs().div_ceil(BUCKET_SECS).max(1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Rule {
+    ErrorRatio,
+    P95Duration,
+    NoTraffic,
+}
+
+impl Rule {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ErrorRatio => "error_ratio",
+            Self::P95Duration => "p95_duration",
+            Self::NoTraffic => "no_traffic",
+        }
+    }
+
+    fn describe(self, host: &str, value: f64, threshold: f64) -> String {
+        match self {
+            Self::ErrorRatio => format!(
+                "5xx ratio on {host} is {:.1}% (threshold {:.1}%)",
+                value * 100.0,
+                threshold * 100.0
+            ),
+            Self::P95Duration => {
+                format!("p95 request duration on {host} is {value:.3}s (threshold {threshold:.3}s)")
+            }
+            Self::NoTraffic => format!("{host} receives no requests"),
+        }
+    }
+}
+
+/// Result of a single rule evaluation
+struct Check {
+    rule: Rule,
+    firing: bool,
+    value: f64,
+    threshold: f64,
+}
+
+#[derive(Default)]
+struct HostState {
+    stats: HostStats,
+    /// Whether the host ever had enough traffic to be monitored for its absence
+    established: bool,
+}
+
+#[derive(Default)]
+struct RuleState {
+    firing: bool,
+    /// Whether the current firing was notified, so that it needs a resolve notification
+    notified: bool,
+    last_notified: Option<Instant>,
+}
+
+/// States of the rules of every host, kept between the evaluations
+#[derive(Default)]
+pub struct AlertStates(HashMap<(String, Rule), RuleState>);
+
+impl AlertStates {
+    /// Number of the rules firing or cooling down, over all the hosts
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Everything that can be changed at runtime
+struct AlertSettings {
+    service: String,
+    environment: String,
+    rules: AlertRules,
+    webhook: Option<Webhook>,
+}
+
+impl AlertSettings {
+    fn from_config(config: &Config) -> Result<Self> {
+        let webhook = match config.alert_webhook_url() {
+            Some(url) => {
+                let template = match config.alert_template_path() {
+                    Some(path) => std::fs::read_to_string(path).wrap_err_with(|| {
+                        format!("Failed to read alert template {}", path.display())
+                    })?,
+                    None => DEFAULT_TEMPLATE.to_string(),
+                };
+
+                Some(Webhook::new(url.clone(), template)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            service: config.service_name().clone(),
+            environment: config.environment().clone(),
+            rules: AlertRules::from_config(config),
+            webhook,
+        })
+    }
+}
+
+/// Sliding-window alerting on the parsed stream, notifying a webhook
+/// when a rule starts firing and when it's resolved
+pub struct AlertEngine {
+    started: Instant,
+    settings: ArcSwap<AlertSettings>,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl AlertEngine {
+    pub fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            started: Instant::now(),
+            settings: ArcSwap::from_pointee(AlertSettings::from_config(config)?),
+            hosts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Applies the new rules and webhook, collected stats and alert states are kept
+    pub fn reload(&self, config: &Config) -> Result<()> {
+        let settings = AlertSettings::from_config(config)?;
+        if settings.webhook.is_some() {
+            info!(rules = ?settings.rules, "Alerting enabled");
+        }
+        self.settings.store(Arc::new(settings));
+
+        Ok(())
+    }
+
+    fn current_bucket(&self) -> u64 {
+        self.started.elapsed().as_secs() / BUCKET_SECS
+    }
+
+    pub fn observe(&self, entry: &DbAccessLogEntry) {
+        let settings = self.settings.load();
+        if settings.webhook.is_none() {
+            return;
+        }
+        let hosts = &settings.rules.hosts;
+        if !hosts.is_empty() && !hosts.contains(entry.host()) {
+            return;
+        }
+
+        let index = self.current_bucket();
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(entry.host().clone())
+            .or_default()
+            .stats
+            .record(index, *entry.status(), *entry.duration());
+    }
+
+    /// Evaluates the rules for every known host, forgetting the ones without traffic
+    fn evaluate(&self, rules: &AlertRules) -> Vec<(String, Check)> {
+        // the current bucket is still being filled, so it's accounted in addition to the window
+        let first_index = self.current_bucket().saturating_sub(rules.window_buckets());
+        let mut checks = Vec::new();
+
+        self.hosts.lock().unwrap().retain(|host, state| {
+            state.stats.prune(first_index);
+            let stats = state.stats.window(first_index);
+            let enough_requests = stats.requests >= rules.min_requests;
+            state.established |= enough_requests;
+
+            let mut push = |check| checks.push((host.clone(), check));
+            if rules.error_ratio > 0.0 {
+                let value = stats.error_ratio();
+                push(Check {
+                    rule: Rule::ErrorRatio,
+                    firing: enough_requests && value >= rules.error_ratio,
+                    value,
+                    threshold: rules.error_ratio,
+                });
+            }
+            if rules.p95_duration > 0.0 {
+                let value = stats.p95_duration.unwrap_or_default();
+                push(Check {
+                    rule: Rule::P95Duration,
+                    firing: enough_requests && value >= rules.p95_duration,
+                    value,
+                    threshold: rules.p95_duration,
+                });
+            }
+            if rules.no_traffic && state.established {
+                push(Check {
+                    rule: Rule::NoTraffic,
+                    firing: stats.requests == 0,
+                    value: stats.requests as f64,
+                    threshold: 0.0,
+                });
+            }
+
+            // hosts, which never had a regular traffic (e.g. random Host headers
+            // from scanners) are forgotten as soon as they leave the window
+            state.established || !state.stats.is_empty()
+        });
+
+        checks
+    }
+
+    /// Periodically evaluates the rules and sends the notifications
+    pub async fn run(&self) {
+        {
+            let settings = self.settings.load();
+            if settings.webhook.is_some() {
+                info!(rules = ?settings.rules, "Alerting enabled");
+            }
+        }
+
+        let mut states = AlertStates::default();
+        let mut interval = tokio::time::interval(Duration::from_secs(BUCKET_SECS));
+
+        loop {
+            interval.tick().await;
+            self.notify(&mut states).await;
+        }
+    }
+
+    /// Evaluates the rules and sends the notifications about the rules, which started firing
+    /// (unless still cooling down) or were resolved since the previous evaluation
+    pub async fn notify(&self, states: &mut AlertStates) {
+        let settings = self.settings.load_full();
+        let Some(webhook) = &settings.webhook else {
+            return;
+        };
+        let cooldown = settings.rules.cooldown;
+        let checks = self.evaluate(&settings.rules);
+        let now = Instant::now();
+        let mut notifications = Vec::new();
+
+        for (host, check) in &checks {
+            // only the rules, which fire or were notified, have a state
+            let state = match states.0.entry((host.clone(), check.rule)) {
+                Entry::Occupied(state) => state.into_mut(),
+                Entry::Vacant(state) if check.firing => state.insert(RuleState::default()),
+                Entry::Vacant(_) => continue,
+            };
+
+            let status = match (state.firing, check.firing) {
+                (false, true) => {
+                    let cooled_down = state
+                        .last_notified
+                        .is_none_or(|at| now.duration_since(at) >= cooldown);
+                    if !cooled_down {
+                        // not firing yet, so that it's notified once the cooldown is over
+                        warn!(%host, rule = check.rule.as_str(), "Alert suppressed by cooldown");
+                        continue;
+                    }
+
+                    state.firing = true;
+                    state.notified = true;
+                    state.last_notified = Some(now);
+                    "firing"
+                }
+                (true, false) => {
+                    state.firing = false;
+                    if !std::mem::take(&mut state.notified) {
+                        continue;
+                    }
+
+                    "resolved"
+                }
+                _ => continue,
+            };
+
+            let mut message = check.rule.describe(host, check.value, check.threshold);
+            if status == "resolved" {
+                message = format!("Resolved: {message}");
+            }
+            warn!(%host, rule = check.rule.as_str(), status, "{}", message);
+
+            notifications.push(Notification {
+                status,
+                rule: check.rule.as_str(),
+                host,
+                service: &settings.service,
+                environment: &settings.environment,
+                value: check.value,
+                threshold: check.threshold,
+                message,
+            });
+        }
+
+        // the states of the forgotten hosts and of the resolved rules go once they cooled down
+        let evaluated = checks
+            .iter()
+            .map(|(host, check)| (host, check.rule))
+            .collect::<HashSet<_>>();
+        states.0.retain(|(host, rule), state| {
+            evaluated.contains(&(host, *rule))
+                && (state.firing
+                    || state
+                        .last_notified
+                        .is_some_and(|at| now.duration_since(at) < cooldown))
+        });
+
+        // sent once everything is evaluated, so that a slow webhook doesn't hold up the rules
+        for notification in notifications {
+            if let Err(e) = webhook.send(&notification).await {
+                error!("Failed to notify about alert: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+/// Width of a single stats bucket, in seconds
+pub const BUCKET_SECS: u64 = 10;
+
+/// Number of latency histogram buckets, upper bounds grow by [`LATENCY_GROWTH`],
+/// starting at [`LATENCY_MIN_SECS`] and ending at ~20 minutes
+const LATENCY_BUCKETS: usize = 64;
+const LATENCY_MIN_SECS: f64 = 0.001;
+const LATENCY_GROWTH: f64 = 1.25;
+
+fn latency_bucket(duration: f64) -> usize {
+    if duration <= LATENCY_MIN_SECS {
+        return 0;
+    }
+
+    let index = (duration / LATENCY_MIN_SECS).ln() / LATENCY_GROWTH.ln();
+
+    (index.ceil() as usize).min(LATENCY_BUCKETS - 1)
+}
+
+fn latency_upper_bound(bucket: usize) -> f64 {
+    LATENCY_MIN_SECS * LATENCY_GROWTH.powi(bucket as i32)
+}
+
+/// Requests of a single host, handled within [`BUCKET_SECS`]
+struct Bucket {
+    index: u64,
+    requests: u64,
+    server_errors: u64,
+    latency: [u32; LATENCY_BUCKETS],
+}
+
+impl Bucket {
+    fn new(index: u64) -> Self {
+        Self {
+            index,
+            requests: 0,
+            server_errors: 0,
+            latency: [0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+/// Aggregated requests of a single host over a window
+#[derive(Debug)]
+pub struct WindowStats {
+    pub requests: u64,
+    pub server_errors: u64,
+    /// 95th percentile of the request duration, in seconds (approximated by the histogram bucket)
+    pub p95_duration: Option<f64>,
+}
+
+impl WindowStats {
+    pub fn error_ratio(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+
+        self.server_errors as f64 / self.requests as f64
+    }
+}
+
+/// Time-bucketed stats of a single host, bounded by the window size
+#[derive(Default)]
+pub struct HostStats {
+    buckets: VecDeque<Bucket>,
+}
+
+impl HostStats {
+    /// Accounts a request, handled in the bucket with the given index
+    pub fn record(&mut self, index: u64, status: u16, duration: f64) {
+        if self
+            .buckets
+            .back()
+            .is_none_or(|bucket| bucket.index < index)
+        {
+            self.buckets.push_back(Bucket::new(index));
+        }
+        let bucket = self
+            .buckets
+            .back_mut()
+            .expect("bucket exists, if not added above");
+
+        bucket.requests += 1;
+        if status >= 500 {
+            bucket.server_errors += 1;
+        }
+        bucket.latency[latency_bucket(duration)] += 1;
+    }
+
+    /// Forgets buckets older than `first_index`
+    pub fn prune(&mut self, first_index: u64) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.index < first_index)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Stats over buckets starting with `first_index`
+    pub fn window(&self, first_index: u64) -> WindowStats {
+        let mut requests = 0;
+        let mut server_errors = 0;
+        let mut latency = [0u64; LATENCY_BUCKETS];
+
+        for bucket in self.buckets.iter().filter(|b| b.index >= first_index) {
+            requests += bucket.requests;
+            server_errors += bucket.server_errors;
+            for (total, count) in latency.iter_mut().zip(bucket.latency) {
+                *total += u64::from(count);
+            }
+        }
+
+        let p95_rank = (requests as f64 * 0.95).ceil() as u64;
+        let mut seen = 0;
+        let p95_duration = latency.iter().enumerate().find_map(|(bucket, count)| {
+            seen += count;
+            (requests > 0 && seen >= p95_rank).then(|| latency_upper_bound(bucket))
+        });
+
+        WindowStats {
+            requests,
+            server_errors,
+            p95_duration,
+        }
+    }
+}
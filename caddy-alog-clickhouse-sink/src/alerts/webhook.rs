@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use eyre::{Result, WrapErr};
+
+/// Payload used if no template is configured.
+///
+/// Templates are JSON documents with `{{placeholder}}`s, string placeholders are JSON-escaped
+/// (but not quoted), `value` and `threshold` are plain numbers.
+pub const DEFAULT_TEMPLATE: &str = r#"{
+  "status": "{{status}}",
+  "rule": "{{rule}}",
+  "host": "{{host}}",
+  "service": "{{service}}",
+  "environment": "{{environment}}",
+  "value": {{value}},
+  "threshold": {{threshold}},
+  "message": "{{message}}"
+}"#;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Single alert state change
+#[derive(Debug)]
+pub struct Notification<'a> {
+    /// `firing` or `resolved`
+    pub status: &'static str,
+    pub rule: &'static str,
+    pub host: &'a str,
+    pub service: &'a str,
+    pub environment: &'a str,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+}
+
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    template: String,
+}
+
+impl Webhook {
+    /// Creates the webhook, checking that the template renders into a valid JSON
+    pub fn new(url: String, template: String) -> Result<Self> {
+        let webhook = Self {
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .wrap_err("Failed to create HTTP client")?,
+            url,
+            template,
+        };
+
+        let sample = webhook.render(&Notification {
+            status: "firing",
+            rule: "sample",
+            host: "example.com",
+            service: "service",
+            environment: "environment",
+            value: 1.0,
+            threshold: 0.5,
+            message: "\"quoted\" sample".to_string(),
+        });
+        serde_json::from_str::<serde_json::Value>(&sample)
+            .wrap_err("Alert template doesn't render into a valid JSON")?;
+
+        Ok(webhook)
+    }
+
+    fn render(&self, notification: &Notification) -> String {
+        let escape = |value: &str| {
+            let quoted = serde_json::Value::from(value).to_string();
+
+            quoted[1..quoted.len() - 1].to_string()
+        };
+
+        let value = |name: &str| match name {
+            "status" => Some(escape(notification.status)),
+            "rule" => Some(escape(notification.rule)),
+            "host" => Some(escape(notification.host)),
+            "service" => Some(escape(notification.service)),
+            "environment" => Some(escape(notification.environment)),
+            "value" => Some(notification.value.to_string()),
+            "threshold" => Some(notification.threshold.to_string()),
+            "message" => Some(escape(&notification.message)),
+            _ => None,
+        };
+
+        // a single pass, so that placeholders in the values aren't expanded
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let placeholder = rest
+                .find("}}")
+                .and_then(|end| Some((end, value(&rest[2..end])?)));
+            match placeholder {
+                Some((end, value)) => {
+                    rendered.push_str(&value);
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+
+        rendered
+    }
+
+    pub async fn send(&self, notification: &Notification<'_>) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(self.render(notification))
+            .send()
+            .await
+            .wrap_err("Failed to send webhook")?
+            .error_for_status()
+            .wrap_err("Webhook returned an error")?;
+
+        Ok(())
+    }
+}
@@ -1,51 +1,128 @@
-use bb8::Pool;
-use eyre::{Result, WrapErr};
-use klickhouse::{ClientOptions, ConnectionManager};
-use secrecy::ExposeSecret;
+use std::sync::Arc;
 
-use crate::config::Config;
+use arc_swap::ArcSwap;
+use eyre::{ensure, Result, WrapErr};
+use tracing::warn;
+
+use crate::{
+    abuse::AbuseDetector, alerts::AlertEngine, clickhouse::ChCluster, config::Config,
+    control::Control, lag::LagMonitor, rate_limit::RateLimiter, schema, schema::SchemaCheck,
+    tail::TailHub, visitors::VisitorKeys,
+};
 
-#[derive(Clone)]
 pub struct AppState {
-    ch_pool: Pool<ConnectionManager>,
-    config: Config,
+    abuse: AbuseDetector,
+    alerts: AlertEngine,
+    clickhouse: ChCluster,
+    config: ArcSwap<Config>,
+    control: Control,
+    lag: LagMonitor,
+    rate_limiter: RateLimiter,
+    tail: TailHub,
+    visitors: VisitorKeys,
 }
 
 impl AppState {
     pub async fn new(config: Config) -> Result<Self> {
-        let options = ClientOptions {
-            username: config.ch_user().to_string(),
-            password: config.ch_password().expose_secret().to_string(),
-            default_database: config.ch_database().to_string(),
-        };
-        let manager = ConnectionManager::new(config.ch_host(), options)
-            .await
-            .wrap_err_with(|| {
-                format!(
-                    "Failed to create connection manager for config: {:?}",
-                    config
-                )
-            })?;
-
-        let pool = Pool::builder()
-            .max_size(20)
-            .build(manager)
-            .await
-            .wrap_err_with(|| {
-                format!("Failed to create connection pool for config: {:?}", config)
-            })?;
+        let clickhouse = ChCluster::new(&config).await.wrap_err_with(|| {
+            format!(
+                "Failed to create Clickhouse client for config: {:?}",
+                config
+            )
+        })?;
+
+        let tail = TailHub::new(*config.tail_buffer_size());
+        let abuse = AbuseDetector::new(&config).wrap_err("Failed to set up abuse detection")?;
+        let alerts = AlertEngine::new(&config).wrap_err("Failed to set up alerting")?;
+        let lag = LagMonitor::new(&config);
+        let rate_limiter = RateLimiter::new(&config);
 
         Ok(Self {
-            ch_pool: pool,
-            config,
+            abuse,
+            alerts,
+            clickhouse,
+            config: ArcSwap::from_pointee(config),
+            control: Control::default(),
+            lag,
+            rate_limiter,
+            tail,
+            visitors: VisitorKeys::default(),
         })
     }
 
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// Applies the new configuration to the running components.
+    ///
+    /// Connection-related settings are only applied on restart, changing them is reported.
+    /// New promoted headers are refused, unless the tables have their columns.
+    pub async fn reload(&self, config: Config) -> Result<()> {
+        let current = self.config();
+        if current.columns_changed(&config) && *config.schema_check() != SchemaCheck::Off {
+            let mismatches = schema::check_tables(&self.clickhouse, &config)
+                .await
+                .wrap_err("Failed to check the tables for the new promoted headers")?;
+            ensure!(
+                mismatches.is_empty(),
+                "Tables don't have the columns of the new promoted headers: {}",
+                mismatches
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let restart_required = current.restart_required(&config);
+        if !restart_required.is_empty() {
+            warn!(
+                settings = ?restart_required,
+                "Changed settings will only be applied on restart"
+            );
+        }
+
+        self.alerts.reload(&config)?;
+        self.abuse.reload(&config);
+        self.lag.reload(&config);
+        self.rate_limiter.reload(&config);
+        self.tail.set_buffer_size(*config.tail_buffer_size());
+        self.config.store(Arc::new(config));
+
+        Ok(())
+    }
+
+    /// Current configuration, may change on reload
+    pub fn config(&self) -> Config {
+        Config::clone(&self.config.load())
+    }
+
+    pub fn clickhouse(&self) -> &ChCluster {
+        &self.clickhouse
+    }
+
+    pub fn control(&self) -> &Control {
+        &self.control
+    }
+
+    pub fn tail(&self) -> &TailHub {
+        &self.tail
+    }
+
+    pub fn abuse(&self) -> &AbuseDetector {
+        &self.abuse
+    }
+
+    pub fn alerts(&self) -> &AlertEngine {
+        &self.alerts
+    }
+
+    pub fn lag(&self) -> &LagMonitor {
+        &self.lag
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
     }
 
-    pub fn ch_pool(&self) -> &Pool<ConnectionManager> {
-        &self.ch_pool
+    pub fn visitors(&self) -> &VisitorKeys {
+        &self.visitors
     }
 }
@@ -0,0 +1,348 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bb8::{ManageConnection, Pool, PooledConnection, RunError};
+use displaydoc::Display;
+use eyre::{bail, Result};
+use klickhouse::{Client, ClientOptions, ConnectionManager, DateTime64, KlickhouseError, Row, Tz};
+use secrecy::ExposeSecret;
+use thiserror::Error;
+use tracing::{debug, error, warn};
+
+use crate::config::Config;
+
+/// Clickhouse server error codes, after which the same insert may succeed if retried
+const TRANSIENT_SERVER_ERRORS: &[i32] = &[
+    3,   // UNEXPECTED_END_OF_FILE
+    159, // TIMEOUT_EXCEEDED
+    202, // TOO_MANY_SIMULTANEOUS_QUERIES
+    209, // SOCKET_TIMEOUT
+    210, // NETWORK_ERROR
+    241, // MEMORY_LIMIT_EXCEEDED
+    242, // TABLE_IS_READ_ONLY
+    252, // TOO_MANY_PARTS
+    319, // UNKNOWN_STATUS_OF_INSERT
+    425, // SYSTEM_ERROR
+    999, // KEEPER_EXCEPTION
+];
+
+#[derive(Debug, Display, Error)]
+pub enum ChError {
+    /// Timed out waiting for a connection to {0}
+    ConnectTimeout(String),
+    /// Insert into {0} timed out
+    InsertTimeout(String),
+    /// Query on {0} timed out
+    QueryTimeout(String),
+    /// Clickhouse error on {0}: {1}
+    Clickhouse(String, #[source] KlickhouseError),
+}
+
+impl ChError {
+    /// Whether the failure is caused by the host itself, so that it should be avoided for a while
+    fn is_connection_failure(&self) -> bool {
+        match self {
+            Self::ConnectTimeout(_) | Self::InsertTimeout(_) | Self::QueryTimeout(_) => true,
+            Self::Clickhouse(_, e) => matches!(
+                e,
+                KlickhouseError::Io(_) | KlickhouseError::ProtocolError(_)
+            ),
+        }
+    }
+
+    /// Whether retrying the same request may succeed
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Clickhouse(_, KlickhouseError::ServerException { code, .. }) => {
+                TRANSIENT_SERVER_ERRORS.contains(code)
+            }
+            // the server may have taken the rows already, they'd be duplicated by a retry
+            Self::InsertTimeout(_) => false,
+            _ => self.is_connection_failure(),
+        }
+    }
+}
+
+/// Pooled client, which is discarded instead of reused once a request on it was interrupted
+struct PooledClient {
+    client: Client,
+    interrupted: bool,
+}
+
+/// Manager of the pooled clients, wrapping the one of `klickhouse`
+struct Manager(ConnectionManager);
+
+#[async_trait]
+impl ManageConnection for Manager {
+    type Connection = PooledClient;
+    type Error = KlickhouseError;
+
+    async fn connect(&self) -> Result<PooledClient, KlickhouseError> {
+        Ok(PooledClient {
+            client: self.0.connect().await?,
+            interrupted: false,
+        })
+    }
+
+    async fn is_valid(&self, conn: &mut PooledClient) -> Result<(), KlickhouseError> {
+        self.0.is_valid(&mut conn.client).await
+    }
+
+    fn has_broken(&self, conn: &mut PooledClient) -> bool {
+        // the protocol state of an interrupted request is unknown
+        conn.interrupted || self.0.has_broken(&mut conn.client)
+    }
+}
+
+/// Connection pool to a single Clickhouse host
+struct Replica {
+    host: String,
+    pool: Pool<Manager>,
+    /// Set after a connection failure, the replica is skipped until this moment
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_healthy(&self, now: Instant) -> bool {
+        match *self.unhealthy_until.lock().unwrap() {
+            Some(until) => until <= now,
+            None => true,
+        }
+    }
+
+    fn mark_healthy(&self) {
+        self.unhealthy_until.lock().unwrap().take();
+    }
+
+    fn mark_unhealthy(&self, cooldown: Duration) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + cooldown);
+    }
+
+    async fn client(&self) -> Result<PooledConnection<'_, Manager>, ChError> {
+        let client = self.pool.get().await.map_err(|e| match e {
+            RunError::User(e) => ChError::Clickhouse(self.host.clone(), e),
+            RunError::TimedOut => ChError::ConnectTimeout(self.host.clone()),
+        })?;
+        debug!(ch_host = %self.host, "Got CH client from pool");
+
+        Ok(client)
+    }
+
+    async fn insert<T: Row + Send + Sync + 'static>(
+        &self,
+        query: &str,
+        rows: Vec<T>,
+        timeout: Duration,
+    ) -> Result<(), ChError> {
+        let mut client = self.client().await?;
+
+        let Ok(result) =
+            tokio::time::timeout(timeout, client.client.insert_native_block(query, rows)).await
+        else {
+            client.interrupted = true;
+            return Err(ChError::InsertTimeout(self.host.clone()));
+        };
+
+        result.map_err(|e| ChError::Clickhouse(self.host.clone(), e))
+    }
+
+    async fn execute(&self, query: &str, timeout: Duration) -> Result<(), ChError> {
+        let mut client = self.client().await?;
+
+        let Ok(result) = tokio::time::timeout(timeout, client.client.execute(query)).await else {
+            client.interrupted = true;
+            return Err(ChError::QueryTimeout(self.host.clone()));
+        };
+
+        result.map_err(|e| ChError::Clickhouse(self.host.clone(), e))
+    }
+
+    async fn query<T: Row>(&self, query: &str, timeout: Duration) -> Result<Vec<T>, ChError> {
+        let mut client = self.client().await?;
+
+        let Ok(result) = tokio::time::timeout(timeout, client.client.query_collect(query)).await
+        else {
+            client.interrupted = true;
+            return Err(ChError::QueryTimeout(self.host.clone()));
+        };
+
+        result.map_err(|e| ChError::Clickhouse(self.host.clone(), e))
+    }
+}
+
+/// Set of Clickhouse hosts with health-based failover and retries of transient errors.
+///
+/// Hosts are tried in the configured order, skipping the ones that failed recently.
+pub struct ChCluster {
+    replicas: Vec<Replica>,
+    insert_timeout: Duration,
+    query_timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    retry_max_backoff: Duration,
+    unhealthy_cooldown: Duration,
+}
+
+impl ChCluster {
+    pub async fn new(config: &Config) -> Result<Self> {
+        let options = ClientOptions {
+            username: config.ch_user().to_string(),
+            password: config.ch_password().expose_secret().to_string(),
+            default_database: config.ch_database().to_string(),
+        };
+
+        let mut replicas = Vec::with_capacity(config.ch_hosts().len());
+        for host in config.ch_hosts() {
+            // resolving is the only thing done eagerly here, so one replica with a broken DNS
+            // record shouldn't prevent the others from being used
+            let manager = match ConnectionManager::new(host.as_str(), options.clone()).await {
+                Ok(manager) => manager,
+                Err(e) => {
+                    error!(ch_host = %host, "Failed to create connection manager: {}", e);
+                    continue;
+                }
+            };
+
+            let pool = Pool::builder()
+                .max_size(*config.ch_pool_size())
+                .connection_timeout(config.ch_connect_timeout())
+                .build_unchecked(Manager(manager));
+
+            replicas.push(Replica {
+                host: host.clone(),
+                pool,
+                unhealthy_until: Mutex::new(None),
+            });
+        }
+
+        if replicas.is_empty() {
+            bail!(
+                "Failed to create connection manager for any of the Clickhouse hosts: {:?}",
+                config.ch_hosts()
+            );
+        }
+
+        Ok(Self {
+            replicas,
+            insert_timeout: config.ch_insert_timeout(),
+            query_timeout: config.ch_query_timeout(),
+            max_retries: *config.ch_max_retries(),
+            retry_backoff: config.ch_retry_backoff(),
+            retry_max_backoff: config.ch_retry_max_backoff(),
+            unhealthy_cooldown: config.ch_unhealthy_cooldown(),
+        })
+    }
+
+    /// Picks the first healthy replica, or the one that will become healthy the soonest
+    fn pick_replica(&self) -> &Replica {
+        let now = Instant::now();
+
+        self.replicas
+            .iter()
+            .find(|replica| replica.is_healthy(now))
+            .unwrap_or_else(|| {
+                self.replicas
+                    .iter()
+                    .min_by_key(|replica| *replica.unhealthy_until.lock().unwrap())
+                    .expect("cluster has at least one replica")
+            })
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.retry_max_backoff)
+    }
+
+    /// Inserts rows, retrying transient failures with exponential backoff
+    /// and failing over to other hosts on connection errors. Timed out inserts aren't retried.
+    pub async fn insert<T: Row + Clone + Send + Sync + 'static>(
+        &self,
+        query: &str,
+        rows: Vec<T>,
+    ) -> Result<(), ChError> {
+        let mut attempt = 0;
+
+        loop {
+            let replica = self.pick_replica();
+
+            let e = match replica
+                .insert(query, rows.clone(), self.insert_timeout)
+                .await
+            {
+                Ok(()) => {
+                    replica.mark_healthy();
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+
+            if e.is_connection_failure() {
+                replica.mark_unhealthy(self.unhealthy_cooldown);
+            }
+            if !e.is_transient() || attempt >= self.max_retries {
+                return Err(e);
+            }
+
+            let backoff = self.backoff(attempt);
+            attempt += 1;
+            warn!(
+                attempt,
+                backoff_ms = backoff.as_millis() as u64,
+                "Insert failed, retrying: {}",
+                e
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// Runs a select query, failing over to other hosts on connection errors (but not retrying)
+    pub async fn query<T: Row>(&self, query: &str) -> Result<Vec<T>, ChError> {
+        let mut attempt = 0;
+
+        loop {
+            let replica = self.pick_replica();
+
+            let e = match replica.query(query, self.query_timeout).await {
+                Ok(rows) => {
+                    replica.mark_healthy();
+                    return Ok(rows);
+                }
+                Err(e) => e,
+            };
+
+            attempt += 1;
+            if !e.is_connection_failure() || attempt >= self.replicas.len() {
+                return Err(e);
+            }
+
+            replica.mark_unhealthy(self.unhealthy_cooldown);
+            warn!("Query failed, trying another host: {}", e);
+        }
+    }
+
+    /// Runs a statement on every host (e.g. to flush their buffers), healthy or not,
+    /// returns the result of each host
+    pub async fn execute_on_all(&self, query: &str) -> Vec<(&str, Result<(), ChError>)> {
+        futures::future::join_all(self.replicas.iter().map(|replica| async move {
+            (
+                replica.host.as_str(),
+                replica.execute(query, self.query_timeout).await,
+            )
+        }))
+        .await
+    }
+}
+
+/// `DateTime64(3, 'UTC')` value of the time
+pub fn to_datetime64(time: SystemTime) -> DateTime64<3> {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    DateTime64(Tz::UTC, millis)
+}
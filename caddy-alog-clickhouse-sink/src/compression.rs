@@ -0,0 +1,69 @@
+use std::io;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, BufReader},
+    net::TcpStream,
+};
+
+/// First byte of a gzip member (`1f 8b`)
+const GZIP_MAGIC: u8 = 0x1f;
+/// First byte of a zstd frame (`28 b5 2f fd`)
+const ZSTD_MAGIC: u8 = 0x28;
+
+/// Compression of the incoming TCP streams
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum InputCompression {
+    /// Detected by the first byte of the stream, which can't start a JSON line
+    #[default]
+    Auto,
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Stream of the decompressed bytes of the socket.
+///
+/// Concatenated gzip members or zstd frames are all decompressed,
+/// so a sender may compress every batch of lines separately.
+pub async fn decompress(
+    socket: TcpStream,
+    compression: InputCompression,
+) -> io::Result<(Box<dyn AsyncRead + Send + Unpin>, InputCompression)> {
+    let compression = match compression {
+        InputCompression::Auto => detect(&socket).await?,
+        compression => compression,
+    };
+
+    let reader: Box<dyn AsyncRead + Send + Unpin> = match compression {
+        InputCompression::Auto | InputCompression::None => Box::new(socket),
+        InputCompression::Gzip => {
+            let mut decoder = GzipDecoder::new(BufReader::new(socket));
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        InputCompression::Zstd => {
+            let mut decoder = ZstdDecoder::new(BufReader::new(socket));
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+    };
+
+    Ok((reader, compression))
+}
+
+/// Waits for the first byte, without consuming it
+async fn detect(socket: &TcpStream) -> io::Result<InputCompression> {
+    let mut first = [0; 1];
+    if socket.peek(&mut first).await? == 0 {
+        return Ok(InputCompression::None);
+    }
+
+    Ok(match first[0] {
+        GZIP_MAGIC => InputCompression::Gzip,
+        ZSTD_MAGIC => InputCompression::Zstd,
+        _ => InputCompression::None,
+    })
+}
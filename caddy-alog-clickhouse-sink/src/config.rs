@@ -1,10 +1,169 @@
-use std::{ops::Deref, sync::Arc};
+use std::{ops::Deref, path::PathBuf, sync::Arc, time::Duration};
 
 use derive_getters::Getters;
-use eyre::{Result, WrapErr};
-use secrecy::SecretString;
+use eyre::{ensure, Result, WrapErr};
+use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 
+use crate::{
+    compression::InputCompression,
+    log::{duration::DurationFormat, promoted, timestamp::TimeFormat},
+    rate_limit::RateLimitAction,
+    routes::Route,
+    schema::SchemaCheck,
+    telemetry::LogFormat,
+};
+
+fn default_ch_pool_size() -> u32 {
+    20
+}
+
+fn default_ch_connect_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_ch_insert_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_ch_query_timeout_ms() -> u64 {
+    60_000
+}
+
+fn default_ch_max_retries() -> u32 {
+    5
+}
+
+fn default_ch_retry_backoff_ms() -> u64 {
+    100
+}
+
+fn default_ch_retry_max_backoff_ms() -> u64 {
+    10_000
+}
+
+fn default_ch_unhealthy_cooldown_ms() -> u64 {
+    30_000
+}
+
+fn default_max_line_length() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_docker_poll_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_tail_buffer_size() -> usize {
+    1024
+}
+
+/// Host name of the machine (or the container id), if it can be found
+fn default_instance_id() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "caddy-alog-clickhouse-sink".to_string())
+}
+
+fn default_ingest_lag_threshold_secs() -> u64 {
+    60
+}
+
+fn default_visitor_max_lag_secs() -> u64 {
+    3_600
+}
+
+fn default_request_id_headers() -> Vec<String> {
+    vec!["X-Request-Id".to_string()]
+}
+
+fn default_promoted_headers() -> Vec<String> {
+    [
+        "Referer",
+        "User-Agent",
+        "Content-Type",
+        "Accept-Language",
+        "X-Forwarded-For",
+    ]
+    .map(ToString::to_string)
+    .to_vec()
+}
+
+fn default_promoted_response_headers() -> Vec<String> {
+    ["Content-Type", "Cache-Control"]
+        .map(ToString::to_string)
+        .to_vec()
+}
+
+fn default_abuse_window_secs() -> u64 {
+    60
+}
+
+fn default_abuse_ban_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_abuse_probe_paths() -> Vec<String> {
+    [
+        "/wp-login.php",
+        "/wp-admin",
+        "/xmlrpc.php",
+        "/.env",
+        "/.git/",
+        "/.aws/",
+        "/phpmyadmin",
+        "/vendor/phpunit",
+        "/cgi-bin/",
+        "/boaform/",
+    ]
+    .into_iter()
+    .map(ToString::to_string)
+    .collect()
+}
+
+fn default_abuse_probe_threshold() -> usize {
+    2
+}
+
+fn default_abuse_not_found_threshold() -> usize {
+    20
+}
+
+fn default_abuse_unauthorized_threshold() -> usize {
+    10
+}
+
+fn default_blocklist_matcher() -> String {
+    "abusers".to_string()
+}
+
+fn default_alert_window_secs() -> u64 {
+    5 * 60
+}
+
+fn default_alert_min_requests() -> u64 {
+    20
+}
+
+fn default_alert_error_ratio() -> f64 {
+    0.05
+}
+
+fn default_alert_p95_duration_secs() -> f64 {
+    2.0
+}
+
+fn default_alert_no_traffic() -> bool {
+    true
+}
+
+fn default_alert_cooldown_secs() -> u64 {
+    15 * 60
+}
+
 #[derive(Getters, Debug, Clone)]
 pub struct Config {
     inner: Arc<ConfigInner>,
@@ -20,16 +179,218 @@ impl Deref for Config {
 
 #[derive(Deserialize, Getters, Debug)]
 pub struct ConfigInner {
+    /// Path of the TOML configuration file, only read from the environment
+    #[serde(default)]
+    config_path: Option<PathBuf>,
+    /// Format of the service's own logs: `tree`, `compact` or `json`
+    #[serde(default)]
+    log_format: LogFormat,
+    /// OTLP (gRPC) collector endpoint to export spans to, e.g. `http://localhost:4317`
+    otlp_endpoint: Option<String>,
+    /// Identifier of this sink instance, stored with every entry, the host name by default
+    #[serde(default = "default_instance_id")]
+    instance_id: String,
     /// The address to bind to
     bind_to: String,
-    /// Clickhouse server host
-    ch_host: String,
+    /// Compression of the TCP streams: `auto` (default, detected per connection),
+    /// `none`, `gzip` or `zstd`
+    #[serde(default)]
+    input_compression: InputCompression,
+    /// Longest accepted line (after decompression) in bytes, longer ones are skipped
+    #[serde(default = "default_max_line_length")]
+    max_line_length: usize,
+    /// Lines per second accepted from a single peer IP (all its connections together),
+    /// unlimited if not set
+    peer_max_lines_per_sec: Option<u64>,
+    /// Bytes (after decompression) per second accepted from a single peer IP,
+    /// unlimited if not set
+    peer_max_bytes_per_sec: Option<u64>,
+    /// Lines per second accepted from all the peers together, unlimited if not set
+    max_lines_per_sec: Option<u64>,
+    /// Bytes (after decompression) per second accepted from all the peers together,
+    /// unlimited if not set
+    max_bytes_per_sec: Option<u64>,
+    /// What happens when a rate limit is exceeded: `backpressure` (default, reading from
+    /// the connection is paused) or `drop` (the lines are dropped and counted)
+    #[serde(default)]
+    rate_limit_action: RateLimitAction,
+    /// The address to bind the HTTP server (live tail) to, disabled if not set
+    http_bind_to: Option<String>,
+    /// How many entries may be queued for a single live tail subscriber before they're dropped
+    #[serde(default = "default_tail_buffer_size")]
+    tail_buffer_size: usize,
+    /// The address to bind the admin server to, disabled if not set.
+    ///
+    /// It has no authentication, so it should be a loopback address.
+    admin_bind_to: Option<String>,
+    /// The address to bind the syslog listener (both UDP and TCP) to, disabled if not set
+    syslog_bind_to: Option<String>,
+    /// Glob patterns of the Docker json-file logs to tail,
+    /// e.g. `/var/lib/docker/containers/*/*-json.log`, disabled if empty
+    #[serde(default)]
+    docker_log_paths: Vec<String>,
+    /// File keeping how far the Docker logs have been read across restarts,
+    /// required if DOCKER_LOG_PATHS are set
+    docker_offsets_path: Option<PathBuf>,
+    /// How often the Docker logs are checked for new lines, in milliseconds
+    #[serde(default = "default_docker_poll_interval_ms")]
+    #[getter(skip)]
+    docker_poll_interval_ms: u64,
+    /// User of the dashboard (`/dashboard` on the HTTP server), disabled if not set.
+    ///
+    /// If set, `/tail` and `/metrics` require the same credentials.
+    dashboard_user: Option<String>,
+    /// Password of the dashboard user
+    dashboard_password: Option<SecretString>,
+    /// Clickhouse server hosts (comma-separated), in order of preference.
+    ///
+    /// `CH_HOST` is still accepted for single-host setups.
+    #[serde(alias = "ch_host")]
+    ch_hosts: Vec<String>,
     /// Clickhouse user
     ch_user: String,
     /// Clickhouse password
     ch_password: SecretString,
     /// Clickhouse database
     ch_database: String,
+    /// Maximum number of connections in the pool of each Clickhouse host
+    #[serde(default = "default_ch_pool_size")]
+    ch_pool_size: u32,
+    /// Timeout for acquiring a (possibly new) Clickhouse connection, in milliseconds
+    #[serde(default = "default_ch_connect_timeout_ms")]
+    #[getter(skip)]
+    ch_connect_timeout_ms: u64,
+    /// Timeout for a single insert query, in milliseconds
+    #[serde(default = "default_ch_insert_timeout_ms")]
+    #[getter(skip)]
+    ch_insert_timeout_ms: u64,
+    /// Timeout for a single select query (reports), in milliseconds
+    #[serde(default = "default_ch_query_timeout_ms")]
+    #[getter(skip)]
+    ch_query_timeout_ms: u64,
+    /// How many times a failed insert is retried before the entry is dropped
+    #[serde(default = "default_ch_max_retries")]
+    ch_max_retries: u32,
+    /// Delay before the first retry, doubled on every next one, in milliseconds
+    #[serde(default = "default_ch_retry_backoff_ms")]
+    #[getter(skip)]
+    ch_retry_backoff_ms: u64,
+    /// Upper bound for the delay between retries, in milliseconds
+    #[serde(default = "default_ch_retry_max_backoff_ms")]
+    #[getter(skip)]
+    ch_retry_max_backoff_ms: u64,
+    /// For how long a host is skipped after a connection failure, in milliseconds
+    #[serde(default = "default_ch_unhealthy_cooldown_ms")]
+    #[getter(skip)]
+    ch_unhealthy_cooldown_ms: u64,
+    /// `time_format` of Caddy's log encoder: `auto` (default, numbers in any unit),
+    /// `unix_seconds_float`, `unix_milli_float`, `unix_nano` or any of the string formats
+    #[serde(default)]
+    time_format: TimeFormat,
+    /// `duration_format` of Caddy's log encoder: `seconds` (default), `milli` or `nano`,
+    /// string durations are always accepted
+    #[serde(default)]
+    duration_format: DurationFormat,
+    /// Response header with the upstream address, used if it's not appended to the log entry
+    upstream_addr_header: Option<String>,
+    /// Response header with the upstream status, used if it's not appended to the log entry
+    upstream_status_header: Option<String>,
+    /// Response header with the upstream latency (seconds or Go duration),
+    /// used if it's not appended to the log entry
+    upstream_latency_header: Option<String>,
+    /// Name of the `Server-Timing` metric with the upstream latency, used as the last resort
+    upstream_server_timing_metric: Option<String>,
+    /// Headers with a trace id, used if there's no `traceparent` header
+    #[serde(default)]
+    trace_id_headers: Vec<String>,
+    /// Headers with a span id, used if there's no `traceparent` header
+    #[serde(default)]
+    span_id_headers: Vec<String>,
+    /// Headers with a request id, the first one present is used
+    #[serde(default = "default_request_id_headers")]
+    request_id_headers: Vec<String>,
+    /// Request headers stored in their own `header_<name>` columns instead of the `headers` map,
+    /// e.g. `User-Agent` goes to `header_user_agent`
+    #[serde(default = "default_promoted_headers")]
+    promoted_headers: Vec<String>,
+    /// Response headers stored in their own `response_header_<name>` columns
+    /// instead of the `response_headers` map
+    #[serde(default = "default_promoted_response_headers")]
+    promoted_response_headers: Vec<String>,
+    /// Whether to detect scanners and brute-forcers and ban them
+    #[serde(default)]
+    abuse_detection: bool,
+    /// Sliding window for the abuse thresholds, in seconds
+    #[serde(default = "default_abuse_window_secs")]
+    #[getter(skip)]
+    abuse_window_secs: u64,
+    /// For how long an abusive IP is banned, in seconds
+    #[serde(default = "default_abuse_ban_secs")]
+    #[getter(skip)]
+    abuse_ban_secs: u64,
+    /// Path fragments of the well-known probes (e.g. `/wp-login.php`), matched case-insensitively
+    #[serde(default = "default_abuse_probe_paths")]
+    abuse_probe_paths: Vec<String>,
+    /// Requests to probe paths within the window, after which the IP is banned
+    #[serde(default = "default_abuse_probe_threshold")]
+    abuse_probe_threshold: usize,
+    /// 404 responses within the window, after which the IP is banned
+    #[serde(default = "default_abuse_not_found_threshold")]
+    abuse_not_found_threshold: usize,
+    /// 401 responses within the window, after which the IP is banned
+    #[serde(default = "default_abuse_unauthorized_threshold")]
+    abuse_unauthorized_threshold: usize,
+    /// Caddyfile snippet with the banned IPs, not written if not set
+    blocklist_path: Option<PathBuf>,
+    /// Name of the matcher, defined in the blocklist snippet
+    #[serde(default = "default_blocklist_matcher")]
+    blocklist_matcher: String,
+    /// URL to POST alert notifications to, alerting is disabled if not set
+    alert_webhook_url: Option<String>,
+    /// JSON template of the alert notification, see `alerts::webhook` for the placeholders
+    alert_template_path: Option<PathBuf>,
+    /// Sliding window for the alert rules, in seconds
+    #[serde(default = "default_alert_window_secs")]
+    #[getter(skip)]
+    alert_window_secs: u64,
+    /// Minimal number of requests in the window for the ratio and latency rules to fire
+    #[serde(default = "default_alert_min_requests")]
+    alert_min_requests: u64,
+    /// Share of 5xx responses to alert on, disabled if not positive
+    #[serde(default = "default_alert_error_ratio")]
+    alert_error_ratio: f64,
+    /// 95th percentile of request duration to alert on, in seconds, disabled if not positive
+    #[serde(default = "default_alert_p95_duration_secs")]
+    alert_p95_duration_secs: f64,
+    /// Whether to alert when a host with regular traffic stops receiving requests
+    #[serde(default = "default_alert_no_traffic")]
+    alert_no_traffic: bool,
+    /// Minimal time between two notifications about the same rule firing, in seconds
+    #[serde(default = "default_alert_cooldown_secs")]
+    #[getter(skip)]
+    alert_cooldown_secs: u64,
+    /// Hosts to alert on, all if empty
+    #[serde(default)]
+    alert_hosts: Vec<String>,
+    /// Difference between the receive and the log time of an entry (either way),
+    /// after which the peer is reported as lagging or having its clock off
+    #[serde(default = "default_ingest_lag_threshold_secs")]
+    #[getter(skip)]
+    ingest_lag_threshold_secs: u64,
+    /// How long after midnight (UTC) the previous day's visitor salt is kept for late entries,
+    /// entries arriving later get no visitor key
+    #[serde(default = "default_visitor_max_lag_secs")]
+    #[getter(skip)]
+    visitor_max_lag_secs: u64,
+    /// Rules routing the access log entries to other tables, the first matching one is used.
+    ///
+    /// Only read from the configuration file.
+    #[serde(default)]
+    routes: Vec<Route>,
+    /// Whether to compare `access_log` and the route tables with the expected schema on startup:
+    /// `off`, `warn` (default, the differences are logged) or `strict` (the sink doesn't start)
+    #[serde(default)]
+    schema_check: SchemaCheck,
     /// Service name of the application, which logs are being processed
     service_name: String,
     /// Environment of the application, which logs are being processed
@@ -37,12 +398,288 @@ pub struct ConfigInner {
     environment: String,
 }
 
+/// Durations, stored as (milli)seconds in the environment
+impl ConfigInner {
+    pub fn ch_connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.ch_connect_timeout_ms)
+    }
+
+    pub fn ch_insert_timeout(&self) -> Duration {
+        Duration::from_millis(self.ch_insert_timeout_ms)
+    }
+
+    pub fn ch_query_timeout(&self) -> Duration {
+        Duration::from_millis(self.ch_query_timeout_ms)
+    }
+
+    pub fn ch_retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.ch_retry_backoff_ms)
+    }
+
+    pub fn ch_retry_max_backoff(&self) -> Duration {
+        Duration::from_millis(self.ch_retry_max_backoff_ms)
+    }
+
+    pub fn ch_unhealthy_cooldown(&self) -> Duration {
+        Duration::from_millis(self.ch_unhealthy_cooldown_ms)
+    }
+
+    pub fn abuse_window(&self) -> Duration {
+        Duration::from_secs(self.abuse_window_secs)
+    }
+
+    pub fn abuse_ban_duration(&self) -> Duration {
+        Duration::from_secs(self.abuse_ban_secs)
+    }
+
+    pub fn alert_window(&self) -> Duration {
+        Duration::from_secs(self.alert_window_secs)
+    }
+
+    pub fn alert_cooldown(&self) -> Duration {
+        Duration::from_secs(self.alert_cooldown_secs)
+    }
+
+    pub fn ingest_lag_threshold(&self) -> Duration {
+        Duration::from_secs(self.ingest_lag_threshold_secs)
+    }
+
+    pub fn visitor_max_lag(&self) -> Duration {
+        Duration::from_secs(self.visitor_max_lag_secs)
+    }
+
+    pub fn docker_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.docker_poll_interval_ms)
+    }
+}
+
+/// Settings, which are lists: comma-separated, when given in the environment
+const LIST_KEYS: &[&str] = &[
+    "ch_host",
+    "ch_hosts",
+    "trace_id_headers",
+    "span_id_headers",
+    "request_id_headers",
+    "promoted_headers",
+    "promoted_response_headers",
+    "abuse_probe_paths",
+    "alert_hosts",
+    "docker_log_paths",
+];
+
 impl Config {
-    pub fn from_env() -> Result<Self> {
-        envy::from_env::<ConfigInner>()
-            .wrap_err("Failed to load configuration")
-            .map(|inner| Self {
-                inner: Arc::new(inner),
-            })
+    /// Loads the configuration from the TOML file at `CONFIG_PATH` (if set),
+    /// overridden by the environment variables
+    pub fn load() -> Result<Self> {
+        let mut builder = config::Config::builder();
+
+        if let Some(path) = std::env::var_os("CONFIG_PATH") {
+            builder = builder.add_source(
+                config::File::from(PathBuf::from(path)).format(config::FileFormat::Toml),
+            );
+        }
+
+        // values are kept as strings and converted to the field types on deserialization,
+        // so that e.g. a numeric password keeps its leading zeroes
+        for (key, value) in std::env::vars() {
+            let key = key.to_lowercase();
+            if !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                continue;
+            }
+
+            builder = if LIST_KEYS.contains(&key.as_str()) {
+                let values = value
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .collect::<Vec<_>>();
+                builder.set_override(key, values)
+            } else {
+                builder.set_override(key, value)
+            }
+            .wrap_err("Failed to read environment variables")?;
+        }
+
+        let inner = builder
+            .build()
+            .and_then(|config| config.try_deserialize::<ConfigInner>())
+            .wrap_err("Failed to load configuration")?;
+        inner.validate()?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+}
+
+impl ConfigInner {
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.ch_hosts.is_empty(),
+            "At least one Clickhouse host must be configured"
+        );
+        ensure!(
+            self.ch_pool_size > 0,
+            "Clickhouse pool size must be positive"
+        );
+        ensure!(
+            self.max_line_length > 0,
+            "Maximum line length must be positive"
+        );
+        for (name, limit) in [
+            ("peer_max_lines_per_sec", self.peer_max_lines_per_sec),
+            ("peer_max_bytes_per_sec", self.peer_max_bytes_per_sec),
+            ("max_lines_per_sec", self.max_lines_per_sec),
+            ("max_bytes_per_sec", self.max_bytes_per_sec),
+        ] {
+            ensure!(limit != Some(0), "Rate limit {name} must be positive");
+        }
+        ensure!(
+            self.tail_buffer_size > 0,
+            "Live tail buffer size must be positive"
+        );
+        ensure!(
+            self.abuse_window_secs > 0,
+            "Abuse detection window must be positive"
+        );
+        for (name, threshold) in [
+            ("abuse_probe_threshold", self.abuse_probe_threshold),
+            ("abuse_not_found_threshold", self.abuse_not_found_threshold),
+            (
+                "abuse_unauthorized_threshold",
+                self.abuse_unauthorized_threshold,
+            ),
+        ] {
+            // with 0, the first request would already reach it
+            ensure!(threshold > 0, "Abuse detection {name} must be positive");
+        }
+        ensure!(
+            self.blocklist_matcher
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-'),
+            "Blocklist matcher name must be alphanumeric"
+        );
+        ensure!(self.alert_window_secs > 0, "Alert window must be positive");
+        for pattern in &self.docker_log_paths {
+            glob::Pattern::new(pattern)
+                .wrap_err_with(|| format!("Invalid Docker log path pattern {pattern:?}"))?;
+        }
+        ensure!(
+            self.docker_log_paths.is_empty() || self.docker_offsets_path.is_some(),
+            "Docker offsets path must be set to tail Docker logs"
+        );
+        ensure!(
+            self.docker_poll_interval_ms > 0,
+            "Docker poll interval must be positive"
+        );
+        ensure!(
+            self.dashboard_user.is_some() == self.dashboard_password.is_some(),
+            "Dashboard user and password must be set together"
+        );
+        for route in &self.routes {
+            route.validate()?;
+        }
+        for (kind, headers) in [
+            ("request", &self.promoted_headers),
+            ("response", &self.promoted_response_headers),
+        ] {
+            let mut columns = headers
+                .iter()
+                .map(|header| promoted::column_suffix(header))
+                .collect::<Vec<_>>();
+            columns.sort();
+            ensure!(
+                columns.iter().all(|column| !column.is_empty()),
+                "Promoted {kind} header names must not be empty"
+            );
+            ensure!(
+                columns.windows(2).all(|pair| pair[0] != pair[1]),
+                "Promoted {kind} headers must map to distinct columns"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether the columns written to `access_log` and the route tables differ from `other`
+    pub fn columns_changed(&self, other: &Self) -> bool {
+        self.promoted_headers != other.promoted_headers
+            || self.promoted_response_headers != other.promoted_response_headers
+    }
+
+    /// Names of the settings, which differ from `other` and are only applied on restart
+    pub fn restart_required(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, is_changed| {
+            if is_changed {
+                changed.push(name);
+            }
+        };
+
+        check("log_format", self.log_format != other.log_format);
+        check("otlp_endpoint", self.otlp_endpoint != other.otlp_endpoint);
+        check("instance_id", self.instance_id != other.instance_id);
+        check("bind_to", self.bind_to != other.bind_to);
+        check("http_bind_to", self.http_bind_to != other.http_bind_to);
+        check(
+            "syslog_bind_to",
+            self.syslog_bind_to != other.syslog_bind_to,
+        );
+        check("admin_bind_to", self.admin_bind_to != other.admin_bind_to);
+        check("ch_hosts", self.ch_hosts != other.ch_hosts);
+        check("ch_user", self.ch_user != other.ch_user);
+        check(
+            "ch_password",
+            self.ch_password.expose_secret() != other.ch_password.expose_secret(),
+        );
+        check("ch_database", self.ch_database != other.ch_database);
+        check("ch_pool_size", self.ch_pool_size != other.ch_pool_size);
+        check(
+            "ch_connect_timeout_ms",
+            self.ch_connect_timeout_ms != other.ch_connect_timeout_ms,
+        );
+        check(
+            "ch_insert_timeout_ms",
+            self.ch_insert_timeout_ms != other.ch_insert_timeout_ms,
+        );
+        check(
+            "ch_query_timeout_ms",
+            self.ch_query_timeout_ms != other.ch_query_timeout_ms,
+        );
+        check(
+            "ch_max_retries",
+            self.ch_max_retries != other.ch_max_retries,
+        );
+        check(
+            "ch_retry_backoff_ms",
+            self.ch_retry_backoff_ms != other.ch_retry_backoff_ms,
+        );
+        check(
+            "ch_retry_max_backoff_ms",
+            self.ch_retry_max_backoff_ms != other.ch_retry_max_backoff_ms,
+        );
+        check(
+            "ch_unhealthy_cooldown_ms",
+            self.ch_unhealthy_cooldown_ms != other.ch_unhealthy_cooldown_ms,
+        );
+        check(
+            "blocklist_path",
+            self.blocklist_path != other.blocklist_path,
+        );
+        check(
+            "blocklist_matcher",
+            self.blocklist_matcher != other.blocklist_matcher,
+        );
+        check("schema_check", self.schema_check != other.schema_check);
+        // the patterns themselves are reloaded, but the tailing only starts on startup
+        check(
+            "docker_log_paths",
+            self.docker_log_paths.is_empty() != other.docker_log_paths.is_empty(),
+        );
+        check(
+            "docker_offsets_path",
+            self.docker_offsets_path != other.docker_offsets_path,
+        );
+
+        changed
     }
 }
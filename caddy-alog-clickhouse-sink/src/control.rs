@@ -0,0 +1,257 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::compression::InputCompression;
+
+/// What happens to the incoming data while ingestion is paused
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PauseMode {
+    /// Nothing is read, the data is kept in the socket buffers and by the senders
+    #[default]
+    Buffer,
+    /// Everything is read and dropped
+    Reject,
+}
+
+/// Line counts of a connection
+#[derive(Default, Debug)]
+pub struct ConnectionStats {
+    lines: AtomicU64,
+    lossy_lines: AtomicU64,
+    discarded_lines: AtomicU64,
+    discarded_bytes: AtomicU64,
+    rate_limited_lines: AtomicU64,
+    throttled_ms: AtomicU64,
+}
+
+impl ConnectionStats {
+    pub fn line(&self, lossy: bool) {
+        self.lines.fetch_add(1, Ordering::Relaxed);
+        if lossy {
+            self.lossy_lines.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn discarded(&self, bytes: u64) {
+        self.discarded_lines.fetch_add(1, Ordering::Relaxed);
+        self.discarded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Line dropped over a rate limit
+    pub fn rate_limited(&self) {
+        self.rate_limited_lines.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reading paused over a rate limit
+    pub fn throttled(&self, duration: Duration) {
+        self.throttled_ms
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Open log connection
+pub struct Connection {
+    id: u64,
+    peer: SocketAddr,
+    connected_at: SystemTime,
+    compression: InputCompression,
+    stats: ConnectionStats,
+    disconnect: CancellationToken,
+}
+
+/// State of a connection, as listed on the admin interface
+#[derive(Serialize, Debug)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub peer: SocketAddr,
+    /// Unix seconds
+    pub connected_at: u64,
+    pub compression: InputCompression,
+    pub lines: u64,
+    pub lossy_lines: u64,
+    pub discarded_lines: u64,
+    pub discarded_bytes: u64,
+    pub rate_limited_lines: u64,
+    /// Time spent waiting on the rate limits, in milliseconds
+    pub throttled_ms: u64,
+}
+
+impl Connection {
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
+    /// Resolves when the connection is to be closed
+    pub async fn disconnected(&self) {
+        self.disconnect.cancelled().await
+    }
+
+    pub fn info(&self) -> ConnectionInfo {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        ConnectionInfo {
+            id: self.id,
+            peer: self.peer,
+            connected_at: self
+                .connected_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            compression: self.compression,
+            lines: load(&self.stats.lines),
+            lossy_lines: load(&self.stats.lossy_lines),
+            discarded_lines: load(&self.stats.discarded_lines),
+            discarded_bytes: load(&self.stats.discarded_bytes),
+            rate_limited_lines: load(&self.stats.rate_limited_lines),
+            throttled_ms: load(&self.stats.throttled_ms),
+        }
+    }
+}
+
+/// Registration of an open connection, which is removed from the list when dropped
+pub struct ConnectionGuard<'a> {
+    control: &'a Control,
+    connection: Arc<Connection>,
+}
+
+impl Deref for ConnectionGuard<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.connection
+    }
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.control
+            .connections
+            .lock()
+            .unwrap()
+            .remove(&self.connection.id);
+    }
+}
+
+/// Runtime control of the ingestion: pausing it and managing the open connections
+pub struct Control {
+    /// `None` while running
+    paused: watch::Sender<Option<PauseMode>>,
+    connections: Mutex<BTreeMap<u64, Arc<Connection>>>,
+    next_id: AtomicU64,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            paused: watch::Sender::new(None),
+            connections: Mutex::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+impl Control {
+    pub fn pause(&self, mode: PauseMode) {
+        info!(?mode, "Ingestion paused");
+        self.paused.send_replace(Some(mode));
+    }
+
+    pub fn resume(&self) {
+        info!("Ingestion resumed");
+        self.paused.send_replace(None);
+    }
+
+    pub fn paused(&self) -> Option<PauseMode> {
+        *self.paused.borrow()
+    }
+
+    /// Waits while ingestion is paused in the buffer mode, returns the state after that
+    pub async fn wait_while_buffering(&self) -> Option<PauseMode> {
+        let mut paused = self.paused.subscribe();
+        let state = paused
+            .wait_for(|paused| *paused != Some(PauseMode::Buffer))
+            .await
+            .map(|paused| *paused);
+
+        // the sender lives as long as `self`
+        state.unwrap_or(None)
+    }
+
+    pub fn register(&self, peer: SocketAddr, compression: InputCompression) -> ConnectionGuard<'_> {
+        let connection = Arc::new(Connection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            peer,
+            connected_at: SystemTime::now(),
+            compression,
+            stats: ConnectionStats::default(),
+            disconnect: CancellationToken::new(),
+        });
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(connection.id, Arc::clone(&connection));
+
+        ConnectionGuard {
+            control: self,
+            connection,
+        }
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|connection| connection.info())
+            .collect()
+    }
+
+    /// Closes the connections from the address (or from any port of the IP),
+    /// returns how many there were
+    pub fn disconnect(&self, peer: Peer) -> usize {
+        let connections = self.connections.lock().unwrap();
+        let matching = connections
+            .values()
+            .filter(|connection| match peer {
+                Peer::Address(address) => connection.peer == address,
+                Peer::Ip(ip) => connection.peer.ip() == ip,
+            })
+            .inspect(|connection| connection.disconnect.cancel())
+            .count();
+        info!(?peer, connections = matching, "Disconnected peer");
+
+        matching
+    }
+}
+
+/// Peer to disconnect: a single connection or every connection from the IP
+#[derive(Clone, Copy, Debug)]
+pub enum Peer {
+    Address(SocketAddr),
+    Ip(IpAddr),
+}
+
+impl std::str::FromStr for Peer {
+    type Err = std::net::AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<SocketAddr>() {
+            Ok(address) => Ok(Self::Address(address)),
+            Err(_) => s.parse::<IpAddr>().map(Self::Ip),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use eyre::{eyre, Result};
+use maud::{html, Markup, DOCTYPE};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{app_state::AppState, http::auth, log::duration::parse_go_duration, routes};
+
+mod chart;
+mod queries;
+
+use self::{
+    chart::{Kind, Series},
+    queries::{Range, TopPath, TrafficBucket},
+};
+
+/// Ranges offered on the page, any Go duration up to [`MAX_RANGE_SECS`] is accepted
+const RANGES: [&str; 5] = ["15m", "1h", "6h", "24h", "168h"];
+const MAX_RANGE_SECS: u32 = 31 * 86_400;
+/// Bucket sizes, the smallest one giving at most [`MAX_BUCKETS`] buckets is used
+const BUCKET_SECS: [u32; 12] = [
+    10, 30, 60, 120, 300, 600, 900, 1_800, 3_600, 7_200, 21_600, 86_400,
+];
+const MAX_BUCKETS: u32 = 120;
+const TOP_PATHS: u32 = 20;
+
+/// Read-only dashboard, behind basic auth with DASHBOARD_USER and DASHBOARD_PASSWORD
+pub fn router(app_state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(dashboard_page))
+        .route_layer(middleware::from_fn_with_state(app_state, auth::basic_auth))
+}
+
+#[derive(Deserialize, Debug)]
+struct DashboardQuery {
+    /// Service to show, SERVICE_NAME if not set
+    service: Option<String>,
+    /// Range ending now, as a Go duration
+    #[serde(default = "default_range")]
+    range: String,
+}
+
+fn default_range() -> String {
+    "1h".to_string()
+}
+
+/// Everything shown on the page
+struct Dashboard {
+    service: String,
+    range: String,
+    services: Vec<String>,
+    bucket_secs: u32,
+    traffic: Vec<TrafficBucket>,
+    top_paths: Vec<TopPath>,
+}
+
+#[tracing::instrument(skip(app_state))]
+async fn dashboard_page(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<DashboardQuery>,
+) -> Response {
+    let Some(range_secs) = parse_go_duration(&query.range)
+        .map(|range| range.ceil() as u32)
+        .filter(|range| (1..=MAX_RANGE_SECS).contains(range))
+    else {
+        return (StatusCode::BAD_REQUEST, "Invalid range").into_response();
+    };
+
+    match load(&app_state, query, range_secs).await {
+        Ok(dashboard) => render(&dashboard).into_response(),
+        Err(e) => {
+            error!("Failed to load dashboard: {:?}", e);
+
+            (StatusCode::BAD_GATEWAY, "Failed to query Clickhouse").into_response()
+        }
+    }
+}
+
+async fn load(app_state: &AppState, query: DashboardQuery, range_secs: u32) -> Result<Dashboard> {
+    let config = app_state.config();
+    let service = query
+        .service
+        .unwrap_or_else(|| config.service_name().clone());
+
+    let bucket_secs = BUCKET_SECS
+        .into_iter()
+        .find(|bucket| range_secs.div_ceil(*bucket) <= MAX_BUCKETS)
+        .unwrap_or(BUCKET_SECS[BUCKET_SECS.len() - 1]);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let now = u32::try_from(now).map_err(|_| eyre!("Clock is out of range"))?;
+    // aligned, so that the first bucket isn't partial
+    let since = (now - range_secs) / bucket_secs * bucket_secs;
+
+    let source = routes::source(config.routes());
+    let range = Range {
+        service: &service,
+        environment: config.environment(),
+        source: &source,
+        since,
+        bucket_secs,
+    };
+    let clickhouse = app_state.clickhouse();
+    let (traffic, top_paths, mut services) = tokio::try_join!(
+        queries::traffic(clickhouse, &range),
+        queries::top_paths(clickhouse, &range, TOP_PATHS),
+        queries::services(clickhouse, &range),
+    )?;
+
+    // buckets without requests are filled, so that gaps are visible on the charts
+    let mut traffic = traffic.into_iter().peekable();
+    let traffic = (since..=now)
+        .step_by(bucket_secs as usize)
+        .map(|bucket| {
+            traffic
+                .next_if(|row| row.bucket == bucket)
+                .unwrap_or(TrafficBucket {
+                    bucket,
+                    ..Default::default()
+                })
+        })
+        .collect();
+
+    if !services.contains(&service) {
+        services.push(service.clone());
+    }
+
+    Ok(Dashboard {
+        service,
+        range: query.range,
+        services,
+        bucket_secs,
+        traffic,
+        top_paths,
+    })
+}
+
+fn format_rate(value: f64) -> String {
+    format!("{value:.2}/s")
+}
+
+fn format_count(value: f64) -> String {
+    format!("{value:.0}")
+}
+
+fn format_duration(value: f64) -> String {
+    if value < 1.0 {
+        format!("{:.0}ms", value * 1_000.0)
+    } else {
+        format!("{value:.2}s")
+    }
+}
+
+/// Link to the dashboard of another service or range
+fn link(service: &str, range: &str) -> String {
+    let query =
+        serde_urlencoded::to_string([("service", service), ("range", range)]).unwrap_or_default();
+
+    format!("?{query}")
+}
+
+fn render(dashboard: &Dashboard) -> Markup {
+    let buckets = dashboard
+        .traffic
+        .iter()
+        .map(|row| row.bucket)
+        .collect::<Vec<_>>();
+    let series = |name, color, value: fn(&TrafficBucket) -> f64| Series {
+        name,
+        color,
+        values: dashboard.traffic.iter().map(value).collect(),
+    };
+    let bucket_secs = f64::from(dashboard.bucket_secs);
+
+    let rate = [Series {
+        name: "requests",
+        color: "#2a6fdb",
+        values: dashboard
+            .traffic
+            .iter()
+            .map(|row| row.requests as f64 / bucket_secs)
+            .collect(),
+    }];
+    let statuses = [
+        series("1xx/2xx", "#3aa655", |row| row.status_1xx_2xx as f64),
+        series("3xx", "#7b8794", |row| row.status_3xx as f64),
+        series("4xx", "#e0a100", |row| row.status_4xx as f64),
+        series("5xx", "#d64545", |row| row.status_5xx as f64),
+    ];
+    let latency = [
+        series("p50", "#3aa655", |row| row.p50_duration),
+        series("p95", "#e0a100", |row| row.p95_duration),
+        series("p99", "#d64545", |row| row.p99_duration),
+    ];
+    let title = format!("Access log – {}", dashboard.service);
+
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                link rel="stylesheet" href="https://unpkg.com/mvp.css";
+                title { (title) }
+                meta name="viewport" content="width=device-width, initial-scale=1";
+            }
+            body {
+                header {
+                    h1 { (title) }
+                    nav {
+                        ul {
+                            @for service in &dashboard.services {
+                                li {
+                                    a href=(link(service, &dashboard.range)) { (service) }
+                                }
+                            }
+                        }
+                        ul {
+                            @for range in RANGES {
+                                li {
+                                    a href=(link(&dashboard.service, range)) { (range) }
+                                }
+                            }
+                        }
+                    }
+                }
+                main {
+                    section {
+                        h2 { "Requests per second" }
+                        (chart::render(Kind::Lines, &buckets, &rate, format_rate))
+                    }
+                    section {
+                        h2 { "Status classes" }
+                        (chart::render(Kind::StackedBars, &buckets, &statuses, format_count))
+                    }
+                    section {
+                        h2 { "Latency percentiles" }
+                        (chart::render(Kind::Lines, &buckets, &latency, format_duration))
+                    }
+                    section {
+                        h2 { "Top paths" }
+                        table {
+                            thead {
+                                tr {
+                                    th { "Host" }
+                                    th { "Path" }
+                                    th { "Requests" }
+                                    th { "5xx" }
+                                    th { "p95" }
+                                }
+                            }
+                            tbody {
+                                @for path in &dashboard.top_paths {
+                                    tr {
+                                        td { (path.host) }
+                                        td { code { (path.path) } }
+                                        td { (path.requests) }
+                                        td { (path.status_5xx) }
+                                        td { (format_duration(path.p95_duration)) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                footer {
+                    p { "Last " (dashboard.range) " in " (dashboard.bucket_secs) "s buckets, times in UTC" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use maud::{html, Markup};
+
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 200.0;
+/// Space for the axis labels
+const MARGIN_LEFT: f64 = 64.0;
+const MARGIN_BOTTOM: f64 = 20.0;
+
+pub struct Series {
+    pub name: &'static str,
+    pub color: &'static str,
+    pub values: Vec<f64>,
+}
+
+/// How a chart is drawn
+#[derive(Clone, Copy)]
+pub enum Kind {
+    /// Series drawn independently of each other
+    Lines,
+    /// Series stacked on top of each other, every bucket is a bar
+    StackedBars,
+}
+
+/// Segment of a stacked bar
+struct Bar<'a> {
+    bucket: usize,
+    series: &'a Series,
+    value: f64,
+    /// Sum of this and the previous series in the bucket
+    top: f64,
+}
+
+fn stacked_bars<'a>(buckets: &[u32], series: &'a [Series]) -> Vec<Bar<'a>> {
+    let mut bars = Vec::new();
+    for bucket in 0..buckets.len() {
+        let mut top = 0.0;
+        for series in series {
+            let value = series.values[bucket];
+            top += value;
+            if value > 0.0 {
+                bars.push(Bar {
+                    bucket,
+                    series,
+                    value,
+                    top,
+                });
+            }
+        }
+    }
+
+    bars
+}
+
+/// `HH:MM` of a unix timestamp, in UTC
+fn time_label(timestamp: u32) -> String {
+    let seconds_of_day = timestamp % 86_400;
+
+    format!(
+        "{:02}:{:02}",
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60
+    )
+}
+
+/// SVG chart of the series over the buckets (unix timestamps), `format` renders the Y axis values
+pub fn render(kind: Kind, buckets: &[u32], series: &[Series], format: fn(f64) -> String) -> Markup {
+    let plot_width = WIDTH - MARGIN_LEFT;
+    let plot_height = HEIGHT - MARGIN_BOTTOM;
+
+    let max = match kind {
+        Kind::Lines => series
+            .iter()
+            .flat_map(|series| series.values.iter().copied())
+            .fold(0.0, f64::max),
+        Kind::StackedBars => (0..buckets.len())
+            .map(|i| series.iter().map(|series| series.values[i]).sum::<f64>())
+            .fold(0.0, f64::max),
+    };
+    // keeps an empty range flat at the bottom
+    let max = if max > 0.0 { max } else { 1.0 };
+
+    let step = plot_width / buckets.len().max(1) as f64;
+    let x = |i: usize| MARGIN_LEFT + step * i as f64;
+    let y = |value: f64| plot_height - value / max * plot_height;
+
+    html! {
+        figure class="chart" {
+            svg viewBox={ "0 0 " (WIDTH) " " (HEIGHT) } width="100%" role="img" {
+                line x1=(MARGIN_LEFT) y1="0" x2=(MARGIN_LEFT) y2=(plot_height) stroke="#999" {}
+                line x1=(MARGIN_LEFT) y1=(plot_height) x2=(WIDTH) y2=(plot_height) stroke="#999" {}
+                text x=(MARGIN_LEFT - 4.0) y="10" text-anchor="end" font-size="11" { (format(max)) }
+                text x=(MARGIN_LEFT - 4.0) y=(plot_height) text-anchor="end" font-size="11" { (format(0.0)) }
+                @if let (Some(first), Some(last)) = (buckets.first(), buckets.last()) {
+                    text x=(MARGIN_LEFT) y=(HEIGHT - 4.0) font-size="11" { (time_label(*first)) }
+                    text x=(WIDTH) y=(HEIGHT - 4.0) text-anchor="end" font-size="11" { (time_label(*last)) }
+                }
+
+                @match kind {
+                    Kind::Lines => {
+                        @for series in series {
+                            @let points = series
+                                .values
+                                .iter()
+                                .enumerate()
+                                .map(|(i, value)| format!("{:.1},{:.1}", x(i) + step / 2.0, y(*value)))
+                                .collect::<Vec<_>>()
+                                .join(" ");
+                            polyline points=(points) fill="none" stroke=(series.color) stroke-width="1.5" {}
+                        }
+                    }
+                    Kind::StackedBars => {
+                        @for bar in stacked_bars(buckets, series) {
+                            rect
+                                x=(format!("{:.1}", x(bar.bucket)))
+                                y=(format!("{:.1}", y(bar.top)))
+                                width=(format!("{:.1}", (step - 1.0).max(0.5)))
+                                height=(format!("{:.1}", bar.value / max * plot_height))
+                                fill=(bar.series.color)
+                            {
+                                title {
+                                    (bar.series.name) ": " (format(bar.value))
+                                    " at " (time_label(buckets[bar.bucket]))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            figcaption {
+                @for series in series {
+                    span style={ "color: " (series.color) } { "■ " }
+                    (series.name) " "
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use eyre::Result;
+use klickhouse::{QueryBuilder, Row};
+
+use crate::clickhouse::ChCluster;
+
+/// Requests of a service within a single bucket of the range
+#[derive(Row, Default, Clone, Debug)]
+pub struct TrafficBucket {
+    /// Start of the bucket, unix timestamp
+    pub bucket: u32,
+    pub requests: u64,
+    pub status_1xx_2xx: u64,
+    pub status_3xx: u64,
+    pub status_4xx: u64,
+    pub status_5xx: u64,
+    pub p50_duration: f64,
+    pub p95_duration: f64,
+    pub p99_duration: f64,
+}
+
+#[derive(Row, Debug)]
+pub struct TopPath {
+    pub host: String,
+    pub path: String,
+    pub requests: u64,
+    pub status_5xx: u64,
+    pub p95_duration: f64,
+}
+
+#[derive(Row, Debug)]
+struct Service {
+    service: String,
+}
+
+/// Time range of the dashboard, ending now
+pub struct Range<'a> {
+    pub service: &'a str,
+    pub environment: &'a str,
+    /// Access log tables of all the routes, see [`crate::routes::source`]
+    pub source: &'a str,
+    /// Start of the range, unix timestamp
+    pub since: u32,
+    pub bucket_secs: u32,
+}
+
+/// Condition on the range, `$1` is the service, `$2` the environment and `$3` the start
+const FILTER: &str = "service = $1 AND environment = $2 \
+     AND logger_timestamp >= toDateTime64($3, 3, 'UTC')";
+
+fn build(range: &Range<'_>, query: &str) -> Result<String> {
+    let query = query
+        .replace("{source}", range.source)
+        .replace("{filter}", FILTER);
+    let query = QueryBuilder::new(&query)
+        .arg(range.service)
+        .arg(range.environment)
+        .arg(range.since)
+        .arg(range.bucket_secs)
+        .finalize()?;
+
+    Ok(query.to_string())
+}
+
+/// Traffic of the service per bucket, buckets without requests are missing
+pub async fn traffic(clickhouse: &ChCluster, range: &Range<'_>) -> Result<Vec<TrafficBucket>> {
+    let query = build(
+        range,
+        "SELECT toUInt32(toUnixTimestamp(toStartOfInterval(logger_timestamp, toIntervalSecond($4)))) AS bucket, \
+         count() AS requests, countIf(status < 300) AS status_1xx_2xx, \
+         countIf(status >= 300 AND status < 400) AS status_3xx, \
+         countIf(status >= 400 AND status < 500) AS status_4xx, countIf(status >= 500) AS status_5xx, \
+         quantile(0.5)(duration) AS p50_duration, quantile(0.95)(duration) AS p95_duration, \
+         quantile(0.99)(duration) AS p99_duration \
+         FROM {source} WHERE {filter} \
+         GROUP BY bucket ORDER BY bucket",
+    )?;
+
+    Ok(clickhouse.query(&query).await?)
+}
+
+pub async fn top_paths(
+    clickhouse: &ChCluster,
+    range: &Range<'_>,
+    limit: u32,
+) -> Result<Vec<TopPath>> {
+    let query = build(
+        range,
+        &format!(
+            "SELECT host, splitByChar('?', uri)[1] AS path, count() AS requests, \
+             countIf(status >= 500) AS status_5xx, quantile(0.95)(duration) AS p95_duration \
+             FROM {{source}} WHERE {{filter}} \
+             GROUP BY host, path ORDER BY requests DESC LIMIT {limit}"
+        ),
+    )?;
+
+    Ok(clickhouse.query(&query).await?)
+}
+
+/// Services with requests in the range, to switch between them
+pub async fn services(clickhouse: &ChCluster, range: &Range<'_>) -> Result<Vec<String>> {
+    let query = build(
+        range,
+        "SELECT DISTINCT service FROM {source} \
+         WHERE environment = $2 AND logger_timestamp >= toDateTime64($3, 3, 'UTC') \
+         ORDER BY service",
+    )?;
+
+    let services = clickhouse.query::<Service>(&query).await?;
+
+    Ok(services.into_iter().map(|row| row.service).collect())
+}
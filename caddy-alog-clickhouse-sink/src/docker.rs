@@ -0,0 +1,587 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use derive_getters::Getters;
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::{bytes::BytesMut, codec::Decoder};
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::{
+    app_state::AppState,
+    config::Config,
+    control::PauseMode,
+    handlers::codec::{Frame, LineCodec},
+};
+
+pub mod db;
+pub mod line;
+mod offsets;
+
+use self::{
+    db::{ContainerMessage, DbContainerLogEntry},
+    line::DockerLine,
+    offsets::{FileId, Offset, Offsets},
+};
+
+const CONTAINER_LOG_INSERT: &str =
+    "INSERT INTO container_log SETTINGS async_insert=1, wait_for_async_insert=0 FORMAT NATIVE";
+
+/// Most bytes read from a file before the lines read so far are inserted
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Suffix of the json-file logs, `<container id>-json.log`
+const LOG_SUFFIX: &str = "-json.log";
+
+/// Container writing a log file
+#[derive(Getters, Clone, Debug)]
+pub struct Container {
+    id: String,
+    name: String,
+}
+
+impl Container {
+    /// Container of the log at `/var/lib/docker/containers/<id>/<id>-json.log`: the id is taken
+    /// from the file name (or its directory), the name from `config.v2.json` next to it
+    async fn of(path: &Path) -> Self {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        let directory = path.parent();
+        let id = file_name
+            .and_then(|name| name.strip_suffix(LOG_SUFFIX))
+            .filter(|id| !id.is_empty())
+            .or_else(|| directory.and_then(Path::file_name)?.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let name = match directory {
+            Some(directory) => container_name(&directory.join("config.v2.json")).await,
+            None => None,
+        };
+
+        Self {
+            name: name.unwrap_or_else(|| id.chars().take(12).collect()),
+            id,
+        }
+    }
+}
+
+/// Name of the container (without the leading slash) from its configuration
+async fn container_name(config_path: &Path) -> Option<String> {
+    #[derive(Deserialize)]
+    struct ContainerConfig {
+        #[serde(rename = "Name")]
+        name: String,
+    }
+
+    let json = tokio::fs::read(config_path).await.ok()?;
+    let config = serde_json::from_slice::<ContainerConfig>(&json).ok()?;
+
+    Some(config.name.trim_start_matches('/').to_string()).filter(|name| !name.is_empty())
+}
+
+/// Reading state of a file before a chunk, to go back to when its messages couldn't be inserted
+struct Checkpoint {
+    position: u64,
+    buffer: BytesMut,
+    codec: LineCodec,
+    partials: BTreeMap<String, ContainerMessage>,
+}
+
+/// Log file being read
+struct TailedFile {
+    file: File,
+    id: FileId,
+    container: Container,
+    /// Bytes read so far
+    position: u64,
+    buffer: BytesMut,
+    max_line_length: usize,
+    codec: LineCodec,
+    /// First parts of the messages, which Docker split over several lines, by stream
+    /// (stdout and stderr are interleaved in the file)
+    partials: BTreeMap<String, ContainerMessage>,
+}
+
+impl TailedFile {
+    /// Opens the log of the container (`path` may be its rotated file), read from `position`
+    /// with the partial messages before it
+    async fn open(
+        path: &Path,
+        container: Container,
+        position: u64,
+        partials: BTreeMap<String, ContainerMessage>,
+        max_line_length: usize,
+    ) -> io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let id = FileId::of(&file.metadata().await?);
+        file.seek(SeekFrom::Start(position)).await?;
+
+        Ok(Self {
+            file,
+            id,
+            container,
+            position,
+            buffer: BytesMut::new(),
+            max_line_length,
+            codec: LineCodec::new(max_line_length),
+            partials,
+        })
+    }
+
+    /// Starts over after the file has been truncated
+    async fn rewind(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.position = 0;
+        self.buffer.clear();
+        self.codec = LineCodec::new(self.max_line_length);
+        self.partials.clear();
+
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+            buffer: self.buffer.clone(),
+            codec: self.codec.clone(),
+            partials: self.partials.clone(),
+        }
+    }
+
+    /// Goes back to the checkpoint, the lines read since are read again
+    async fn restore(&mut self, checkpoint: Checkpoint) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(checkpoint.position)).await?;
+        self.position = checkpoint.position;
+        self.buffer = checkpoint.buffer;
+        self.codec = checkpoint.codec;
+        self.partials = checkpoint.partials;
+
+        Ok(())
+    }
+
+    /// Where to continue reading after a restart: after the last complete line,
+    /// with the partial messages read so far
+    fn offset(&self) -> Offset {
+        Offset {
+            file: self.id,
+            offset: self.position - self.buffer.len() as u64,
+            partials: self.partials.clone(),
+        }
+    }
+
+    /// Reads up to a chunk into the buffer, returns how many bytes were read
+    async fn read_chunk(&mut self) -> io::Result<usize> {
+        self.buffer.reserve(READ_CHUNK_SIZE);
+        let mut read = 0;
+        while read < READ_CHUNK_SIZE {
+            let bytes = (&mut self.file)
+                .take((READ_CHUNK_SIZE - read) as u64)
+                .read_buf(&mut self.buffer)
+                .await?;
+            if bytes == 0 {
+                break;
+            }
+            read += bytes;
+        }
+        self.position += read as u64;
+
+        Ok(read)
+    }
+
+    /// Next line in the buffer, the last one may lack the newline at the end of a rotated file
+    fn next_frame(&mut self, at_end: bool) -> io::Result<Option<Frame>> {
+        if at_end {
+            self.codec.decode_eof(&mut self.buffer)
+        } else {
+            self.codec.decode(&mut self.buffer)
+        }
+    }
+
+    /// Message of the line, `None` until the last part of a split one
+    fn message(&mut self, line: &str, lossy: bool) -> Option<ContainerMessage> {
+        let line = match DockerLine::parse(line) {
+            Ok(line) => line,
+            Err(e) => return reject(&e),
+        };
+        let timestamp = match line.timestamp() {
+            Ok(timestamp) => timestamp,
+            Err(e) => return reject(&e),
+        };
+        let is_partial = line.is_partial();
+        let (text, stream, attrs) = line.into_parts();
+
+        let message = match self.partials.remove(&stream) {
+            Some(mut message) => {
+                message.message.push_str(&text);
+                message.lossy_utf8 |= lossy;
+                message
+            }
+            None => ContainerMessage {
+                stream,
+                timestamp,
+                message: text,
+                attrs,
+                lossy_utf8: lossy,
+            },
+        };
+
+        // an endless message is cut at the maximum line length
+        if is_partial && message.message.len() <= self.max_line_length {
+            self.partials.insert(message.stream.clone(), message);
+            None
+        } else {
+            Some(message)
+        }
+    }
+}
+
+fn reject(e: &line::InvalidLine) -> Option<ContainerMessage> {
+    error!("Rejected Docker log line: {}", e);
+    metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "docker").increment(1);
+
+    None
+}
+
+/// Tails the Docker json-file logs matching DOCKER_LOG_PATHS
+pub struct DockerTailer {
+    offsets: Offsets,
+    files: HashMap<PathBuf, TailedFile>,
+}
+
+impl DockerTailer {
+    /// Tailer continuing from the offsets saved at DOCKER_OFFSETS_PATH
+    pub async fn new(config: &Config) -> Result<Self> {
+        let offsets_path = config
+            .docker_offsets_path()
+            .as_deref()
+            .ok_or_else(|| eyre!("DOCKER_OFFSETS_PATH is not set"))?;
+        let offsets = Offsets::load(offsets_path).await.wrap_err_with(|| {
+            format!(
+                "Failed to load the Docker log offsets from {}",
+                offsets_path.display()
+            )
+        })?;
+
+        Ok(Self {
+            offsets,
+            files: HashMap::new(),
+        })
+    }
+
+    /// Reads what has been appended to the matching files since the last poll
+    /// and saves the offsets
+    pub async fn poll(&mut self, app_state: &AppState) -> Result<()> {
+        let config = app_state.config();
+        // nothing is read while paused, the logs wait in the files
+        let paused = app_state.control().wait_while_buffering().await;
+        let reject = paused == Some(PauseMode::Reject);
+
+        let patterns = config.docker_log_paths().clone();
+        let paths = tokio::task::spawn_blocking(move || matching_paths(&patterns)).await?;
+
+        // files, which are gone (e.g. rotated without a new one yet, or of removed containers),
+        // are read to their end
+        let gone = self
+            .files
+            .keys()
+            .filter(|path| !paths.contains(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in gone {
+            if let Some(mut tailed) = self.files.remove(&path) {
+                if let Err(e) = self.read(app_state, &path, &mut tailed, true, reject).await {
+                    // still open, its rest is read on the next poll
+                    error!(path = %path.display(), "Failed to finish Docker log: {:?}", e);
+                    self.files.insert(path, tailed);
+                    continue;
+                }
+            }
+            self.offsets.remove(&path);
+        }
+        let stale = self
+            .offsets
+            .paths()
+            .filter(|path| !paths.contains(*path) && !self.files.contains_key(*path))
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        for path in stale {
+            self.offsets.remove(&path);
+        }
+
+        for path in paths {
+            if let Err(e) = self.tail(app_state, &config, &path, reject).await {
+                error!(path = %path.display(), "Failed to tail Docker log: {:?}", e);
+            }
+        }
+
+        self.offsets
+            .save()
+            .await
+            .wrap_err("Failed to save the Docker log offsets")
+    }
+
+    async fn tail(
+        &mut self,
+        app_state: &AppState,
+        config: &Config,
+        path: &Path,
+        reject: bool,
+    ) -> Result<()> {
+        let max_line_length = *config.max_line_length();
+        let metadata = tokio::fs::metadata(path).await?;
+        let id = FileId::of(&metadata);
+
+        let mut tailed = match self.files.remove(path) {
+            Some(mut tailed) if tailed.id == id => {
+                if metadata.len() < tailed.position {
+                    warn!(path = %path.display(), "Docker log truncated, reading from the start");
+                    tailed.rewind().await?;
+                }
+                tailed
+            }
+            Some(mut rotated) => {
+                info!(path = %path.display(), "Docker log rotated");
+                if let Err(e) = self.read(app_state, path, &mut rotated, true, reject).await {
+                    // the new file is read once the rest of the rotated one is inserted
+                    self.files.insert(path.to_path_buf(), rotated);
+                    return Err(e);
+                }
+                TailedFile::open(path, rotated.container, 0, BTreeMap::new(), max_line_length)
+                    .await?
+            }
+            None => {
+                let container = Container::of(path).await;
+                let saved = self.offsets.get(path).cloned();
+                let (position, partials) = match saved {
+                    Some(saved) if saved.file == id && saved.offset <= metadata.len() => {
+                        (saved.offset, saved.partials)
+                    }
+                    Some(saved) => {
+                        // rotated while not running, its rest is read from the previous file
+                        self.finish_rotated(app_state, path, &container, saved, reject)
+                            .await?;
+                        (0, BTreeMap::new())
+                    }
+                    None => (0, BTreeMap::new()),
+                };
+                debug!(path = %path.display(), position, "Tailing Docker log");
+                TailedFile::open(path, container, position, partials, max_line_length).await?
+            }
+        };
+
+        // the offset is only moved past the inserted messages
+        let read = self.read(app_state, path, &mut tailed, false, reject).await;
+        self.offsets.set(path, tailed.offset());
+        self.files.insert(path.to_path_buf(), tailed);
+
+        read
+    }
+
+    /// Reads the rest of `<path>.1`, the previous file of the log, if it's the one of the offset
+    async fn finish_rotated(
+        &self,
+        app_state: &AppState,
+        path: &Path,
+        container: &Container,
+        saved: Offset,
+        reject: bool,
+    ) -> Result<()> {
+        let mut rotated_path = path.as_os_str().to_os_string();
+        rotated_path.push(".1");
+        let rotated_path = PathBuf::from(rotated_path);
+
+        let is_previous = tokio::fs::metadata(&rotated_path)
+            .await
+            .is_ok_and(|metadata| FileId::of(&metadata) == saved.file);
+        if !is_previous {
+            warn!(path = %path.display(), "Docker log rotated, the rest of the previous file is lost");
+            return Ok(());
+        }
+
+        let max_line_length = *app_state.config().max_line_length();
+        let mut rotated = TailedFile::open(
+            &rotated_path,
+            container.clone(),
+            saved.offset,
+            saved.partials,
+            max_line_length,
+        )
+        .await
+        .wrap_err_with(|| format!("Failed to open {}", rotated_path.display()))?;
+
+        self.read(app_state, &rotated_path, &mut rotated, true, reject)
+            .await
+    }
+
+    /// Reads the file up to its end and inserts the messages, `at_end` if nothing
+    /// will be appended to it anymore.
+    ///
+    /// If an insert fails, the file is left before the lines of the failed insert,
+    /// which are read again the next time.
+    async fn read(
+        &self,
+        app_state: &AppState,
+        path: &Path,
+        tailed: &mut TailedFile,
+        at_end: bool,
+        reject: bool,
+    ) -> Result<()> {
+        let span = tracing::info_span!(
+            "docker",
+            path = %path.display(),
+            container_name = %tailed.container.name,
+        );
+
+        async move {
+            loop {
+                let checkpoint = tailed.checkpoint();
+                let read = tailed
+                    .read_chunk()
+                    .await
+                    .wrap_err("Failed to read Docker log")?;
+                let last = read == 0;
+
+                let mut messages = Vec::new();
+                loop {
+                    let frame = match tailed.next_frame(at_end && last) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("Failed to read Docker log line: {}", e);
+                            break;
+                        }
+                    };
+
+                    match frame {
+                        _ if reject => {
+                            metrics::counter!(
+                                "caddy_sink_entries_rejected_total",
+                                "reason" => "paused"
+                            )
+                            .increment(1);
+                        }
+                        Frame::Line { line, lossy } => {
+                            messages.extend(tailed.message(&line, lossy));
+                        }
+                        Frame::Discarded { bytes } => {
+                            warn!(bytes, "Discarded Docker log line over the maximum length");
+                            metrics::counter!(
+                                "caddy_sink_discarded_lines_total",
+                                "peer" => format!("docker:{}", tailed.container.name)
+                            )
+                            .increment(1);
+                        }
+                    }
+                }
+                if let Err(e) = insert(app_state, &tailed.container, messages).await {
+                    tailed
+                        .restore(checkpoint)
+                        .await
+                        .wrap_err("Failed to rewind Docker log")?;
+                    return Err(e);
+                }
+
+                if last {
+                    return Ok(());
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+async fn insert(
+    app_state: &AppState,
+    container: &Container,
+    messages: Vec<ContainerMessage>,
+) -> Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let config = app_state.config();
+    let ingested_at = SystemTime::now();
+    let entries = messages
+        .into_iter()
+        .filter_map(|message| {
+            DbContainerLogEntry::new(
+                uuid::Uuid::now_v7(),
+                &config,
+                ingested_at,
+                container,
+                message,
+            )
+            .inspect_err(|e| {
+                error!("Rejected Docker log message: {}", e);
+                metrics::counter!("caddy_sink_entries_rejected_total", "reason" => e.reason())
+                    .increment(1);
+            })
+            .ok()
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let count = entries.len();
+
+    app_state
+        .clickhouse()
+        .insert(CONTAINER_LOG_INSERT, entries)
+        .await
+        .wrap_err("Failed to insert container log entries")?;
+    info!(entries = count, "Inserted container log entries");
+
+    Ok(())
+}
+
+/// Files matching any of the glob patterns
+fn matching_paths(patterns: &[String]) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+
+    for pattern in patterns {
+        let entries = match glob::glob(pattern) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!(pattern, "Invalid Docker log path pattern: {}", e);
+                continue;
+            }
+        };
+        for entry in entries {
+            match entry {
+                Ok(path) if path.is_file() => {
+                    paths.insert(path);
+                }
+                Ok(_) => {}
+                Err(e) => warn!(pattern, "Failed to list Docker logs: {}", e),
+            }
+        }
+    }
+
+    paths
+}
+
+/// Tails the Docker logs, polling the files every DOCKER_POLL_INTERVAL_MS
+pub async fn run(app_state: Arc<AppState>) -> Result<()> {
+    let mut tailer = DockerTailer::new(&app_state.config()).await?;
+    info!(
+        patterns = ?app_state.config().docker_log_paths(),
+        "Tailing Docker logs"
+    );
+
+    loop {
+        if let Err(e) = tailer.poll(&app_state).await {
+            error!("{:?}", e);
+        }
+        tokio::time::sleep(app_state.config().docker_poll_interval()).await;
+    }
+}
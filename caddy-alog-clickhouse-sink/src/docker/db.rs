@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use derive_getters::Getters;
+use klickhouse::{DateTime64, Row, Tz, Uuid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clickhouse::to_datetime64,
+    config::Config,
+    docker::Container,
+    log::{db::InvalidEntry, timestamp::is_plausible},
+};
+
+/// Message of a container, possibly joined from several lines
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ContainerMessage {
+    pub stream: String,
+    /// Microseconds since the unix epoch
+    pub timestamp: i64,
+    pub message: String,
+    pub attrs: BTreeMap<String, String>,
+    pub lossy_utf8: bool,
+}
+
+/// Row of the `container_log` table
+#[derive(Serialize, Deserialize, Row, Getters, Clone, Debug)]
+pub struct DbContainerLogEntry {
+    // Added by the sink service
+    id: Uuid,
+    service: String,
+    environment: String,
+    sink_instance: String,
+    ingested_at: DateTime64<3>,
+    // Container, derived from the path of the log file
+    container_id: String,
+    container_name: String,
+    // Docker log line
+    stream: String,
+    timestamp: DateTime64<6>,
+    message: String,
+    /// Attributes as a JSON object
+    attrs: String,
+    /// Whether invalid UTF-8 of the line was replaced
+    lossy_utf8: bool,
+}
+
+impl DbContainerLogEntry {
+    /// Entry of the message, read at `ingested_at`, messages with an implausible timestamp
+    /// are rejected
+    pub fn new(
+        id: uuid::Uuid,
+        config: &Config,
+        ingested_at: SystemTime,
+        container: &Container,
+        message: ContainerMessage,
+    ) -> Result<Self, InvalidEntry> {
+        if !is_plausible(message.timestamp, ingested_at) {
+            return Err(InvalidEntry::Timestamp(message.timestamp.to_string()));
+        }
+
+        Ok(Self {
+            id,
+            service: config.service_name().to_string(),
+            environment: config.environment().to_string(),
+            sink_instance: config.instance_id().clone(),
+            ingested_at: to_datetime64(ingested_at),
+            container_id: container.id().clone(),
+            container_name: container.name().clone(),
+            stream: message.stream,
+            timestamp: DateTime64(Tz::UTC, message.timestamp as u64),
+            message: message.message,
+            attrs: serde_json::to_string(&message.attrs).unwrap_or_default(),
+            lossy_utf8: message.lossy_utf8,
+        })
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use displaydoc::Display;
+use serde::Deserialize;
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(Error, Display, Debug)]
+pub enum InvalidLine {
+    /// Invalid json-file line: {0}
+    Json(#[from] serde_json::Error),
+    /// Invalid time {0}
+    Time(String),
+}
+
+/// Line of a json-file log, e.g.
+/// `{"log":"GET / 200\n","stream":"stdout","time":"2024-04-05T10:00:00.123456789Z"}`
+#[derive(Deserialize, Debug)]
+pub struct DockerLine {
+    /// Output of the container, with the line ending
+    log: String,
+    /// `stdout` or `stderr`
+    stream: String,
+    time: String,
+    /// Labels and environment variables, added with the `labels`/`env` log options
+    #[serde(default)]
+    attrs: BTreeMap<String, String>,
+}
+
+impl DockerLine {
+    pub fn parse(line: &str) -> Result<Self, InvalidLine> {
+        Ok(serde_json::from_str(line)?)
+    }
+
+    /// Microseconds since the unix epoch
+    pub fn timestamp(&self) -> Result<i64, InvalidLine> {
+        OffsetDateTime::parse(&self.time, &Rfc3339)
+            .ok()
+            .and_then(|time| i64::try_from(time.unix_timestamp_nanos() / 1_000).ok())
+            .ok_or_else(|| InvalidLine::Time(self.time.clone()))
+    }
+
+    /// Whether the message goes on in the next line, Docker splits messages over 16 KiB
+    pub fn is_partial(&self) -> bool {
+        !self.log.ends_with('\n')
+    }
+
+    /// Message without the line ending, the stream and the attributes
+    pub fn into_parts(self) -> (String, String, BTreeMap<String, String>) {
+        let mut message = self.log;
+        if message.ends_with('\n') {
+            message.pop();
+            if message.ends_with('\r') {
+                message.pop();
+            }
+        }
+
+        (message, self.stream, self.attrs)
+    }
+}
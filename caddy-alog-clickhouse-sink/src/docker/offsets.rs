@@ -0,0 +1,101 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::docker::db::ContainerMessage;
+
+/// Identity of a file, which survives renames, so that a rotated log can be told
+/// from the new one at its path
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl FileId {
+    pub fn of(metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        }
+    }
+}
+
+/// How far a file has been read, up to the end of its last complete line
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Offset {
+    #[serde(flatten)]
+    pub file: FileId,
+    pub offset: u64,
+    /// First parts of the messages split over several lines before the offset,
+    /// which wait for their rest, by stream
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partials: BTreeMap<String, ContainerMessage>,
+}
+
+/// Offsets of the tailed files by their paths, kept in a JSON file across restarts
+pub struct Offsets {
+    path: PathBuf,
+    offsets: BTreeMap<PathBuf, Offset>,
+    changed: bool,
+}
+
+impl Offsets {
+    /// Loads the offsets from `path`, there are none if it doesn't exist yet
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        let offsets = match tokio::fs::read(path).await {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            offsets,
+            changed: false,
+        })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Offset> {
+        self.offsets.get(path)
+    }
+
+    pub fn set(&mut self, path: &Path, offset: Offset) {
+        if self.offsets.get(path) != Some(&offset) {
+            self.offsets.insert(path.to_path_buf(), offset);
+            self.changed = true;
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.changed |= self.offsets.remove(path).is_some();
+    }
+
+    /// Paths with an offset
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.offsets.keys().map(PathBuf::as_path)
+    }
+
+    /// Writes the offsets, if they changed since the last time.
+    ///
+    /// The file is replaced atomically, so that a crash doesn't leave it half-written.
+    pub async fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        tokio::fs::write(&temporary, serde_json::to_vec_pretty(&self.offsets)?).await?;
+        tokio::fs::rename(&temporary, &self.path).await?;
+        self.changed = false;
+
+        Ok(())
+    }
+}
@@ -1,78 +1,343 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
 
 use futures::StreamExt;
-use tokio::net::TcpStream;
-use tokio_util::codec::{Framed, LinesCodec};
-use tracing::{debug, error, info, Instrument};
+use tokio::{io::AsyncRead, net::TcpStream};
+use tokio_util::codec::FramedRead;
+use tracing::{debug, error, field::Empty, info, warn, Instrument};
 
 use crate::{
     app_state::AppState,
-    log::{db::DbAccessLogEntry, AccessLogEntry},
+    compression::{self, InputCompression},
+    control::PauseMode,
+    log::{
+        db::{DbAccessLogEntry, DbAccessLogRow, DbCaddyLogEntry, InvalidEntry},
+        generic::CaddyLogEntry,
+        AccessLogEntry, LineParser, LogLine,
+    },
+    rate_limit::{Scope, Verdict},
+    routes,
 };
 
-/// Maximum line payload for one access log entry is 10MB
-const MAX_LINE_LENGTH: usize = 10 * 1024 * 1024;
+pub(crate) mod codec;
+
+use self::codec::{Frame, LineCodec};
+
+const CADDY_LOG_INSERT: &str =
+    "INSERT INTO caddy_log SETTINGS async_insert=1, wait_for_async_insert=0 FORMAT NATIVE";
 
 pub async fn handle_stream(app_state: Arc<AppState>, socket: TcpStream, peer: SocketAddr) {
-    let mut framed = Framed::new(socket, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+    let config = app_state.config();
+    let (reader, compression) =
+        match compression::decompress(socket, *config.input_compression()).await {
+            Ok(decompressed) => decompressed,
+            Err(e) => {
+                error!(peer_addr = %peer, "Failed to read from connection: {}", e);
+                return;
+            }
+        };
+    debug!(peer_addr = %peer, ?compression, "Reading stream");
+
+    let (action, peer_rates, global_rates) = app_state.rate_limiter().limits();
+    let connection_span = tracing::info_span!(
+        "connection",
+        peer_addr = %peer,
+        rate_limit_action = ?action,
+        peer_max_lines_per_sec = Empty,
+        peer_max_bytes_per_sec = Empty,
+        max_lines_per_sec = Empty,
+        max_bytes_per_sec = Empty,
+    );
+    for (field, limit) in [
+        ("peer_max_lines_per_sec", peer_rates.lines),
+        ("peer_max_bytes_per_sec", peer_rates.bytes),
+        ("max_lines_per_sec", global_rates.lines),
+        ("max_bytes_per_sec", global_rates.bytes),
+    ] {
+        if let Some(limit) = limit {
+            connection_span.record(field, limit);
+        }
+    }
+
+    read_stream(app_state, reader, peer, compression)
+        .instrument(connection_span)
+        .await
+}
+
+async fn read_stream(
+    app_state: Arc<AppState>,
+    reader: impl AsyncRead + Unpin,
+    peer: SocketAddr,
+    compression: InputCompression,
+) {
+    let config = app_state.config();
+    let control = app_state.control();
+    let connection = control.register(peer, compression);
+    let limiter = app_state.rate_limiter().connection(peer.ip());
+    let max_line_length = *config.max_line_length();
+    let mut framed = FramedRead::new(reader, LineCodec::new(max_line_length));
+    let mut parser = LineParser::default();
+    let peer_label = peer.ip().to_string();
+    // limit exceeded by the previous line, to only log the transitions
+    let mut limited: Option<Scope> = None;
+
+    loop {
+        let frame = tokio::select! {
+            frame = framed.next() => frame,
+            () = connection.disconnected() => break,
+        };
+        let Some(frame) = frame else {
+            break;
+        };
+        // nothing more is read while paused, so that the data waits in the socket buffers
+        let paused = tokio::select! {
+            paused = control.wait_while_buffering() => paused,
+            () = connection.disconnected() => break,
+        };
+        if paused == Some(PauseMode::Reject) {
+            metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "paused")
+                .increment(1);
+            continue;
+        }
+
+        let (line, bytes) = match &frame {
+            Ok(Frame::Line { line, .. }) => (true, line.len()),
+            Ok(Frame::Discarded { bytes }) => (false, *bytes),
+            Err(_) => (false, 0),
+        };
+        let verdict = limiter.admit(line, bytes);
+        let exceeded = match verdict {
+            Verdict::Pass => None,
+            Verdict::Wait(_, scope) | Verdict::Drop(scope) => Some(scope),
+        };
+        if exceeded != limited {
+            match exceeded {
+                Some(scope) => warn!(scope = scope.label(), ?verdict, "Rate limit exceeded"),
+                None => info!("Back under the rate limits"),
+            }
+            limited = exceeded;
+        }
+        match verdict {
+            Verdict::Pass => {}
+            // the socket isn't read meanwhile, so that TCP flow control slows the peer down
+            Verdict::Wait(duration, scope) => {
+                tokio::select! {
+                    () = tokio::time::sleep(duration) => {}
+                    () = connection.disconnected() => break,
+                }
+                connection.stats().throttled(duration);
+                metrics::counter!(
+                    "caddy_sink_throttled_milliseconds_total",
+                    "peer" => peer_label.clone(),
+                    "scope" => scope.label()
+                )
+                .increment(duration.as_millis() as u64);
+            }
+            Verdict::Drop(scope) => {
+                connection.stats().rate_limited();
+                metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "rate_limit")
+                    .increment(1);
+                metrics::counter!(
+                    "caddy_sink_rate_limited_lines_total",
+                    "peer" => peer_label.clone(),
+                    "scope" => scope.label()
+                )
+                .increment(1);
+                continue;
+            }
+        }
 
-    while let Some(line) = framed.next().await {
+        let ingested_at = SystemTime::now();
         let frame_uuid = uuid::Uuid::now_v7();
         let frame_span = tracing::info_span!("frame", peer_addr = %peer, frame_uuid = %frame_uuid);
         let app_state = Arc::clone(&app_state);
+        let parser = &mut parser;
+        let stats = connection.stats();
+        let peer_label = &peer_label;
 
         // running everything inside the async block to correctly instrument it
         // (see documentation for the Span::enter method from the tracing crate for more details)
         async move {
             debug!("Next frame");
-            match line {
-                Ok(line) => {
+            match frame {
+                Ok(Frame::Line { line, lossy }) => {
                     debug!(frame_len = line.len(), "Received line");
-                    let access_log_entry: AccessLogEntry = match serde_json::from_str(&line) {
-                        Ok(entry) => entry,
-                        Err(e) => {
-                            error!("Failed to parse line: {}", e);
-                            return;
-                        }
-                    };
-                    debug!("Parsed line");
-
-                    let db_access_log_entry = DbAccessLogEntry::new(
-                        frame_uuid,
-                        app_state.config().service_name(),
-                        app_state.config().environment(),
-                        access_log_entry,
-                    );
+                    stats.line(lossy);
+                    if lossy {
+                        warn!("Replaced invalid UTF-8 in line");
+                        metrics::counter!(
+                            "caddy_sink_lossy_lines_total",
+                            "peer" => peer_label.clone()
+                        )
+                        .increment(1);
+                    }
 
-                    let client = match app_state.ch_pool().get().await {
-                        Ok(client) => client,
-                        Err(e) => {
-                            error!("Failed to get CH client from pool: {}", e);
-                            return;
+                    let mut line = line.into_bytes();
+                    match parser.parse(&mut line) {
+                        Ok(LogLine::Access(entry)) => {
+                            debug!("Parsed access log entry");
+                            handle_access_entry(
+                                &app_state,
+                                frame_uuid,
+                                ingested_at,
+                                peer,
+                                lossy,
+                                entry,
+                            )
+                            .await;
                         }
-                    };
-                    debug!("Got CH client from pool");
-
-                    match client
-                        .insert_native_block(
-                            "INSERT INTO access_log SETTINGS async_insert=1, wait_for_async_insert=0 FORMAT NATIVE",
-                            vec![db_access_log_entry],
-                        )
-                        .await
-                    {
-                        Ok(_) => {
-                            info!("Inserted log entry");
+                        Ok(LogLine::Other(entry)) => {
+                            debug!(logger = entry.logger(), "Parsed other log entry");
+                            handle_caddy_entry(
+                                &app_state,
+                                frame_uuid,
+                                ingested_at,
+                                peer,
+                                lossy,
+                                entry,
+                            )
+                            .await;
                         }
                         Err(e) => {
-                            error!("Failed to insert log entry: {}", e);
+                            error!("Failed to parse line: {}", e);
+                            metrics::counter!(
+                                "caddy_sink_entries_rejected_total",
+                                "reason" => "parse"
+                            )
+                            .increment(1);
                         }
                     }
                 }
+                Ok(Frame::Discarded { bytes }) => {
+                    warn!(
+                        bytes,
+                        max_line_length, "Discarded line over the maximum length"
+                    );
+                    stats.discarded(bytes as u64);
+                    metrics::counter!(
+                        "caddy_sink_discarded_lines_total",
+                        "peer" => peer_label.clone()
+                    )
+                    .increment(1);
+                    metrics::counter!(
+                        "caddy_sink_discarded_bytes_total",
+                        "peer" => peer_label.clone()
+                    )
+                    .increment(bytes as u64);
+                }
                 Err(e) => {
                     error!("Failed to read line: {}", e);
                 }
             }
-        }.instrument(frame_span)
+        }
+        .instrument(frame_span)
+        .await
+    }
+
+    let info = connection.info();
+    info!(
+        peer_addr = %peer,
+        lines = info.lines,
+        lossy_lines = info.lossy_lines,
+        discarded_lines = info.discarded_lines,
+        discarded_bytes = info.discarded_bytes,
+        rate_limited_lines = info.rate_limited_lines,
+        throttled_ms = info.throttled_ms,
+        "Connection closed"
+    );
+}
+
+fn reject(e: &InvalidEntry) {
+    error!("Rejected log entry: {}", e);
+    metrics::counter!("caddy_sink_entries_rejected_total", "reason" => e.reason()).increment(1);
+}
+
+async fn handle_access_entry(
+    app_state: &AppState,
+    id: uuid::Uuid,
+    ingested_at: SystemTime,
+    peer: SocketAddr,
+    lossy_utf8: bool,
+    access_log_entry: AccessLogEntry<'_>,
+) {
+    let db_access_log_entry = match DbAccessLogEntry::new(
+        id,
+        &app_state.config(),
+        ingested_at,
+        app_state.visitors(),
+        lossy_utf8,
+        access_log_entry,
+    ) {
+        Ok(entry) => entry,
+        Err(e) => return reject(&e),
+    };
+    app_state
+        .lag()
+        .observe(peer.ip(), db_access_log_entry.logged_at(), ingested_at);
+
+    app_state.tail().publish(&db_access_log_entry);
+    app_state.alerts().observe(&db_access_log_entry);
+
+    if let Some(incident) = app_state.abuse().observe(&db_access_log_entry) {
+        if let Err(e) = app_state
+            .clickhouse()
+            .insert("INSERT INTO abuse_incident FORMAT NATIVE", vec![incident])
+            .await
+        {
+            error!("Failed to insert abuse incident: {}", e);
+        }
+    }
+
+    let config = app_state.config();
+    let destination = routes::route(config.routes(), &db_access_log_entry);
+    match app_state
+        .clickhouse()
+        .insert(
+            &destination.insert_query(),
+            vec![DbAccessLogRow(db_access_log_entry)],
+        )
+        .await
+    {
+        Ok(_) => {
+            info!(%destination, "Inserted log entry");
+        }
+        Err(e) => {
+            error!(%destination, "Failed to insert log entry: {}", e);
+        }
+    }
+}
+
+async fn handle_caddy_entry(
+    app_state: &AppState,
+    id: uuid::Uuid,
+    ingested_at: SystemTime,
+    peer: SocketAddr,
+    lossy_utf8: bool,
+    caddy_log_entry: CaddyLogEntry,
+) {
+    let db_caddy_log_entry = match DbCaddyLogEntry::new(
+        id,
+        &app_state.config(),
+        ingested_at,
+        lossy_utf8,
+        caddy_log_entry,
+    ) {
+        Ok(entry) => entry,
+        Err(e) => return reject(&e),
+    };
+    app_state
+        .lag()
+        .observe(peer.ip(), db_caddy_log_entry.logged_at(), ingested_at);
+
+    match app_state
+        .clickhouse()
+        .insert(CADDY_LOG_INSERT, vec![db_caddy_log_entry])
         .await
+    {
+        Ok(_) => {
+            info!("Inserted caddy log entry");
+        }
+        Err(e) => {
+            error!("Failed to insert caddy log entry: {}", e);
+        }
     }
 }
@@ -0,0 +1,113 @@
+use std::io;
+
+use tokio_util::{
+    bytes::{Buf, BytesMut},
+    codec::Decoder,
+};
+
+/// Frame of the newline-delimited stream
+#[derive(Debug)]
+pub enum Frame {
+    /// Line without the line ending, `lossy` if invalid UTF-8 sequences were replaced
+    Line { line: String, lossy: bool },
+    /// Line over the maximum length, dropped up to the next newline
+    Discarded { bytes: usize },
+}
+
+/// Newline-delimited lines, like `LinesCodec`, except that neither an oversized line
+/// nor invalid UTF-8 ends the stream
+#[derive(Clone)]
+pub struct LineCodec {
+    max_length: usize,
+    /// Where to continue looking for the newline
+    next_index: usize,
+    /// Bytes dropped so far of the oversized line being skipped
+    discarding: Option<usize>,
+}
+
+impl LineCodec {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            max_length,
+            next_index: 0,
+            discarding: None,
+        }
+    }
+
+    fn line(bytes: BytesMut) -> Frame {
+        let mut bytes = Vec::from(bytes);
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+
+        match String::from_utf8(bytes) {
+            Ok(line) => Frame::Line { line, lossy: false },
+            Err(e) => Frame::Line {
+                line: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                lossy: true,
+            },
+        }
+    }
+}
+
+impl Decoder for LineCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let newline = src[self.next_index..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|offset| self.next_index + offset);
+
+        match (newline, self.discarding) {
+            (Some(end), Some(discarded)) => {
+                src.advance(end + 1);
+                self.next_index = 0;
+                self.discarding = None;
+
+                Ok(Some(Frame::Discarded {
+                    bytes: discarded + end,
+                }))
+            }
+            (Some(end), None) => {
+                let mut line = src.split_to(end + 1);
+                self.next_index = 0;
+
+                if end > self.max_length {
+                    return Ok(Some(Frame::Discarded { bytes: end }));
+                }
+                line.truncate(end);
+
+                Ok(Some(Self::line(line)))
+            }
+            // the rest of the line isn't kept, so that the buffer doesn't grow
+            (None, discarding) if discarding.is_some() || src.len() > self.max_length => {
+                self.discarding = Some(discarding.unwrap_or_default() + src.len());
+                self.next_index = 0;
+                src.clear();
+
+                Ok(None)
+            }
+            (None, _) => {
+                self.next_index = src.len();
+
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(frame) = self.decode(src)? {
+            return Ok(Some(frame));
+        }
+        self.next_index = 0;
+
+        // last line without a newline
+        match self.discarding.take() {
+            Some(bytes) => Ok(Some(Frame::Discarded { bytes })),
+            None if src.is_empty() => Ok(None),
+            None => Ok(Some(Self::line(src.split()))),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Sse,
+    },
+    routing::get,
+    Router,
+};
+use eyre::{Result, WrapErr};
+use futures::{stream, Stream};
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::{
+    app_state::AppState,
+    dashboard, metrics,
+    tail::{TailFilter, TailSubscription},
+};
+
+pub mod auth;
+
+pub async fn serve(app_state: Arc<AppState>, bind_to: &str) -> Result<()> {
+    let app = Router::new()
+        .route("/tail", get(tail))
+        .route("/metrics", get(metrics))
+        // the tailed entries carry the request headers, so they're as private as the dashboard
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&app_state),
+            auth::optional_basic_auth,
+        ))
+        .nest("/dashboard", dashboard::router(Arc::clone(&app_state)))
+        .with_state(app_state);
+
+    let listener = TcpListener::bind(bind_to)
+        .await
+        .wrap_err_with(|| format!("Failed to bind HTTP server to address {}", bind_to))?;
+    info!("HTTP server listening on {}", bind_to);
+
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .wrap_err("HTTP server failed")
+}
+
+/// Prometheus metrics of the sink
+async fn metrics() -> String {
+    metrics::handle().render()
+}
+
+/// Live tail of the parsed access log entries as Server-Sent Events
+#[tracing::instrument(skip(app_state))]
+async fn tail(
+    State(app_state): State<Arc<AppState>>,
+    Query(filter): Query<TailFilter>,
+) -> impl IntoResponse {
+    let subscription = app_state.tail().subscribe(filter);
+
+    Sse::new(tail_events(subscription)).keep_alive(KeepAlive::default())
+}
+
+/// Entries as `message` events, preceded by a `dropped` event
+/// with the number of skipped entries whenever the client falls behind
+fn tail_events(subscription: TailSubscription) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(subscription, |mut subscription| async move {
+        let dropped = subscription.take_dropped();
+        if dropped > 0 {
+            let event = Event::default().event("dropped").data(dropped.to_string());
+
+            return Some((Ok(event), subscription));
+        }
+
+        let entry = subscription.recv().await?;
+
+        Some((Ok(Event::default().data(entry)), subscription))
+    })
+}
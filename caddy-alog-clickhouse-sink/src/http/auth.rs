@@ -0,0 +1,74 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{AppendHeaders, IntoResponse, Response},
+    RequestExt,
+};
+use axum_auth::AuthBasic;
+use secrecy::ExposeSecret;
+use tracing::warn;
+
+use crate::app_state::AppState;
+
+/// Basic auth with DASHBOARD_USER and DASHBOARD_PASSWORD, the dashboard is disabled without them
+#[tracing::instrument(skip(app_state, addr, req, next), fields(ip = %addr.ip(), port = addr.port()))]
+pub async fn basic_auth(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    authorize(&app_state, addr, req, next).await
+}
+
+/// Basic auth like [`basic_auth`], if DASHBOARD_USER is set, open otherwise
+#[tracing::instrument(skip(app_state, addr, req, next), fields(ip = %addr.ip(), port = addr.port()))]
+pub async fn optional_basic_auth(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if app_state.config().dashboard_user().is_none() {
+        return next.run(req).await;
+    }
+
+    authorize(&app_state, addr, req, next).await
+}
+
+async fn authorize(
+    app_state: &AppState,
+    addr: SocketAddr,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let config = app_state.config();
+    let (Some(dashboard_user), Some(dashboard_password)) =
+        (config.dashboard_user(), config.dashboard_password())
+    else {
+        return (StatusCode::NOT_FOUND, "Dashboard is disabled").into_response();
+    };
+
+    let auth = req.extract_parts::<AuthBasic>().await;
+    let headers = AppendHeaders([(
+        "WWW-Authenticate",
+        "Basic realm=\"Caddy access log dashboard\"",
+    )]);
+
+    match auth {
+        Ok(AuthBasic((user, pass))) => {
+            if pass.is_some_and(|pass| {
+                user == *dashboard_user && pass == *dashboard_password.expose_secret()
+            }) {
+                return next.run(req).await;
+            }
+            warn!(%addr, "Unauthorized");
+
+            (StatusCode::UNAUTHORIZED, headers, "Unauthorized").into_response()
+        }
+        Err(e) => (StatusCode::UNAUTHORIZED, headers, e.1).into_response(),
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use arc_swap::ArcSwap;
+use tracing::warn;
+
+use crate::config::Config;
+
+/// Minimal time between two warnings about the same peer
+const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tracks the delay between Caddy logging an entry and the sink receiving it, per peer.
+///
+/// Entries arriving late mean a backlog somewhere on the way, entries from the future
+/// mean the clock of the Caddy host is ahead of the sink's one.
+pub struct LagMonitor {
+    threshold: ArcSwap<Duration>,
+    last_warned: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl LagMonitor {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: ArcSwap::from_pointee(config.ingest_lag_threshold()),
+            last_warned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn reload(&self, config: &Config) {
+        self.threshold.store(config.ingest_lag_threshold().into());
+    }
+
+    /// Accounts an entry logged at `logged_at` and received at `ingested_at`
+    pub fn observe(&self, peer: IpAddr, logged_at: SystemTime, ingested_at: SystemTime) {
+        let lag = match ingested_at.duration_since(logged_at) {
+            Ok(lag) => lag.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        };
+        let peer_label = peer.to_string();
+
+        metrics::histogram!("caddy_sink_ingest_lag_seconds", "peer" => peer_label.clone())
+            .record(lag.max(0.0));
+        metrics::gauge!("caddy_sink_ingest_lag_last_seconds", "peer" => peer_label.clone())
+            .set(lag);
+
+        let threshold = self.threshold.load().as_secs_f64();
+        let (kind, message) = if lag > threshold {
+            ("delayed", "Entries arrive late")
+        } else if -lag > threshold {
+            (
+                "clock_skew",
+                "Entries are logged in the future, the peer's clock may be ahead",
+            )
+        } else {
+            return;
+        };
+        metrics::counter!(
+            "caddy_sink_ingest_lag_exceeded_total",
+            "peer" => peer_label,
+            "kind" => kind,
+        )
+        .increment(1);
+
+        let now = Instant::now();
+        {
+            let mut last_warned = self.last_warned.lock().unwrap();
+            if last_warned
+                .get(&peer)
+                .is_some_and(|at| now.duration_since(*at) < WARN_INTERVAL)
+            {
+                return;
+            }
+            last_warned.insert(peer, now);
+        }
+
+        warn!(%peer, lag_secs = lag, "{}", message);
+    }
+}
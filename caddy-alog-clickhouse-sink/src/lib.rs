@@ -0,0 +1,24 @@
+pub mod abuse;
+pub mod admin;
+pub mod alerts;
+pub mod app_state;
+pub mod clickhouse;
+pub mod compression;
+pub mod config;
+pub mod control;
+pub mod dashboard;
+pub mod docker;
+pub mod handlers;
+pub mod http;
+pub mod lag;
+pub mod log;
+pub mod metrics;
+pub mod rate_limit;
+pub mod reload;
+pub mod report;
+pub mod routes;
+pub mod schema;
+pub mod syslog;
+pub mod tail;
+pub mod telemetry;
+pub mod visitors;
@@ -1,52 +1,163 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
 
 use derive_getters::{Dissolve, Getters};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
 pub mod db;
+pub mod duration;
+pub mod generic;
+pub mod headers;
+pub mod promoted;
+pub mod raw;
+pub mod timestamp;
+pub mod trace;
+pub mod upstream;
 
-/// General information about the log entry
-#[derive(Serialize, Deserialize, Dissolve, Getters, Debug)]
-pub struct Meta {
-    /// The log level
-    level: String,
-    /// The timestamp of the log entry
-    #[serde(rename = "ts")]
-    timestamp: f64,
-    /// The logger name
-    logger: String,
-    /// The log message
-    #[serde(rename = "msg")]
-    message: String,
+use self::{generic::CaddyLogEntry, headers::Headers, raw::RawValue};
+
+#[cfg(feature = "simd")]
+pub type ParseError = simd_json::Error;
+#[cfg(not(feature = "simd"))]
+pub type ParseError = serde_json::Error;
+
+/// Whether the entries of the logger are access log entries
+/// (`http.log.access`, or `http.log.access.<name>` for the named server logs)
+pub fn is_access_logger(logger: &str) -> bool {
+    logger
+        .strip_prefix("http.log.access")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Line, dispatched by its logger
+// access entries are the common case, boxing them would cost an allocation per line
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum LogLine<'a> {
+    Access(AccessLogEntry<'a>),
+    Other(CaddyLogEntry),
+}
+
+/// Parser of the incoming lines, meant to be kept for the whole connection
+/// (the SIMD parser reuses its scratch buffers between the lines)
+#[derive(Default)]
+pub struct LineParser {
+    #[cfg(feature = "simd")]
+    buffers: simd_json::Buffers,
 }
 
-/// A HashMap of headers
-pub type Headers = HashMap<String, Vec<String>>;
+impl LineParser {
+    /// Parses the line, strings of access log entries without escapes are borrowed from it
+    /// (the line is mutable, because the SIMD parser unescapes strings in place)
+    pub fn parse<'a>(&mut self, line: &'a mut [u8]) -> Result<LogLine<'a>, ParseError> {
+        #[cfg(feature = "simd")]
+        {
+            // the line is parsed once, the fallback deserializes the same tape again
+            let mut deserializer =
+                simd_json::Deserializer::from_slice_with_buffers(line, &mut self.buffers)?;
+            let access = AccessLogEntry::deserialize(&mut deserializer);
+
+            dispatch(access, || {
+                deserializer.restart();
+                CaddyLogEntry::deserialize(&mut deserializer)
+            })
+        }
+
+        #[cfg(not(feature = "simd"))]
+        {
+            let line: &'a [u8] = line;
+
+            dispatch(serde_json::from_slice(line), || {
+                serde_json::from_slice(line)
+            })
+        }
+    }
+}
+
+/// Access log entries take the fast path, everything else is parsed as a generic entry.
+///
+/// Entries of the access loggers, which can't be parsed, fail with the access log parsing error.
+fn dispatch<'a>(
+    access: Result<AccessLogEntry<'a>, ParseError>,
+    other: impl FnOnce() -> Result<CaddyLogEntry, ParseError>,
+) -> Result<LogLine<'a>, ParseError> {
+    let access_error = match access {
+        Ok(entry) if is_access_logger(entry.logger()) => return Ok(LogLine::Access(entry)),
+        Ok(_) => None,
+        Err(e) => Some(e),
+    };
+
+    match (other(), access_error) {
+        (Ok(entry), Some(e)) if is_access_logger(entry.logger()) => Err(e),
+        (Ok(entry), _) => Ok(LogLine::Other(entry)),
+        (Err(e), access_error) => Err(access_error.unwrap_or(e)),
+    }
+}
 
 /// Information about the request, handled by the Caddy server
-#[derive(Serialize, Deserialize, Dissolve, Getters, Debug)]
-pub struct RequestInfo {
-    remote_ip: String,
-    remote_port: String,
-    client_ip: Option<String>,
-    #[serde(rename = "proto")]
-    protocol: String,
-    method: String,
-    host: String,
-    uri: String,
-    headers: Headers,
-}
-
-#[derive(Serialize, Deserialize, Dissolve, Getters, Debug)]
-pub struct AccessLogEntry {
-    #[serde(flatten)]
-    meta: Meta,
-    request: RequestInfo,
+#[derive(Deserialize, Dissolve, Getters, Debug)]
+pub struct RequestInfo<'a> {
+    #[serde(borrow)]
+    remote_ip: Cow<'a, str>,
+    #[serde(borrow)]
+    remote_port: Cow<'a, str>,
+    #[serde(borrow)]
+    client_ip: Option<Cow<'a, str>>,
+    #[serde(borrow, rename = "proto")]
+    protocol: Cow<'a, str>,
+    #[serde(borrow)]
+    method: Cow<'a, str>,
+    #[serde(borrow)]
+    host: Cow<'a, str>,
+    #[serde(borrow)]
+    uri: Cow<'a, str>,
+    #[serde(borrow)]
+    headers: Headers<'a>,
+}
+
+impl RequestInfo<'_> {
+    /// Address of the client: `client_ip` accounts for the trusted proxies,
+    /// so it's preferred over the remote one
+    pub fn client_addr(&self) -> &str {
+        self.client_ip.as_deref().unwrap_or(&self.remote_ip)
+    }
+}
+
+/// Caddy access log entry, borrowing from the parsed line
+#[derive(Deserialize, Dissolve, Getters, Debug)]
+pub struct AccessLogEntry<'a> {
+    // General information about the log entry, kept inline, since `#[serde(flatten)]`
+    // buffers the whole entry before deserializing it
+    /// The log level
+    #[serde(borrow)]
+    level: Cow<'a, str>,
+    /// The timestamp of the log entry, encoded according to Caddy's `time_format`
+    #[serde(borrow, rename = "ts")]
+    timestamp: RawValue<'a>,
+    /// The logger name
+    #[serde(borrow)]
+    logger: Cow<'a, str>,
+    /// The log message
+    #[serde(borrow, rename = "msg")]
+    message: Cow<'a, str>,
+    #[serde(borrow)]
+    request: RequestInfo<'a>,
     bytes_read: u64,
-    user_id: Option<String>,
-    duration: f64,
+    #[serde(borrow)]
+    user_id: Option<Cow<'a, str>>,
+    /// Encoded according to Caddy's `duration_format`
+    #[serde(borrow)]
+    duration: RawValue<'a>,
     size: u64,
     status: u16,
-    #[serde(rename = "resp_headers")]
-    response_headers: Headers,
+    #[serde(borrow, rename = "resp_headers")]
+    response_headers: Headers<'a>,
+    // Upstream info, added with `log_append` in `reverse_proxy` sites
+    #[serde(borrow, default, alias = "upstream_address")]
+    upstream_addr: Option<Cow<'a, str>>,
+    #[serde(default, deserialize_with = "upstream::deserialize_opt_status")]
+    upstream_status: Option<u16>,
+    #[serde(default, deserialize_with = "duration::deserialize_opt_seconds")]
+    upstream_latency: Option<f64>,
+    #[serde(default, deserialize_with = "duration::deserialize_opt_millis")]
+    upstream_latency_ms: Option<f64>,
 }
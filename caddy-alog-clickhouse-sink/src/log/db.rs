@@ -1,18 +1,83 @@
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use derive_getters::Getters;
-use klickhouse::{DateTime64, Row, Tz, Uuid};
+use displaydoc::Display;
+use klickhouse::{DateTime64, IndexMap, Row, Type, Tz, Uuid, Value};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clickhouse::to_datetime64,
+    config::Config,
+    log::{
+        duration::parse_duration,
+        generic::CaddyLogEntry,
+        headers::HeaderMap,
+        promoted::{self, PromotedHeaders},
+        raw::RawValue,
+        timestamp::{is_plausible, parse_timestamp},
+        trace::TraceContext,
+        upstream::Upstream,
+        AccessLogEntry,
+    },
+    visitors::VisitorKeys,
+};
+
+/// Longest plausible request (e.g. a long-lived websocket connection)
+const MAX_DURATION_SECS: f64 = 30.0 * 86_400.0;
+
+/// Entry with values, which can't be stored
+#[derive(Error, Display, Debug)]
+pub enum InvalidEntry {
+    /// Invalid timestamp {0}
+    Timestamp(String),
+    /// Invalid duration {0}
+    Duration(String),
+    /// Invalid status {0}
+    Status(u16),
+}
+
+impl InvalidEntry {
+    /// Label of the rejection reason in the metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Timestamp(_) => "timestamp",
+            Self::Duration(_) => "duration",
+            Self::Status(_) => "status",
+        }
+    }
+}
+
+/// Log time of an entry, received at `ingested_at`
+fn logger_timestamp(
+    raw: &RawValue<'_>,
+    config: &Config,
+    ingested_at: SystemTime,
+) -> Result<DateTime64<6>, InvalidEntry> {
+    parse_timestamp(raw, *config.time_format())
+        .filter(|micros| is_plausible(*micros, ingested_at))
+        .map(|micros| DateTime64(Tz::UTC, micros as u64))
+        .ok_or_else(|| InvalidEntry::Timestamp(raw.to_string()))
+}
 
-use crate::log::{AccessLogEntry, Headers};
+fn logged_at(logger_timestamp: &DateTime64<6>) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(logger_timestamp.1)
+}
 
-#[derive(Serialize, Deserialize, Row, Getters, Debug)]
+#[derive(Serialize, Deserialize, Row, Getters, Clone, Debug)]
 pub struct DbAccessLogEntry {
     // Added by the sink service
     id: Uuid,
     service: String,
     environment: String,
+    sink_instance: String,
+    ingested_at: DateTime64<3>,
     // Caddy logger meta
     level: String,
-    logger_timestamp: DateTime64<3>,
+    logger_timestamp: DateTime64<6>,
     logger: String,
     message: String,
     // Caddy request info
@@ -23,7 +88,7 @@ pub struct DbAccessLogEntry {
     method: String,
     host: String,
     uri: String,
-    headers: Headers,
+    headers: HeaderMap,
     // Caddy request handling info
     bytes_read: u64,
     user_id: Option<String>,
@@ -31,46 +96,222 @@ pub struct DbAccessLogEntry {
     size: u64,
     status: u16,
     // Caddy response headers
-    response_headers: Headers,
+    response_headers: HeaderMap,
+    // Reverse proxy upstream info
+    upstream_addr: Option<String>,
+    upstream_status: Option<u16>,
+    upstream_latency: Option<f64>,
+    // Trace context
+    trace_id: Option<String>,
+    span_id: Option<String>,
+    trace_state: Option<String>,
+    request_id: Option<String>,
+    // Anonymous visitor key, see `VisitorKeys`
+    visitor_key: u64,
+    // Whether invalid UTF-8 of the line was replaced
+    lossy_utf8: bool,
+    // Promoted headers, their columns depend on the config, so they're added by `DbAccessLogRow`
+    #[klickhouse(skip)]
+    #[serde(flatten, skip_deserializing)]
+    promoted_headers: PromotedHeaders,
 }
 
 impl DbAccessLogEntry {
     pub fn new(
         id: uuid::Uuid,
-        service: &str,
-        environment: &str,
-        access_log_entry: AccessLogEntry,
-    ) -> Self {
-        let (meta, request, bytes_read, user_id, duration, size, status, response_headers) =
-            access_log_entry.dissolve();
-        let (level, logger_timestamp, logger, message) = meta.dissolve();
-        let (remote_ip, remote_port, client_ip, protocol, method, host, uri, headers) =
-            request.dissolve();
-        let millis = logger_timestamp as u64 * 1_000 + (logger_timestamp.fract() * 1_000.0) as u64;
-        let logger_timestamp = DateTime64(Tz::UTC, millis);
+        config: &Config,
+        ingested_at: SystemTime,
+        visitors: &VisitorKeys,
+        lossy_utf8: bool,
+        access_log_entry: AccessLogEntry<'_>,
+    ) -> Result<Self, InvalidEntry> {
+        let logger_timestamp = logger_timestamp(access_log_entry.timestamp(), config, ingested_at)?;
+        // keyed with the salt of the day the entry is counted on by `visitors_daily`
+        let visitor_key = visitors.key(
+            &access_log_entry,
+            logged_at(&logger_timestamp),
+            ingested_at,
+            config.visitor_max_lag(),
+        );
+        let duration = parse_duration(access_log_entry.duration(), *config.duration_format())
+            .filter(|duration| (0.0..=MAX_DURATION_SECS).contains(duration))
+            .ok_or_else(|| InvalidEntry::Duration(access_log_entry.duration().to_string()))?;
+        if *access_log_entry.status() > 999 {
+            return Err(InvalidEntry::Status(*access_log_entry.status()));
+        }
 
-        Self {
-            id,
-            service: service.to_string(),
-            environment: environment.to_string(),
+        let (upstream_addr, upstream_status, upstream_latency) =
+            Upstream::extract(&access_log_entry, config).dissolve();
+        let (trace_id, span_id, trace_state, request_id) =
+            TraceContext::extract(&access_log_entry, config).dissolve();
+        let (
             level,
-            logger_timestamp,
+            _,
             logger,
             message,
-            remote_ip,
-            remote_port,
-            client_ip,
-            protocol,
-            method,
-            host,
-            uri,
-            headers,
+            request,
             bytes_read,
             user_id,
+            _,
+            size,
+            status,
+            mut response_headers,
+            ..,
+        ) = access_log_entry.dissolve();
+        let (remote_ip, remote_port, client_ip, protocol, method, host, uri, mut headers) =
+            request.dissolve();
+        let promoted_headers = PromotedHeaders::extract(
+            &mut headers,
+            config.promoted_headers(),
+            &mut response_headers,
+            config.promoted_response_headers(),
+        );
+
+        Ok(Self {
+            id,
+            service: config.service_name().to_string(),
+            environment: config.environment().to_string(),
+            sink_instance: config.instance_id().clone(),
+            ingested_at: to_datetime64(ingested_at),
+            level: level.into_owned(),
+            logger_timestamp,
+            logger: logger.into_owned(),
+            message: message.into_owned(),
+            remote_ip: remote_ip.into_owned(),
+            remote_port: remote_port.into_owned(),
+            client_ip: client_ip.map(Cow::into_owned),
+            protocol: protocol.into_owned(),
+            method: method.into_owned(),
+            host: host.into_owned(),
+            uri: uri.into_owned(),
+            headers: headers.into(),
+            bytes_read,
+            user_id: user_id.map(Cow::into_owned),
             duration,
             size,
             status,
-            response_headers,
-        }
+            response_headers: response_headers.into(),
+            upstream_addr,
+            upstream_status,
+            upstream_latency,
+            trace_id,
+            span_id,
+            trace_state,
+            request_id,
+            visitor_key,
+            lossy_utf8,
+            promoted_headers,
+        })
+    }
+
+    /// When Caddy logged the entry
+    pub fn logged_at(&self) -> SystemTime {
+        logged_at(&self.logger_timestamp)
+    }
+
+    /// Address of the client, see [`RequestInfo::client_addr`](crate::log::RequestInfo::client_addr)
+    pub fn client_addr(&self) -> &str {
+        self.client_ip.as_deref().unwrap_or(&self.remote_ip)
+    }
+}
+
+/// Row of the `caddy_log` table, with the output of all the other loggers
+#[derive(Serialize, Deserialize, Row, Getters, Clone, Debug)]
+pub struct DbCaddyLogEntry {
+    // Added by the sink service
+    id: Uuid,
+    service: String,
+    environment: String,
+    sink_instance: String,
+    ingested_at: DateTime64<3>,
+    // Caddy logger meta
+    level: String,
+    logger_timestamp: DateTime64<6>,
+    logger: String,
+    message: String,
+    /// Other fields of the entry, as a JSON object
+    fields: String,
+    // Whether invalid UTF-8 of the line was replaced
+    lossy_utf8: bool,
+}
+
+impl DbCaddyLogEntry {
+    pub fn new(
+        id: uuid::Uuid,
+        config: &Config,
+        ingested_at: SystemTime,
+        lossy_utf8: bool,
+        caddy_log_entry: CaddyLogEntry,
+    ) -> Result<Self, InvalidEntry> {
+        let logger_timestamp =
+            logger_timestamp(&caddy_log_entry.raw_timestamp(), config, ingested_at)?;
+        let (level, _, logger, message, fields) = caddy_log_entry.dissolve();
+
+        Ok(Self {
+            id,
+            service: config.service_name().to_string(),
+            environment: config.environment().to_string(),
+            sink_instance: config.instance_id().clone(),
+            ingested_at: to_datetime64(ingested_at),
+            level,
+            logger_timestamp,
+            logger,
+            message,
+            fields: serde_json::Value::Object(fields).to_string(),
+            lossy_utf8,
+        })
+    }
+
+    /// When Caddy logged the entry
+    pub fn logged_at(&self) -> SystemTime {
+        logged_at(&self.logger_timestamp)
+    }
+}
+
+/// Row of the `access_log` table: the entry with its promoted header columns
+#[derive(Clone, Debug)]
+pub struct DbAccessLogRow(pub DbAccessLogEntry);
+
+impl Row for DbAccessLogRow {
+    const COLUMN_COUNT: Option<usize> = None;
+
+    fn column_names() -> Option<Vec<Cow<'static, str>>> {
+        None
+    }
+
+    fn deserialize_row(map: Vec<(&str, &Type, Value)>) -> klickhouse::Result<Self> {
+        let (promoted, entry): (Vec<_>, Vec<_>) = map.into_iter().partition(|(name, _, _)| {
+            name.starts_with(promoted::REQUEST_PREFIX)
+                || name.starts_with(promoted::RESPONSE_PREFIX)
+        });
+        let promoted_headers = promoted
+            .into_iter()
+            .map(|(name, type_, value)| {
+                Ok((
+                    name.to_string(),
+                    klickhouse::FromSql::from_sql(type_, value)?,
+                ))
+            })
+            .collect::<klickhouse::Result<_>>()?;
+
+        let mut entry = DbAccessLogEntry::deserialize_row(entry)?;
+        entry.promoted_headers = promoted_headers;
+
+        Ok(Self(entry))
+    }
+
+    fn serialize_row(
+        mut self,
+        type_hints: &IndexMap<String, Type>,
+    ) -> klickhouse::Result<Vec<(Cow<'static, str>, Value)>> {
+        let promoted_headers = std::mem::take(&mut self.0.promoted_headers);
+        let mut columns = self.0.serialize_row(type_hints)?;
+        columns.extend(
+            promoted_headers
+                .into_columns()
+                .map(|(column, value)| (Cow::Owned(column), Value::String(value.into_bytes()))),
+        );
+
+        Ok(columns)
     }
 }
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Deserializer};
+
+use crate::log::raw::RawValue;
+
+/// Caddy's `duration_format` of the log encoder.
+///
+/// Only matters for numeric durations, strings are always parsed as Go durations.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationFormat {
+    #[default]
+    #[serde(alias = "s", alias = "second", alias = "string")]
+    Seconds,
+    #[serde(alias = "ms", alias = "milli", alias = "millis")]
+    Milliseconds,
+    #[serde(alias = "ns", alias = "nano", alias = "nanos")]
+    Nanoseconds,
+}
+
+impl DurationFormat {
+    fn seconds_per_unit(self) -> f64 {
+        match self {
+            Self::Seconds => 1.0,
+            Self::Milliseconds => 1e-3,
+            Self::Nanoseconds => 1e-9,
+        }
+    }
+}
+
+/// Duration in seconds, `None` if the value isn't a valid duration
+pub fn parse_duration(value: &RawValue<'_>, format: DurationFormat) -> Option<f64> {
+    let seconds = match value {
+        RawValue::Integer(value) => *value as f64 * format.seconds_per_unit(),
+        RawValue::Float(value) => value * format.seconds_per_unit(),
+        RawValue::Text(text) => match text.trim().parse::<f64>() {
+            Ok(value) => value * format.seconds_per_unit(),
+            Err(_) => parse_go_duration(text)?,
+        },
+    };
+
+    seconds.is_finite().then_some(seconds)
+}
+
+/// Parses a Go duration string (e.g. `1.5ms`, `1m3.2s`, `250µs`) into seconds.
+///
+/// Plain numbers without a unit are treated as seconds, same as Caddy does by default.
+pub fn parse_go_duration(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(seconds);
+    }
+
+    let (negative, mut rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let multiplier = match &rest[..unit_len] {
+            "ns" => 1e-9,
+            "us" | "µs" | "μs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+
+        total += number * multiplier;
+    }
+
+    Some(if negative { -total } else { total })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDuration {
+    Number(f64),
+    Text(String),
+}
+
+/// Deserializes an optional duration, given either as a number of seconds or as a Go duration string.
+///
+/// Unparseable values are treated as missing.
+pub fn deserialize_opt_seconds<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<RawDuration>::deserialize(deserializer)? {
+        Some(RawDuration::Number(seconds)) => Some(seconds),
+        Some(RawDuration::Text(text)) => parse_go_duration(&text),
+        None => None,
+    })
+}
+
+/// Same as [`deserialize_opt_seconds`], but numbers are milliseconds
+/// (as in Caddy's `*_ms` placeholders)
+pub fn deserialize_opt_millis<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<RawDuration>::deserialize(deserializer)? {
+        Some(RawDuration::Number(millis)) => Some(millis / 1_000.0),
+        Some(RawDuration::Text(text)) => match text.trim().parse::<f64>() {
+            Ok(millis) => Some(millis / 1_000.0),
+            Err(_) => parse_go_duration(&text),
+        },
+        None => None,
+    })
+}
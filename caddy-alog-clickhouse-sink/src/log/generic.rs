@@ -0,0 +1,40 @@
+use std::borrow::Cow;
+
+use derive_getters::{Dissolve, Getters};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::log::raw::RawValue;
+
+/// Entry of any other Caddy logger: errors of the handlers (`http.log.error`,
+/// `http.handlers.reverse_proxy`), TLS/ACME events (`tls.obtain`), admin API (`admin.api`), etc.
+///
+/// Their fields differ by logger, so everything except the common ones is kept as JSON.
+/// Owned, since these are rare compared to the access log.
+#[derive(Deserialize, Dissolve, Getters, Debug)]
+pub struct CaddyLogEntry {
+    level: String,
+    /// Encoded according to Caddy's `time_format`
+    #[serde(rename = "ts")]
+    timestamp: Value,
+    /// Missing for the default logger
+    #[serde(default)]
+    logger: String,
+    #[serde(default, rename = "msg")]
+    message: String,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+impl CaddyLogEntry {
+    pub fn raw_timestamp(&self) -> RawValue<'_> {
+        match &self.timestamp {
+            Value::Number(number) => match number.as_i64() {
+                Some(integer) => RawValue::Integer(integer),
+                None => RawValue::Float(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(text) => RawValue::Text(Cow::Borrowed(text)),
+            other => RawValue::Text(Cow::Owned(other.to_string())),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use klickhouse::{unexpected_type, FromSql, ToSql, Type, Value};
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Headers of a parsed entry, borrowed from the line where possible.
+///
+/// Kept as a list in the logged order: there are only a few lookups per entry,
+/// so hashing every name would cost more than it saves.
+#[derive(Default, Debug)]
+pub struct Headers<'a>(Vec<(Cow<'a, str>, Vec<Cow<'a, str>>)>);
+
+impl<'a> Headers<'a> {
+    /// First value of the header, header names are matched case-insensitively
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first())
+            .map(AsRef::as_ref)
+    }
+
+    /// Removes the header, returning all its values joined with `, `,
+    /// header names are matched case-insensitively
+    pub fn take(&mut self, name: &str) -> Option<String> {
+        let mut taken: Option<String> = None;
+        self.0.retain_mut(|(key, values)| {
+            if !key.eq_ignore_ascii_case(name) {
+                return true;
+            }
+
+            for value in values.drain(..) {
+                match &mut taken {
+                    Some(joined) => {
+                        joined.push_str(", ");
+                        joined.push_str(&value);
+                    }
+                    None => taken = Some(value.into_owned()),
+                }
+            }
+            false
+        });
+
+        taken
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Headers<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HeadersVisitor<'a>(PhantomData<Headers<'a>>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for HeadersVisitor<'a> {
+            type Value = Headers<'a>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of header names to lists of values")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut headers = Vec::with_capacity(map.size_hint().unwrap_or(16));
+                while let Some((name, values)) =
+                    map.next_entry::<BorrowedStr, Vec<BorrowedStr>>()?
+                {
+                    headers.push((name.0, values.into_iter().map(|value| value.0).collect()));
+                }
+
+                Ok(Headers(headers))
+            }
+        }
+
+        deserializer.deserialize_map(HeadersVisitor(PhantomData))
+    }
+}
+
+/// String, borrowed from the input, unless it has escapes
+/// (a plain `Cow<str>` is always deserialized as owned, unless used as a `#[serde(borrow)]` field)
+struct BorrowedStr<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for BorrowedStr<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper<'a>(#[serde(borrow)] Cow<'a, str>);
+
+        Wrapper::deserialize(deserializer).map(|wrapper| Self(wrapper.0))
+    }
+}
+
+/// Owned headers, stored as a `Map(String, Array(String))` column
+#[derive(Clone, Default, Debug)]
+pub struct HeaderMap(Vec<(String, Vec<String>)>);
+
+impl HeaderMap {
+    /// First value of the header, header names are matched case-insensitively
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first())
+            .map(String::as_str)
+    }
+}
+
+impl From<Headers<'_>> for HeaderMap {
+    fn from(headers: Headers<'_>) -> Self {
+        Self(
+            headers
+                .0
+                .into_iter()
+                .map(|(name, values)| {
+                    (
+                        name.into_owned(),
+                        values.into_iter().map(Cow::into_owned).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToSql for HeaderMap {
+    fn to_sql(self, type_hint: Option<&Type>) -> klickhouse::Result<Value> {
+        let type_hint = type_hint.and_then(Type::unmap);
+        let mut names = Vec::with_capacity(self.0.len());
+        let mut values = Vec::with_capacity(self.0.len());
+        for (name, header_values) in self.0 {
+            names.push(Value::String(name.into_bytes()));
+            values.push(header_values.to_sql(type_hint.map(|(_, values)| values))?);
+        }
+
+        Ok(Value::Map(names, values))
+    }
+}
+
+impl FromSql for HeaderMap {
+    fn from_sql(type_: &Type, value: Value) -> klickhouse::Result<Self> {
+        let Type::Map(name_type, values_type) = type_ else {
+            return Err(unexpected_type(type_));
+        };
+        let Value::Map(names, values) = value else {
+            return Err(unexpected_type(type_));
+        };
+
+        names
+            .into_iter()
+            .zip(values)
+            .map(|(name, header_values)| {
+                Ok((
+                    String::from_sql(name_type.strip_low_cardinality(), name)?,
+                    Vec::from_sql(values_type, header_values)?,
+                ))
+            })
+            .collect::<klickhouse::Result<_>>()
+            .map(Self)
+    }
+}
+
+impl Serialize for HeaderMap {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, values) in &self.0 {
+            map.serialize_entry(name, values)?;
+        }
+
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for HeaderMap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Headers::deserialize(deserializer).map(Self::from)
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::log::headers::Headers;
+
+/// Prefix of the columns with promoted request headers
+pub const REQUEST_PREFIX: &str = "header_";
+/// Prefix of the columns with promoted response headers
+pub const RESPONSE_PREFIX: &str = "response_header_";
+
+/// Column name part for the header: lowercased, with everything but letters and digits
+/// replaced by underscores, e.g. `User-Agent` becomes `user_agent`
+pub fn column_suffix(header: &str) -> String {
+    header
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Headers, stored in their own columns instead of the headers maps.
+///
+/// The set of columns depends on the config, missing headers are stored as empty strings.
+#[derive(Clone, Default, Debug)]
+pub struct PromotedHeaders(Vec<(String, String)>);
+
+impl PromotedHeaders {
+    /// Moves the configured headers out of the maps
+    pub fn extract<'a>(
+        request_headers: &mut Headers<'a>,
+        request_names: &[String],
+        response_headers: &mut Headers<'a>,
+        response_names: &[String],
+    ) -> Self {
+        let mut columns = Vec::with_capacity(request_names.len() + response_names.len());
+        for (headers, names, prefix) in [
+            (request_headers, request_names, REQUEST_PREFIX),
+            (response_headers, response_names, RESPONSE_PREFIX),
+        ] {
+            for name in names {
+                let value = headers.take(name).unwrap_or_default();
+                columns.push((format!("{prefix}{}", column_suffix(name)), value));
+            }
+        }
+
+        Self(columns)
+    }
+
+    pub fn into_columns(self) -> impl Iterator<Item = (String, String)> {
+        self.0.into_iter()
+    }
+}
+
+impl FromIterator<(String, String)> for PromotedHeaders {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Serialize for PromotedHeaders {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (column, value) in &self.0 {
+            map.serialize_entry(column, value)?;
+        }
+
+        map.end()
+    }
+}
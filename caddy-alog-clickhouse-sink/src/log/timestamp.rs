@@ -0,0 +1,112 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use time::{
+    format_description::well_known::{Iso8601, Rfc3339},
+    macros::format_description,
+    OffsetDateTime, PrimitiveDateTime,
+};
+
+use crate::log::raw::RawValue;
+
+/// 2000-01-01, earlier timestamps are considered garbage (e.g. of devices without a real-time clock)
+const MIN_TIMESTAMP_MICROS: i64 = 946_684_800_000_000;
+/// How far an entry may be logged in the future, e.g. because of a clock skew
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(24 * 3_600);
+
+/// Caddy's `time_format` of the log encoder.
+///
+/// Only matters for numeric timestamps, strings are parsed in any of the supported formats.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// Unit of numbers is guessed by their magnitude
+    #[default]
+    Auto,
+    UnixSecondsFloat,
+    UnixMilliFloat,
+    UnixNano,
+    /// Any of the string formats: `iso8601`, `rfc3339(_nano)`, `wall(_milli|_nano)`
+    /// (taken as UTC) or `common_log`
+    #[serde(
+        alias = "iso8601",
+        alias = "rfc3339",
+        alias = "rfc3339_nano",
+        alias = "wall",
+        alias = "wall_milli",
+        alias = "wall_nano",
+        alias = "common_log"
+    )]
+    String,
+}
+
+impl TimeFormat {
+    /// Microseconds in a unit of the numeric timestamp
+    fn micros_per_unit(self, value: f64) -> f64 {
+        match self {
+            Self::UnixSecondsFloat => 1e6,
+            Self::UnixMilliFloat => 1e3,
+            Self::UnixNano => 1e-3,
+            // seconds, milliseconds, microseconds or nanoseconds of a date after 1973
+            Self::Auto | Self::String => match value.abs() {
+                value if value < 1e11 => 1e6,
+                value if value < 1e14 => 1e3,
+                value if value < 1e17 => 1.0,
+                _ => 1e-3,
+            },
+        }
+    }
+}
+
+/// Microseconds since the unix epoch, `None` if the value isn't a valid timestamp
+pub fn parse_timestamp(value: &RawValue<'_>, format: TimeFormat) -> Option<i64> {
+    match value {
+        RawValue::Integer(value) => match format.micros_per_unit(*value as f64) {
+            // exact for nanoseconds, which don't fit into a float
+            micros if micros < 1.0 => Some(value / 1_000),
+            micros => value.checked_mul(micros as i64),
+        },
+        RawValue::Float(value) => from_float(*value, format),
+        RawValue::Text(text) => {
+            let text = text.trim();
+            match text.parse::<f64>() {
+                Ok(value) => from_float(value, format),
+                Err(_) => parse_datetime(text),
+            }
+        }
+    }
+}
+
+/// Whether an entry received at `ingested_at` may have been logged at the timestamp
+/// (in microseconds since the unix epoch)
+pub fn is_plausible(micros: i64, ingested_at: SystemTime) -> bool {
+    let max_timestamp = (ingested_at + MAX_CLOCK_SKEW)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64;
+
+    (MIN_TIMESTAMP_MICROS..=max_timestamp).contains(&micros)
+}
+
+fn from_float(value: f64, format: TimeFormat) -> Option<i64> {
+    let micros = (value * format.micros_per_unit(value)).round();
+
+    (micros.is_finite() && micros.abs() < i64::MAX as f64).then_some(micros as i64)
+}
+
+fn parse_datetime(text: &str) -> Option<i64> {
+    let wall = format_description!(
+        "[year]/[month]/[day] [hour]:[minute]:[second][optional [.[subsecond]]]"
+    );
+    let common_log = format_description!(
+        "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+    );
+
+    let datetime = OffsetDateTime::parse(text, &Rfc3339)
+        .or_else(|_| OffsetDateTime::parse(text, &Iso8601::DEFAULT))
+        .or_else(|_| OffsetDateTime::parse(text, common_log))
+        .or_else(|_| PrimitiveDateTime::parse(text, wall).map(PrimitiveDateTime::assume_utc))
+        .ok()?;
+
+    i64::try_from(datetime.unix_timestamp_nanos() / 1_000).ok()
+}
@@ -0,0 +1,97 @@
+use derive_getters::Dissolve;
+
+use crate::{
+    config::Config,
+    log::{headers::Headers, AccessLogEntry},
+};
+
+/// Trace context, propagated with the request
+#[derive(Dissolve, Default, Debug)]
+pub struct TraceContext {
+    /// W3C trace id, 32 lowercase hex digits for `traceparent`-based ids
+    trace_id: Option<String>,
+    /// W3C parent span id, 16 lowercase hex digits for `traceparent`-based ids
+    span_id: Option<String>,
+    /// Vendor-specific `tracestate`, as is
+    trace_state: Option<String>,
+    /// Request id, as set by the client or the upstream
+    request_id: Option<String>,
+}
+
+impl TraceContext {
+    /// Collects the trace context from the request headers and, if it's missing there,
+    /// from the response ones (for traces started by the upstream).
+    ///
+    /// `traceparent` (or `traceresponse`) takes precedence over the configured alternatives.
+    pub fn extract(entry: &AccessLogEntry<'_>, config: &Config) -> Self {
+        let all_headers = [entry.request().headers(), entry.response_headers()];
+
+        let (trace_id, span_id, trace_state) = all_headers
+            .iter()
+            .find_map(|headers| {
+                let traceparent = headers
+                    .get("traceparent")
+                    .or_else(|| headers.get("traceresponse"))?;
+                let (trace_id, span_id) = parse_traceparent(traceparent)?;
+                let trace_state = headers.get("tracestate").map(ToString::to_string);
+
+                Some((Some(trace_id), Some(span_id), trace_state))
+            })
+            .unwrap_or_else(|| {
+                (
+                    first_header(&all_headers, config.trace_id_headers())
+                        .map(|trace_id| trace_id.to_ascii_lowercase()),
+                    first_header(&all_headers, config.span_id_headers())
+                        .map(|span_id| span_id.to_ascii_lowercase()),
+                    None,
+                )
+            });
+        let request_id = first_header(&all_headers, config.request_id_headers());
+
+        Self {
+            trace_id,
+            span_id,
+            trace_state,
+            request_id,
+        }
+    }
+}
+
+/// First non-empty value of any of the headers, trimmed
+fn first_header(all_headers: &[&Headers<'_>], names: &[String]) -> Option<String> {
+    all_headers.iter().find_map(|headers| {
+        names
+            .iter()
+            .filter_map(|name| headers.get(name))
+            .map(str::trim)
+            .find(|value| !value.is_empty())
+            .map(ToString::to_string)
+    })
+}
+
+/// Parses `version-trace_id-parent_id-flags` into trace and span ids,
+/// rejecting the invalid ones as per the W3C Trace Context spec
+fn parse_traceparent(value: &str) -> Option<(String, String)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let flags = parts.next()?;
+
+    let is_hex =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_zero = |part: &str| part.bytes().all(|b| b == b'0');
+
+    if !is_hex(version, 2) || version.eq_ignore_ascii_case("ff") || !is_hex(flags, 2) {
+        return None;
+    }
+    // version 00 has exactly four parts, future versions may append more
+    if version == "00" && parts.next().is_some() {
+        return None;
+    }
+    if !is_hex(trace_id, 32) || is_zero(trace_id) || !is_hex(span_id, 16) || is_zero(span_id) {
+        return None;
+    }
+
+    Some((trace_id.to_ascii_lowercase(), span_id.to_ascii_lowercase()))
+}
@@ -0,0 +1,96 @@
+use derive_getters::Dissolve;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    config::Config,
+    log::{duration::parse_go_duration, AccessLogEntry},
+};
+
+/// Information about the reverse proxy upstream, which handled the request
+#[derive(Dissolve, Default, Debug)]
+pub struct Upstream {
+    /// Address of the upstream
+    addr: Option<String>,
+    /// Status, returned by the upstream
+    status: Option<u16>,
+    /// Time the upstream took to respond, in seconds
+    latency: Option<f64>,
+}
+
+impl Upstream {
+    /// Collects upstream info from the fields added with Caddy's `log_append`
+    /// and, if those are missing, from the configured response headers
+    pub fn extract(entry: &AccessLogEntry<'_>, config: &Config) -> Self {
+        let response_headers = entry.response_headers();
+        let from_header =
+            |name: &Option<String>| name.as_deref().and_then(|name| response_headers.get(name));
+
+        let addr = entry
+            .upstream_addr()
+            .as_deref()
+            .map(ToString::to_string)
+            .or_else(|| from_header(config.upstream_addr_header()).map(ToString::to_string));
+        let status = (*entry.upstream_status()).or_else(|| {
+            from_header(config.upstream_status_header()).and_then(|value| value.trim().parse().ok())
+        });
+        let latency = (*entry.upstream_latency())
+            .or(*entry.upstream_latency_ms())
+            .or_else(|| from_header(config.upstream_latency_header()).and_then(parse_go_duration))
+            .or_else(|| {
+                let metric = config.upstream_server_timing_metric().as_deref()?;
+
+                server_timing_duration(response_headers.get("Server-Timing")?, metric)
+            });
+
+        Self {
+            addr,
+            status,
+            latency,
+        }
+    }
+}
+
+/// Finds the `dur` parameter of the given metric in a `Server-Timing` header
+/// and converts it from milliseconds to seconds
+fn server_timing_duration(header: &str, metric: &str) -> Option<f64> {
+    header
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            if !params.next()?.eq_ignore_ascii_case(metric) {
+                return None;
+            }
+
+            params.find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                if !key.trim().eq_ignore_ascii_case("dur") {
+                    return None;
+                }
+
+                value.trim().trim_matches('"').parse::<f64>().ok()
+            })
+        })
+        .next()
+        .map(|millis| millis / 1_000.0)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawStatus {
+    Number(u16),
+    Text(String),
+}
+
+/// Deserializes a status code, given either as a number or as a string.
+///
+/// Unparseable values (e.g. an empty placeholder) are treated as missing.
+pub fn deserialize_opt_status<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<RawStatus>::deserialize(deserializer)? {
+        Some(RawStatus::Number(status)) => Some(status),
+        Some(RawStatus::Text(text)) => text.trim().parse().ok(),
+        None => None,
+    })
+}
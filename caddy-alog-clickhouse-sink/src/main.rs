@@ -1,41 +1,149 @@
 use std::sync::Arc;
 
+use clap::{Parser, Subcommand};
 use eyre::{Result, WrapErr};
-use tokio::net::TcpListener;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
-use tracing_tree::HierarchicalLayer;
+use tokio::{
+    net::TcpListener,
+    signal::unix::{signal, SignalKind},
+};
+use tracing::{error, info};
 
-mod app_state;
-mod config;
-mod handlers;
-mod log;
+use caddy_alog_clickhouse_sink::{
+    admin,
+    app_state::AppState,
+    config::Config,
+    docker, handlers, http, metrics, reload,
+    report::{self, ReportArgs},
+    schema::{self, SchemaArgs},
+    syslog, telemetry,
+};
 
-use crate::config::Config;
+/// Receives Caddy access logs over TCP and stores them in Clickhouse
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the sink (default)
+    Run,
+    Report(ReportArgs),
+    Schema(SchemaArgs),
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     dotenvy::dotenv().ok();
-    let config = Config::from_env()?;
-
-    // enable tracing
-    Registry::default()
-        .with(EnvFilter::from_default_env())
-        .with(
-            HierarchicalLayer::new(4)
-                .with_targets(true)
-                .with_indent_lines(true)
-                .with_bracketed_fields(true)
-                .with_thread_names(false)
-                .with_thread_ids(true),
-        )
-        .init();
-
-    let app_state = Arc::new(app_state::AppState::new(config.clone()).await?);
-
-    let listener = TcpListener::bind(config.bind_to())
+    let config = Config::load()?;
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(config).await,
+        Command::Report(args) => report::run(config, args).await,
+        Command::Schema(args) => schema::run(config, args).await,
+    }
+}
+
+async fn run(config: Config) -> Result<()> {
+    telemetry::init(&config)?;
+    // installs the recorder before anything is recorded
+    metrics::handle();
+
+    let app_state = Arc::new(AppState::new(config.clone()).await?);
+    schema::check_on_startup(app_state.clickhouse(), &config).await?;
+
+    {
+        let app_state = Arc::clone(&app_state);
+        tokio::spawn(async move { app_state.abuse().run().await });
+    }
+    {
+        let app_state = Arc::clone(&app_state);
+        tokio::spawn(async move { app_state.alerts().run().await });
+    }
+    tokio::spawn(reload::run(Arc::clone(&app_state)));
+
+    if let Some(http_bind_to) = config.http_bind_to().clone() {
+        let app_state = Arc::clone(&app_state);
+
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(app_state, &http_bind_to).await {
+                error!("{:?}", e);
+            }
+        });
+    }
+
+    if let Some(admin_bind_to) = config.admin_bind_to().clone() {
+        let app_state = Arc::clone(&app_state);
+
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(app_state, &admin_bind_to).await {
+                error!("{:?}", e);
+            }
+        });
+    }
+
+    if let Some(syslog_bind_to) = config.syslog_bind_to().clone() {
+        let app_state = Arc::clone(&app_state);
+
+        tokio::spawn(async move {
+            if let Err(e) = syslog::serve(app_state, &syslog_bind_to).await {
+                error!("{:?}", e);
+            }
+        });
+    }
+
+    if !config.docker_log_paths().is_empty() {
+        let app_state = Arc::clone(&app_state);
+
+        tokio::spawn(async move {
+            if let Err(e) = docker::run(app_state).await {
+                error!("{:?}", e);
+            }
+        });
+    }
+
+    let result = tokio::select! {
+        result = accept(app_state, config.bind_to()) => result,
+        () = shutdown_signal() => Ok(()),
+    };
+    telemetry::shutdown();
+
+    result
+}
+
+/// Waits for SIGTERM or Ctrl+C, a signal, which can't be listened for, never comes
+async fn shutdown_signal() {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        () = terminate => info!("Got SIGTERM, shutting down"),
+        () = ctrl_c => info!("Got Ctrl+C, shutting down"),
+    }
+}
+
+async fn accept(app_state: Arc<AppState>, bind_to: &str) -> Result<()> {
+    let listener = TcpListener::bind(bind_to)
         .await
-        .wrap_err_with(|| format!("Failed to bind to address {}", config.bind_to()))?;
+        .wrap_err_with(|| format!("Failed to bind to address {}", bind_to))?;
 
     loop {
         let (socket, peer) = listener.accept().await?;
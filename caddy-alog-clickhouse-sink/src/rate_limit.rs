@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// What happens to the lines over the rate limits
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitAction {
+    /// Reading from the connection waits, so that TCP flow control slows the sender down
+    #[default]
+    Backpressure,
+    /// Lines are read and dropped
+    Drop,
+}
+
+/// Limits per second, unlimited if not set
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Rates {
+    pub lines: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+/// Which limit was exceeded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scope {
+    Peer,
+    Global,
+}
+
+impl Scope {
+    /// Label of the scope in the metrics
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Peer => "peer",
+            Self::Global => "global",
+        }
+    }
+}
+
+/// Decision on a line (or discarded bytes) read from a connection
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Pass,
+    /// Reading on has to wait this long (backpressure)
+    Wait(Duration, Scope),
+    /// The line is to be dropped
+    Drop(Scope),
+}
+
+/// Token bucket holding up to a second worth of its rate.
+///
+/// It may go into debt, so that a line larger than the bucket still passes
+/// (and holds back the following ones).
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    /// Time until the debt is paid off
+    fn debt(&self) -> Duration {
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Line and byte buckets of a peer, or of all of them
+struct Limiter {
+    lines: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Limiter {
+    fn new(rates: Rates, now: Instant) -> Self {
+        Self {
+            lines: rates.lines.map(|rate| TokenBucket::new(rate, now)),
+            bytes: rates.bytes.map(|rate| TokenBucket::new(rate, now)),
+        }
+    }
+
+    /// Replaces the buckets, which rates changed
+    fn set_rates(&mut self, rates: Rates, now: Instant) {
+        for (bucket, rate) in [
+            (&mut self.lines, rates.lines),
+            (&mut self.bytes, rates.bytes),
+        ] {
+            if bucket.as_ref().map(|bucket| bucket.rate as u64) != rate {
+                *bucket = rate.map(|rate| TokenBucket::new(rate, now));
+            }
+        }
+    }
+
+    fn buckets(&mut self) -> impl Iterator<Item = &mut TokenBucket> {
+        self.lines.iter_mut().chain(self.bytes.iter_mut())
+    }
+
+    /// Whether any of the buckets has less than a token left after refilling them
+    fn is_exhausted(&mut self, now: Instant) -> bool {
+        self.buckets().fold(false, |exhausted, bucket| {
+            bucket.refill(now);
+            exhausted || bucket.tokens < 1.0
+        })
+    }
+
+    /// Takes the tokens, returns how long until the buckets are out of debt
+    fn take(&mut self, lines: u64, bytes: u64, now: Instant) -> Duration {
+        for (bucket, amount) in [(&mut self.lines, lines), (&mut self.bytes, bytes)] {
+            if let Some(bucket) = bucket {
+                bucket.refill(now);
+                bucket.tokens -= amount as f64;
+            }
+        }
+
+        self.buckets()
+            .map(|bucket| bucket.debt())
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+struct State {
+    action: RateLimitAction,
+    peer_rates: Rates,
+    global_rates: Rates,
+    global: Limiter,
+    /// Shared by the connections of the peer, removed after the last one is closed
+    peers: HashMap<IpAddr, Weak<Mutex<Limiter>>>,
+}
+
+/// Token bucket rate limits of the lines and bytes read from the log connections,
+/// per peer IP (shared by all its connections) and for all the peers together
+pub struct RateLimiter {
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        let global_rates = global_rates(config);
+
+        Self {
+            state: Mutex::new(State {
+                action: *config.rate_limit_action(),
+                peer_rates: peer_rates(config),
+                global_rates,
+                global: Limiter::new(global_rates, Instant::now()),
+                peers: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn reload(&self, config: &Config) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        state.action = *config.rate_limit_action();
+        state.peer_rates = peer_rates(config);
+        state.global_rates = global_rates(config);
+        let (peer_rates, global_rates) = (state.peer_rates, state.global_rates);
+        state.global.set_rates(global_rates, now);
+        for peer in state.peers.values().filter_map(Weak::upgrade) {
+            peer.lock().unwrap().set_rates(peer_rates, now);
+        }
+    }
+
+    /// Current action, per peer and global rates
+    pub fn limits(&self) -> (RateLimitAction, Rates, Rates) {
+        let state = self.state.lock().unwrap();
+
+        (state.action, state.peer_rates, state.global_rates)
+    }
+
+    /// Limiter of a connection from the peer
+    pub fn connection(&self, peer: IpAddr) -> ConnectionLimiter<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.peers.retain(|_, limiter| limiter.strong_count() > 0);
+
+        let peer_rates = state.peer_rates;
+        let limiter = match state.peers.get(&peer).and_then(Weak::upgrade) {
+            Some(limiter) => limiter,
+            None => {
+                let limiter = Arc::new(Mutex::new(Limiter::new(peer_rates, Instant::now())));
+                state.peers.insert(peer, Arc::downgrade(&limiter));
+                limiter
+            }
+        };
+
+        ConnectionLimiter {
+            rate_limiter: self,
+            peer: limiter,
+        }
+    }
+}
+
+fn peer_rates(config: &Config) -> Rates {
+    Rates {
+        lines: *config.peer_max_lines_per_sec(),
+        bytes: *config.peer_max_bytes_per_sec(),
+    }
+}
+
+fn global_rates(config: &Config) -> Rates {
+    Rates {
+        lines: *config.max_lines_per_sec(),
+        bytes: *config.max_bytes_per_sec(),
+    }
+}
+
+/// Rate limits applying to a connection: its peer's and the global ones
+pub struct ConnectionLimiter<'a> {
+    rate_limiter: &'a RateLimiter,
+    peer: Arc<Mutex<Limiter>>,
+}
+
+impl ConnectionLimiter<'_> {
+    /// Accounts for a line of `bytes`, or for discarded bytes if `line` is false
+    /// (those are never dropped, but may still have to wait)
+    pub fn admit(&self, line: bool, bytes: usize) -> Verdict {
+        let now = Instant::now();
+        let mut state = self.rate_limiter.state.lock().unwrap();
+        let mut peer = self.peer.lock().unwrap();
+
+        if state.action == RateLimitAction::Drop && line {
+            if peer.is_exhausted(now) {
+                return Verdict::Drop(Scope::Peer);
+            }
+            if state.global.is_exhausted(now) {
+                return Verdict::Drop(Scope::Global);
+            }
+        }
+
+        let (lines, bytes) = (u64::from(line), bytes as u64);
+        let peer_wait = peer.take(lines, bytes, now);
+        let global_wait = state.global.take(lines, bytes, now);
+
+        match state.action {
+            RateLimitAction::Drop => Verdict::Pass,
+            RateLimitAction::Backpressure if peer_wait.is_zero() && global_wait.is_zero() => {
+                Verdict::Pass
+            }
+            RateLimitAction::Backpressure if peer_wait >= global_wait => {
+                Verdict::Wait(peer_wait, Scope::Peer)
+            }
+            RateLimitAction::Backpressure => Verdict::Wait(global_wait, Scope::Global),
+        }
+    }
+}
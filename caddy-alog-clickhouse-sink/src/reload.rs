@@ -0,0 +1,66 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+use crate::{app_state::AppState, config::Config};
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn modified_at(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Reloads the configuration on SIGHUP and whenever the config file changes
+pub async fn run(app_state: Arc<AppState>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!(
+                "Failed to listen for SIGHUP, configuration reload is disabled: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    let path = app_state.config().config_path().clone();
+    let mut modified = match &path {
+        Some(path) => modified_at(path).await,
+        None => None,
+    };
+    let mut watch = tokio::time::interval(WATCH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => info!("Got SIGHUP, reloading configuration"),
+            _ = watch.tick(), if path.is_some() => {
+                let path = path.as_deref().expect("checked in the select guard");
+                let current = modified_at(path).await;
+                if current == modified {
+                    continue;
+                }
+
+                modified = current;
+                info!("Config file {} changed, reloading configuration", path.display());
+            }
+        }
+
+        let reloaded = match Config::load() {
+            Ok(config) => app_state.reload(config).await,
+            Err(e) => Err(e),
+        };
+        match reloaded {
+            Ok(()) => info!("Configuration reloaded"),
+            Err(e) => error!(
+                "Failed to reload configuration, keeping the current one: {:?}",
+                e
+            ),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::Write;
+
+use clap::{Args, ValueEnum};
+use eyre::{eyre, Result, WrapErr};
+use klickhouse::QueryBuilder;
+
+use crate::{clickhouse::ChCluster, config::Config, log::duration::parse_go_duration, routes};
+
+mod output;
+
+use self::output::{OutputFormat, ReportRow};
+
+/// Condition shared by all the reports, `{from}` and `{to}` are replaced by [`ReportArgs::range`].
+///
+/// The reports read `{source}`, the access log tables of all the routes (see [`routes::source`]).
+///
+/// Arguments: `$1` start, `$2` end, `$3` service, `$4` environment, `$5` host (all if empty),
+/// `$6` limit, `$7` minimal number of requests.
+const FILTER: &str = "logger_timestamp >= {from} AND logger_timestamp < {to} \
+     AND service = $3 AND environment = $4 AND ($5 = '' OR host = $5)";
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ReportKind {
+    /// Hosts with the most requests
+    Hosts,
+    /// Paths (without the query string) with the most requests
+    Paths,
+    /// Number and share of the requests per response status
+    Statuses,
+    /// Endpoints with the highest 95th percentile of the request duration
+    Slowest,
+    /// Client IPs with the most requests
+    Ips,
+    /// Bytes sent and received per host
+    Bandwidth,
+}
+
+impl ReportKind {
+    fn query(self) -> &'static str {
+        match self {
+            Self::Hosts => {
+                "SELECT host, count() AS requests, \
+                 countIf(status >= 400 AND status < 500) AS status_4xx, \
+                 countIf(status >= 500) AS status_5xx, round(avg(duration), 4) AS avg_duration \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY host ORDER BY requests DESC LIMIT $6"
+            }
+            Self::Paths => {
+                "SELECT host, splitByChar('?', uri)[1] AS path, count() AS requests, \
+                 countIf(status >= 500) AS status_5xx \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY host, path ORDER BY requests DESC LIMIT $6"
+            }
+            Self::Statuses => {
+                "SELECT status, count() AS requests, \
+                 round(requests / sum(requests) OVER (), 4) AS share \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY status ORDER BY status"
+            }
+            Self::Slowest => {
+                "SELECT host, splitByChar('?', uri)[1] AS path, count() AS requests, \
+                 round(quantile(0.5)(duration), 4) AS p50_duration, \
+                 round(quantile(0.95)(duration), 4) AS p95_duration, \
+                 round(max(duration), 4) AS max_duration \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY host, path HAVING requests >= $7 \
+                 ORDER BY p95_duration DESC LIMIT $6"
+            }
+            Self::Ips => {
+                // the address of `RequestInfo::client_addr`
+                "SELECT coalesce(client_ip, remote_ip) AS ip, count() AS requests, \
+                 countIf(status >= 400) AS errors, uniqExact(host) AS hosts \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY ip ORDER BY requests DESC LIMIT $6"
+            }
+            Self::Bandwidth => {
+                "SELECT host, count() AS requests, \
+                 sum(size) AS bytes_sent, formatReadableSize(bytes_sent) AS sent, \
+                 sum(bytes_read) AS bytes_received, formatReadableSize(bytes_received) AS received \
+                 FROM {source} WHERE {filter} \
+                 GROUP BY host ORDER BY bytes_sent DESC LIMIT $6"
+            }
+        }
+    }
+}
+
+/// Prints a common analysis of the access log for a time range
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    #[arg(value_enum)]
+    kind: ReportKind,
+    /// Start of the range relative to now, as a Go duration (e.g. `90m`)
+    #[arg(long, default_value = "1h")]
+    since: String,
+    /// Start of the range as a date and time (e.g. `2024-04-05 10:00:00`), overrides `--since`
+    #[arg(long)]
+    from: Option<String>,
+    /// End of the range as a date and time, now if not set
+    #[arg(long)]
+    to: Option<String>,
+    /// Service to report on, SERVICE_NAME if not set
+    #[arg(long)]
+    service: Option<String>,
+    /// Environment to report on, ENVIRONMENT if not set
+    #[arg(long)]
+    environment: Option<String>,
+    /// Only requests to this host
+    #[arg(long)]
+    host: Option<String>,
+    /// Maximum number of rows
+    #[arg(long, default_value_t = 20)]
+    limit: u64,
+    /// Minimal number of requests to an endpoint to be listed as slow
+    #[arg(long, default_value_t = 10)]
+    min_requests: u64,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+impl ReportArgs {
+    /// SQL expressions of the range bounds and their arguments
+    fn range(&self) -> Result<(&'static str, &'static str, String, String)> {
+        // dates are parsed by Clickhouse, so that it accepts the same formats as its clients
+        let (from_sql, from) = match &self.from {
+            Some(from) => ("parseDateTime64BestEffort($1, 3, 'UTC')", from.clone()),
+            None => {
+                let since = parse_go_duration(&self.since)
+                    .filter(|since| *since > 0.0)
+                    .ok_or_else(|| eyre!("Invalid duration: {}", self.since))?;
+
+                (
+                    "now64(3) - toIntervalMillisecond(toUInt64($1))",
+                    ((since * 1_000.0) as u64).to_string(),
+                )
+            }
+        };
+        let (to_sql, to) = match &self.to {
+            Some(to) => ("parseDateTime64BestEffort($2, 3, 'UTC')", to.clone()),
+            None => ("now64(3)", String::new()),
+        };
+
+        Ok((from_sql, to_sql, from, to))
+    }
+
+    fn query(&self, config: &Config) -> Result<String> {
+        let (from_sql, to_sql, from, to) = self.range()?;
+        let filter = FILTER.replace("{from}", from_sql).replace("{to}", to_sql);
+        let query = self
+            .kind
+            .query()
+            .replace("{source}", &routes::source(config.routes()))
+            .replace("{filter}", &filter);
+
+        let query = QueryBuilder::new(&query)
+            .arg(from)
+            .arg(to)
+            .arg(
+                self.service
+                    .as_ref()
+                    .unwrap_or(config.service_name())
+                    .as_str(),
+            )
+            .arg(
+                self.environment
+                    .as_ref()
+                    .unwrap_or(config.environment())
+                    .as_str(),
+            )
+            .arg(self.host.as_deref().unwrap_or_default())
+            .arg(self.limit)
+            .arg(self.min_requests)
+            .finalize()?;
+
+        Ok(query.to_string())
+    }
+}
+
+pub async fn run(config: Config, args: ReportArgs) -> Result<()> {
+    let query = args.query(&config)?;
+    let ch_cluster = ChCluster::new(&config).await?;
+
+    let rows = ch_cluster
+        .query::<ReportRow>(&query)
+        .await
+        .wrap_err("Failed to query the report")?;
+
+    let mut stdout = std::io::stdout().lock();
+    output::write(&mut stdout, args.format, &rows)?;
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, Write},
+};
+
+use clap::ValueEnum;
+use klickhouse::{IndexMap, KlickhouseError, Row, Type, Value};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    /// Aligned columns, for reading in a terminal
+    Table,
+    /// Comma-separated values with a header line
+    Csv,
+    /// Array of objects
+    Json,
+}
+
+/// Single value of a report, numbers are kept as numbers for the JSON output
+#[derive(Debug)]
+pub enum Cell {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl Cell {
+    fn is_numeric(&self) -> bool {
+        matches!(self, Self::Int(_) | Self::UInt(_) | Self::Float(_))
+    }
+}
+
+impl From<Value> for Cell {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Int8(x) => Self::Int(x.into()),
+            Value::Int16(x) => Self::Int(x.into()),
+            Value::Int32(x) => Self::Int(x.into()),
+            Value::Int64(x) => Self::Int(x),
+            Value::UInt8(x) => Self::UInt(x.into()),
+            Value::UInt16(x) => Self::UInt(x.into()),
+            Value::UInt32(x) => Self::UInt(x.into()),
+            Value::UInt64(x) => Self::UInt(x),
+            Value::Float32(x) => Self::Float(x.into()),
+            Value::Float64(x) => Self::Float(x),
+            Value::String(bytes) => Self::Text(String::from_utf8_lossy(&bytes).into_owned()),
+            other => Self::Text(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => Ok(()),
+            Self::Int(x) => write!(f, "{x}"),
+            Self::UInt(x) => write!(f, "{x}"),
+            Self::Float(x) => write!(f, "{x}"),
+            Self::Text(x) => f.write_str(x),
+        }
+    }
+}
+
+impl Serialize for Cell {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Null => serializer.serialize_none(),
+            Self::Int(x) => serializer.serialize_i64(*x),
+            Self::UInt(x) => serializer.serialize_u64(*x),
+            Self::Float(x) => serializer.serialize_f64(*x),
+            Self::Text(x) => serializer.serialize_str(x),
+        }
+    }
+}
+
+/// Row of any report, columns are kept in the selected order
+#[derive(Debug)]
+pub struct ReportRow(Vec<(String, Cell)>);
+
+impl Row for ReportRow {
+    const COLUMN_COUNT: Option<usize> = None;
+
+    fn column_names() -> Option<Vec<Cow<'static, str>>> {
+        None
+    }
+
+    fn deserialize_row(map: Vec<(&str, &Type, Value)>) -> klickhouse::Result<Self> {
+        Ok(Self(
+            map.into_iter()
+                .map(|(name, _, value)| (name.to_string(), Cell::from(value)))
+                .collect(),
+        ))
+    }
+
+    fn serialize_row(
+        self,
+        _type_hints: &IndexMap<String, Type>,
+    ) -> klickhouse::Result<Vec<(Cow<'static, str>, Value)>> {
+        Err(KlickhouseError::SerializeError(
+            "report rows are read-only".to_string(),
+        ))
+    }
+}
+
+impl Serialize for ReportRow {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (column, cell) in &self.0 {
+            map.serialize_entry(column, cell)?;
+        }
+
+        map.end()
+    }
+}
+
+pub fn write(out: &mut impl Write, format: OutputFormat, rows: &[ReportRow]) -> io::Result<()> {
+    match format {
+        OutputFormat::Table => write_table(out, rows),
+        OutputFormat::Csv => write_csv(out, rows),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows)?;
+            writeln!(out)
+        }
+    }
+}
+
+fn write_table(out: &mut impl Write, rows: &[ReportRow]) -> io::Result<()> {
+    let Some(first) = rows.first() else {
+        return writeln!(out, "(no requests in the range)");
+    };
+
+    let header = first
+        .0
+        .iter()
+        .map(|(column, _)| column.clone())
+        .collect::<Vec<_>>();
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.0
+                .iter()
+                .map(|(_, cell)| (cell.to_string(), cell.is_numeric()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header
+        .iter()
+        .map(|column| column.chars().count())
+        .collect::<Vec<_>>();
+    for row in &cells {
+        for (width, (text, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+
+    let line = header
+        .iter()
+        .zip(&widths)
+        .map(|(column, width)| format!("{column:<width$}"))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", line.join("  ").trim_end())?;
+    let rule = widths
+        .iter()
+        .map(|width| "-".repeat(*width))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", rule.join("  "))?;
+
+    for row in &cells {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|((text, is_numeric), width)| {
+                if *is_numeric {
+                    format!("{text:>width$}")
+                } else {
+                    format!("{text:<width$}")
+                }
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", line.join("  ").trim_end())?;
+    }
+
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, rows: &[ReportRow]) -> io::Result<()> {
+    let escape = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+
+    let Some(first) = rows.first() else {
+        return Ok(());
+    };
+    let header = first
+        .0
+        .iter()
+        .map(|(column, _)| escape(column))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", header.join(","))?;
+
+    for row in rows {
+        let line = row
+            .0
+            .iter()
+            .map(|(_, cell)| escape(&cell.to_string()))
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", line.join(","))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::{fmt, ops::RangeInclusive};
+
+use derive_getters::Getters;
+use eyre::{ensure, Result};
+use serde::Deserialize;
+
+use crate::log::db::DbAccessLogEntry;
+
+const DEFAULT_TABLE: &str = "access_log";
+
+fn default_table() -> String {
+    DEFAULT_TABLE.to_string()
+}
+
+/// Routing rule: access log entries matching all its conditions go to its table.
+///
+/// The table must have the columns of `access_log`,
+/// e.g. `CREATE TABLE client.access_log AS logs.access_log`. The reports and the dashboard
+/// read all the tables, the `visitors_daily` rollup only `access_log` (a route table needs
+/// its own materialized view).
+#[derive(Deserialize, Getters, Debug)]
+pub struct Route {
+    /// Hosts, `*.example.com` matches the subdomains, any if empty
+    #[serde(default)]
+    hosts: Vec<String>,
+    /// Services (SERVICE_NAME), any if empty
+    #[serde(default)]
+    services: Vec<String>,
+    /// Status codes (`404`), classes (`5xx`) or ranges (`400-499`), any if empty
+    #[serde(default)]
+    statuses: Vec<StatusMatcher>,
+    /// Database of the table, CH_DATABASE if not set
+    database: Option<String>,
+    #[serde(default = "default_table")]
+    table: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "String")]
+pub struct StatusMatcher(RangeInclusive<u16>);
+
+impl TryFrom<String> for StatusMatcher {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |status: &str| status.trim().parse::<u16>().ok();
+        let range = match (value.strip_suffix("xx"), value.split_once('-')) {
+            (Some(class), _) => parse(class)
+                .filter(|class| (1..=9).contains(class))
+                .map(|class| class * 100..=class * 100 + 99),
+            (None, Some((from, to))) => parse(from).zip(parse(to)).map(|(from, to)| from..=to),
+            (None, None) => parse(&value).map(|status| status..=status),
+        };
+
+        range
+            .filter(|range| !range.is_empty())
+            .map(Self)
+            .ok_or_else(|| format!("invalid status matcher {value:?}"))
+    }
+}
+
+impl Route {
+    fn matches(&self, entry: &DbAccessLogEntry) -> bool {
+        let host = strip_port(entry.host());
+
+        (self.hosts.is_empty() || self.hosts.iter().any(|pattern| host_matches(pattern, host)))
+            && (self.services.is_empty() || self.services.contains(entry.service()))
+            && (self.statuses.is_empty()
+                || self
+                    .statuses
+                    .iter()
+                    .any(|matcher| matcher.0.contains(entry.status())))
+    }
+
+    fn destination(&self) -> Destination<'_> {
+        Destination {
+            database: self.database.as_deref(),
+            table: &self.table,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for name in self.database.iter().chain([&self.table]) {
+            ensure!(
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'),
+                "Route database and table names must be alphanumeric, got {name:?}"
+            );
+        }
+        for pattern in &self.hosts {
+            ensure!(
+                !pattern
+                    .strip_prefix("*.")
+                    .unwrap_or(pattern)
+                    .contains(['*', ':']),
+                "Route hosts may only have a leading `*.` wildcard and no port, got {pattern:?}"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Host without the port, `[::1]:8443` is `[::1]`
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if port.bytes().all(|b| b.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.len().checked_sub(domain.len() + 1).is_some_and(|dot| {
+            host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(domain)
+        }),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Table the entries are inserted into
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Destination<'a> {
+    database: Option<&'a str>,
+    table: &'a str,
+}
+
+impl<'a> Destination<'a> {
+    /// Database of the table, CH_DATABASE if not set
+    pub fn database(&self) -> Option<&'a str> {
+        self.database
+    }
+
+    pub fn table(&self) -> &'a str {
+        self.table
+    }
+
+    pub fn insert_query(&self) -> String {
+        format!("INSERT INTO {self} SETTINGS async_insert=1, wait_for_async_insert=0 FORMAT NATIVE")
+    }
+}
+
+impl fmt::Display for Destination<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.database {
+            Some(database) => write!(f, "{database}.{}", self.table),
+            None => f.write_str(self.table),
+        }
+    }
+}
+
+/// Every table the entries may be inserted into: `access_log` and those of the routes
+pub fn destinations(routes: &[Route]) -> Vec<Destination<'_>> {
+    let mut destinations = vec![Destination {
+        database: None,
+        table: DEFAULT_TABLE,
+    }];
+    for destination in routes.iter().map(Route::destination) {
+        if !destinations.contains(&destination) {
+            destinations.push(destination);
+        }
+    }
+
+    destinations
+}
+
+/// Table expression of all the access log entries, wherever they were routed,
+/// e.g. `(SELECT * FROM access_log UNION ALL SELECT * FROM errors.access_log)`
+pub fn source(routes: &[Route]) -> String {
+    let destinations = destinations(routes);
+    if let [destination] = destinations.as_slice() {
+        return destination.to_string();
+    }
+
+    let selects = destinations
+        .iter()
+        .map(|destination| format!("SELECT * FROM {destination}"))
+        .collect::<Vec<_>>();
+
+    format!("({})", selects.join(" UNION ALL "))
+}
+
+/// Destination of the entry: the one of the first matching route, `access_log` by default
+pub fn route<'a>(routes: &'a [Route], entry: &DbAccessLogEntry) -> Destination<'a> {
+    routes.iter().find(|route| route.matches(entry)).map_or(
+        Destination {
+            database: None,
+            table: DEFAULT_TABLE,
+        },
+        Route::destination,
+    )
+}
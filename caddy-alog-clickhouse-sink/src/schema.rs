@@ -0,0 +1,504 @@
+use std::{collections::HashMap, fmt::Write as _, io::Write as _};
+
+use clap::{Args, Subcommand, ValueEnum};
+use displaydoc::Display;
+use eyre::{ensure, Result, WrapErr};
+use klickhouse::{DateTime64, QueryBuilder, Row, Uuid};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::{
+    clickhouse::ChCluster,
+    config::Config,
+    log::{
+        db::DbAccessLogEntry,
+        headers::HeaderMap,
+        promoted::{self, REQUEST_PREFIX, RESPONSE_PREFIX},
+    },
+    routes::{self, Destination},
+};
+
+/// Promoted headers (column suffixes), which are stored as plain `String`s,
+/// the others are `LowCardinality`
+const HIGH_CARDINALITY_HEADERS: &[&str] = &[
+    "referer",
+    "x_forwarded_for",
+    "forwarded",
+    "x_real_ip",
+    "cookie",
+    "set_cookie",
+    "location",
+    "etag",
+    "authorization",
+];
+
+const ZSTD: &str = "ZSTD(1)";
+const TIMESTAMP_CODEC: &str = "Delta, ZSTD(1)";
+const COUNTER_CODEC: &str = "T64, ZSTD(1)";
+
+/// What happens on startup, if the live tables differ from the expected schema
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaCheck {
+    /// The tables aren't checked
+    Off,
+    /// The differences are logged
+    #[default]
+    Warn,
+    /// The sink doesn't start
+    Strict,
+}
+
+/// Clickhouse type of the columns, which a Rust type is stored in
+pub trait ColumnType {
+    fn column_type() -> String;
+}
+
+macro_rules! column_types {
+    ($($rust:ty => $clickhouse:literal),* $(,)?) => {
+        $(impl ColumnType for $rust {
+            fn column_type() -> String {
+                $clickhouse.to_string()
+            }
+        })*
+    };
+}
+
+column_types! {
+    String => "String",
+    bool => "Bool",
+    u8 => "UInt8",
+    u16 => "UInt16",
+    u32 => "UInt32",
+    u64 => "UInt64",
+    f64 => "Float64",
+    Uuid => "UUID",
+    HeaderMap => "Map(String, Array(String))",
+}
+
+impl<T: ColumnType> ColumnType for Option<T> {
+    fn column_type() -> String {
+        format!("Nullable({})", T::column_type())
+    }
+}
+
+impl<const PRECISION: usize> ColumnType for DateTime64<PRECISION> {
+    fn column_type() -> String {
+        format!("DateTime64({PRECISION}, 'UTC')")
+    }
+}
+
+/// Column of a table, its type is formatted like in `system.columns`
+#[derive(Debug)]
+pub struct Column {
+    name: String,
+    type_: String,
+    codec: Option<&'static str>,
+    comment: String,
+}
+
+impl Column {
+    /// Column of the row field, which getter is given, so that its type follows the field's
+    fn of<T: ColumnType>(name: &str, _getter: fn(&DbAccessLogEntry) -> &T, comment: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            type_: T::column_type(),
+            codec: None,
+            comment: comment.to_string(),
+        }
+    }
+
+    fn low_cardinality(mut self) -> Self {
+        self.type_ = format!("LowCardinality({})", self.type_);
+        self
+    }
+
+    fn codec(mut self, codec: &'static str) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+}
+
+/// Expected schema of a table
+#[derive(Debug)]
+pub struct TableSchema {
+    columns: Vec<Column>,
+    /// Data skipping index definitions
+    indexes: &'static [&'static str],
+    partition_by: &'static str,
+    order_by: &'static str,
+}
+
+impl TableSchema {
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|column| column.name.as_str())
+    }
+
+    /// `CREATE TABLE` statement of the table
+    pub fn create_table(&self, table: &str) -> String {
+        let mut definitions = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut definition = format!("{} {}", column.name, column.type_);
+                if let Some(codec) = column.codec {
+                    write!(definition, " CODEC({codec})").unwrap();
+                }
+                write!(definition, " COMMENT {}", quote(&column.comment)).unwrap();
+                definition
+            })
+            .collect::<Vec<_>>();
+        definitions.extend(self.indexes.iter().map(|index| format!("INDEX {index}")));
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {table}\n(\n    {}\n)\nENGINE = MergeTree\n\
+             PARTITION BY {}\nORDER BY {};\n",
+            definitions.join(",\n    "),
+            self.partition_by,
+            self.order_by,
+        )
+    }
+
+    /// Markdown table documenting the columns
+    pub fn markdown(&self) -> String {
+        let mut markdown =
+            "| Column | Type | Codec | Description |\n|---|---|---|---|\n".to_string();
+        for column in &self.columns {
+            writeln!(
+                markdown,
+                "| `{}` | `{}` | {} | {} |",
+                column.name,
+                column.type_,
+                column
+                    .codec
+                    .map(|codec| format!("`{codec}`"))
+                    .unwrap_or_default(),
+                column.comment.replace('|', "\\|"),
+            )
+            .unwrap();
+        }
+
+        markdown
+    }
+
+    /// Differences of the live columns (name and type) from the expected ones,
+    /// extra live columns are fine as long as they have defaults
+    fn compare(&self, table: &str, live: &[LiveColumn]) -> Vec<SchemaMismatch> {
+        if live.is_empty() {
+            return vec![SchemaMismatch::Table(table.to_string())];
+        }
+
+        let live = live
+            .iter()
+            .map(|column| (column.name.as_str(), normalize(&column.type_)))
+            .collect::<HashMap<_, _>>();
+
+        self.columns
+            .iter()
+            .filter_map(|column| match live.get(column.name.as_str()) {
+                None => Some(SchemaMismatch::Missing {
+                    table: table.to_string(),
+                    column: column.name.clone(),
+                    expected: column.type_.clone(),
+                }),
+                Some(actual) if *actual != normalize(&column.type_) => Some(SchemaMismatch::Type {
+                    table: table.to_string(),
+                    column: column.name.clone(),
+                    expected: column.type_.clone(),
+                    actual: actual.clone(),
+                }),
+                Some(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// SQL string literal
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Type without the optional whitespace, e.g. after the commas
+fn normalize(type_: &str) -> String {
+    type_.split_whitespace().collect()
+}
+
+/// Schema of `access_log` (and of the route tables) with the configured promoted headers
+pub fn access_log(config: &Config) -> TableSchema {
+    use DbAccessLogEntry as E;
+
+    let mut columns = vec![
+        Column::of("id", E::id, "UUIDv7 of the entry, assigned by the sink"),
+        Column::of("service", E::service, "SERVICE_NAME of the sink").low_cardinality(),
+        Column::of("environment", E::environment, "ENVIRONMENT of the sink").low_cardinality(),
+        Column::of(
+            "sink_instance",
+            E::sink_instance,
+            "INSTANCE_ID of the receiving sink",
+        )
+        .low_cardinality(),
+        Column::of(
+            "ingested_at",
+            E::ingested_at,
+            "When the sink received the entry",
+        )
+        .codec(TIMESTAMP_CODEC),
+        Column::of("level", E::level, "Log level").low_cardinality(),
+        Column::of(
+            "logger_timestamp",
+            E::logger_timestamp,
+            "When Caddy logged the entry",
+        )
+        .codec(TIMESTAMP_CODEC),
+        Column::of("logger", E::logger, "Name of the Caddy logger").low_cardinality(),
+        Column::of("message", E::message, "Log message").codec(ZSTD),
+        Column::of("remote_ip", E::remote_ip, "IP of the peer").codec(ZSTD),
+        Column::of("remote_port", E::remote_port, "Port of the peer"),
+        Column::of(
+            "client_ip",
+            E::client_ip,
+            "IP of the client, as seen through the trusted proxies",
+        )
+        .codec(ZSTD),
+        Column::of("protocol", E::protocol, "HTTP protocol version").low_cardinality(),
+        Column::of("method", E::method, "Request method").low_cardinality(),
+        Column::of("host", E::host, "Requested host").low_cardinality(),
+        Column::of("uri", E::uri, "Request URI with the query string").codec(ZSTD),
+        Column::of(
+            "headers",
+            E::headers,
+            "Request headers, except for the promoted ones",
+        )
+        .codec(ZSTD),
+        Column::of("bytes_read", E::bytes_read, "Size of the request body").codec(COUNTER_CODEC),
+        Column::of("user_id", E::user_id, "Authenticated user"),
+        Column::of("duration", E::duration, "Request duration, in seconds").codec(ZSTD),
+        Column::of("size", E::size, "Size of the response body").codec(COUNTER_CODEC),
+        Column::of("status", E::status, "Response status"),
+        Column::of(
+            "response_headers",
+            E::response_headers,
+            "Response headers, except for the promoted ones",
+        )
+        .codec(ZSTD),
+        Column::of(
+            "upstream_addr",
+            E::upstream_addr,
+            "Address of the reverse proxy upstream",
+        )
+        .low_cardinality(),
+        Column::of(
+            "upstream_status",
+            E::upstream_status,
+            "Response status of the upstream",
+        ),
+        Column::of(
+            "upstream_latency",
+            E::upstream_latency,
+            "Latency of the upstream, in seconds",
+        ),
+        Column::of("trace_id", E::trace_id, "W3C trace id"),
+        Column::of("span_id", E::span_id, "W3C parent span id"),
+        Column::of("trace_state", E::trace_state, "W3C trace state"),
+        Column::of(
+            "request_id",
+            E::request_id,
+            "First of REQUEST_ID_HEADERS present",
+        ),
+        Column::of(
+            "visitor_key",
+            E::visitor_key,
+            "Hash of the host, client IP and User-Agent with a daily salt",
+        ),
+        Column::of(
+            "lossy_utf8",
+            E::lossy_utf8,
+            "Whether invalid UTF-8 of the line was replaced",
+        ),
+    ];
+
+    for (headers, prefix, kind) in [
+        (config.promoted_headers(), REQUEST_PREFIX, "request"),
+        (
+            config.promoted_response_headers(),
+            RESPONSE_PREFIX,
+            "response",
+        ),
+    ] {
+        columns.extend(headers.iter().map(|header| {
+            let suffix = promoted::column_suffix(header);
+            let column = Column {
+                name: format!("{prefix}{suffix}"),
+                type_: String::column_type(),
+                codec: None,
+                comment: format!("{} {kind} header, empty if missing", header.trim()),
+            };
+
+            if HIGH_CARDINALITY_HEADERS.contains(&suffix.as_str()) {
+                column.codec(ZSTD)
+            } else {
+                column.low_cardinality()
+            }
+        }));
+    }
+
+    TableSchema {
+        columns,
+        indexes: &[
+            "idx_trace_id trace_id TYPE bloom_filter(0.01) GRANULARITY 4",
+            "idx_request_id request_id TYPE bloom_filter(0.01) GRANULARITY 4",
+        ],
+        partition_by: "toYYYYMM(logger_timestamp)",
+        order_by: "(service, environment, host, logger_timestamp)",
+    }
+}
+
+/// Column of a live table, from `system.columns`
+#[derive(Row, Debug)]
+struct LiveColumn {
+    name: String,
+    #[klickhouse(rename = "type")]
+    type_: String,
+}
+
+/// Difference of a live table from the expected schema
+#[derive(Error, Display, Debug)]
+pub enum SchemaMismatch {
+    /// Table {0} does not exist
+    Table(String),
+    /// Column {column} ({expected}) is missing in {table}
+    Missing {
+        table: String,
+        column: String,
+        expected: String,
+    },
+    /// Column {column} of {table} is {actual}, expected {expected}
+    Type {
+        table: String,
+        column: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+async fn live_columns(
+    clickhouse: &ChCluster,
+    destination: &Destination<'_>,
+) -> Result<Vec<LiveColumn>> {
+    let query = QueryBuilder::new(
+        "SELECT name, type FROM system.columns \
+         WHERE database = if($1 = '', currentDatabase(), $1) AND table = $2 ORDER BY position",
+    )
+    .arg(destination.database().unwrap_or_default())
+    .arg(destination.table())
+    .finalize()?;
+
+    Ok(clickhouse.query::<LiveColumn>(&query.to_string()).await?)
+}
+
+/// Compares `access_log` and the route tables with the expected schema
+pub async fn check_tables(clickhouse: &ChCluster, config: &Config) -> Result<Vec<SchemaMismatch>> {
+    let schema = access_log(config);
+    let mut mismatches = Vec::new();
+
+    for destination in routes::destinations(config.routes()) {
+        let live = live_columns(clickhouse, &destination)
+            .await
+            .wrap_err_with(|| format!("Failed to read the columns of {destination}"))?;
+        mismatches.extend(schema.compare(&destination.to_string(), &live));
+    }
+
+    Ok(mismatches)
+}
+
+/// Checks the tables before ingesting, according to SCHEMA_CHECK
+pub async fn check_on_startup(clickhouse: &ChCluster, config: &Config) -> Result<()> {
+    let mode = *config.schema_check();
+    if mode == SchemaCheck::Off {
+        return Ok(());
+    }
+
+    let mismatches = match check_tables(clickhouse, config).await {
+        Ok(mismatches) => mismatches,
+        Err(e) if mode == SchemaCheck::Warn => {
+            error!("Failed to check the table schemas: {:?}", e);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    for mismatch in &mismatches {
+        warn!("Table schema differs: {}", mismatch);
+    }
+    if mismatches.is_empty() {
+        info!("Table schemas match");
+    }
+
+    ensure!(
+        mode != SchemaCheck::Strict || mismatches.is_empty(),
+        "Tables differ from the expected schema in {} places, see `schema print`",
+        mismatches.len()
+    );
+
+    Ok(())
+}
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug)]
+pub enum SchemaFormat {
+    /// `CREATE TABLE` statement
+    #[default]
+    Sql,
+    /// Documentation of the columns
+    Markdown,
+}
+
+#[derive(Subcommand, Debug)]
+enum SchemaCommand {
+    /// Prints the schema of `access_log`, with the configured promoted headers
+    Print {
+        /// Name of the table (with the database, if needed) in the statement
+        #[arg(long, default_value = "access_log")]
+        table: String,
+        #[arg(long, value_enum, default_value_t)]
+        format: SchemaFormat,
+    },
+    /// Compares `access_log` and the route tables with the expected schema
+    Check,
+}
+
+/// Expected schema of the access log tables
+#[derive(Args, Debug)]
+pub struct SchemaArgs {
+    #[command(subcommand)]
+    command: SchemaCommand,
+}
+
+pub async fn run(config: Config, args: SchemaArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    match args.command {
+        SchemaCommand::Print { table, format } => {
+            let schema = access_log(&config);
+            match format {
+                SchemaFormat::Sql => write!(stdout, "{}", schema.create_table(&table))?,
+                SchemaFormat::Markdown => write!(stdout, "{}", schema.markdown())?,
+            }
+        }
+        SchemaCommand::Check => {
+            let ch_cluster = ChCluster::new(&config).await?;
+            let mismatches = check_tables(&ch_cluster, &config).await?;
+            for mismatch in &mismatches {
+                writeln!(stdout, "{mismatch}")?;
+            }
+            ensure!(
+                mismatches.is_empty(),
+                "Tables differ from the expected schema in {} places",
+                mismatches.len()
+            );
+            writeln!(stdout, "Table schemas match")?;
+        }
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use eyre::{bail, Result, WrapErr};
+use futures::StreamExt;
+use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc::{self, error::TrySendError},
+};
+use tokio_util::codec::FramedRead;
+use tracing::{debug, error, info, warn};
+
+use crate::{app_state::AppState, compression::InputCompression, control::PauseMode};
+
+pub mod codec;
+pub mod db;
+pub mod message;
+
+use self::{
+    codec::{Frame, SyslogCodec},
+    db::DbSyslogEntry,
+    message::SyslogMessage,
+};
+
+/// Longest accepted message, also the largest UDP datagram
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+
+/// Datagrams waiting to be inserted, more are dropped
+const DATAGRAM_QUEUE_SIZE: usize = 1024;
+
+/// Most datagrams inserted at once
+const MAX_DATAGRAM_BATCH: usize = 256;
+
+const SYSLOG_INSERT: &str =
+    "INSERT INTO syslog SETTINGS async_insert=1, wait_for_async_insert=0 FORMAT NATIVE";
+
+/// Receives syslog messages over both UDP and TCP on `bind_to`
+pub async fn serve(app_state: Arc<AppState>, bind_to: &str) -> Result<()> {
+    let udp = UdpSocket::bind(bind_to)
+        .await
+        .wrap_err_with(|| format!("Failed to bind syslog UDP socket to address {}", bind_to))?;
+    let tcp = TcpListener::bind(bind_to)
+        .await
+        .wrap_err_with(|| format!("Failed to bind syslog TCP listener to address {}", bind_to))?;
+    info!("Syslog listening on {} (UDP and TCP)", bind_to);
+
+    tokio::try_join!(
+        receive_datagrams(Arc::clone(&app_state), udp),
+        accept(app_state, tcp)
+    )?;
+
+    Ok(())
+}
+
+async fn receive_datagrams(app_state: Arc<AppState>, socket: UdpSocket) -> Result<()> {
+    let mut buf = vec![0; MAX_MESSAGE_LENGTH];
+    // a single task inserts the datagrams, so that a flood can't pile up tasks and memory
+    let (queue, queued) = mpsc::channel(DATAGRAM_QUEUE_SIZE);
+    tokio::spawn(insert_datagrams(Arc::clone(&app_state), queued));
+
+    loop {
+        let (len, peer) = socket
+            .recv_from(&mut buf)
+            .await
+            .wrap_err("Failed to receive syslog datagram")?;
+        // nothing more is received while paused, datagrams over the socket's buffer are lost
+        let paused = app_state.control().wait_while_buffering().await;
+        if paused == Some(PauseMode::Reject) {
+            reject_paused();
+            continue;
+        }
+        let datagram = buf[..len].to_vec();
+
+        // never waited for, so that the socket's buffer doesn't overflow while inserting
+        match queue.try_send((peer, datagram)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                debug!(peer_addr = %peer, "Dropped syslog datagram, the queue is full");
+                metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "queue_full")
+                    .increment(1);
+            }
+            Err(TrySendError::Closed(_)) => bail!("Syslog datagram inserts stopped"),
+        }
+    }
+}
+
+/// Inserts the queued datagrams, as many at once as there are waiting
+async fn insert_datagrams(
+    app_state: Arc<AppState>,
+    mut queued: mpsc::Receiver<(SocketAddr, Vec<u8>)>,
+) {
+    let mut datagrams = Vec::with_capacity(MAX_DATAGRAM_BATCH);
+
+    while queued.recv_many(&mut datagrams, MAX_DATAGRAM_BATCH).await > 0 {
+        let entries = datagrams
+            .drain(..)
+            .filter_map(|(peer, datagram)| parse_message(&app_state, peer, &datagram))
+            .collect();
+        insert(&app_state, entries).await;
+    }
+}
+
+async fn accept(app_state: Arc<AppState>, listener: TcpListener) -> Result<()> {
+    loop {
+        let (socket, peer) = listener
+            .accept()
+            .await
+            .wrap_err("Failed to accept syslog connection")?;
+        info!(peer_addr = %peer, "Accepted new syslog connection");
+
+        tokio::spawn(handle_stream(Arc::clone(&app_state), socket, peer));
+    }
+}
+
+async fn handle_stream(app_state: Arc<AppState>, socket: TcpStream, peer: SocketAddr) {
+    let control = app_state.control();
+    // listed and closed on the admin interface, like the log connections
+    let connection = control.register(peer, InputCompression::None);
+    let mut framed = FramedRead::new(socket, SyslogCodec::new(MAX_MESSAGE_LENGTH));
+
+    loop {
+        let message = tokio::select! {
+            message = framed.next() => message,
+            () = connection.disconnected() => break,
+        };
+        let Some(message) = message else {
+            break;
+        };
+        let paused = tokio::select! {
+            paused = control.wait_while_buffering() => paused,
+            () = connection.disconnected() => break,
+        };
+        if paused == Some(PauseMode::Reject) {
+            reject_paused();
+            continue;
+        }
+
+        match message {
+            Ok(Frame::Message(message)) => {
+                connection
+                    .stats()
+                    .line(std::str::from_utf8(&message).is_err());
+                let entry = parse_message(&app_state, peer, &message);
+                insert(&app_state, entry.into_iter().collect()).await;
+            }
+            Ok(Frame::Discarded { bytes }) => {
+                warn!(
+                    peer_addr = %peer,
+                    bytes,
+                    max_length = MAX_MESSAGE_LENGTH,
+                    "Discarded syslog message over the maximum length"
+                );
+                connection.stats().discarded(bytes as u64);
+                metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "too_long")
+                    .increment(1);
+            }
+            Err(e) => {
+                // the framing is lost, there's no telling where the next message starts
+                error!(peer_addr = %peer, "Failed to read syslog message: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn reject_paused() {
+    metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "paused").increment(1);
+}
+
+/// Parses the message into a row, `None` if it's rejected
+fn parse_message(app_state: &AppState, peer: SocketAddr, message: &[u8]) -> Option<DbSyslogEntry> {
+    let ingested_at = SystemTime::now();
+    let id = uuid::Uuid::now_v7();
+    let _span = tracing::info_span!("syslog", peer_addr = %peer, message_uuid = %id).entered();
+
+    debug!(message_len = message.len(), "Received syslog message");
+    let text = String::from_utf8_lossy(message);
+    let message = match SyslogMessage::parse(&text, ingested_at) {
+        Ok(message) => message,
+        Err(e) => {
+            error!("Failed to parse syslog message: {}", e);
+            metrics::counter!("caddy_sink_entries_rejected_total", "reason" => "syslog")
+                .increment(1);
+            return None;
+        }
+    };
+    let entry = match DbSyslogEntry::new(id, &app_state.config(), ingested_at, peer.ip(), message) {
+        Ok(entry) => entry,
+        Err(e) => {
+            error!("Rejected syslog message: {}", e);
+            metrics::counter!("caddy_sink_entries_rejected_total", "reason" => e.reason())
+                .increment(1);
+            return None;
+        }
+    };
+    debug!(app_name = entry.app_name(), "Parsed syslog message");
+
+    Some(entry)
+}
+
+async fn insert(app_state: &AppState, entries: Vec<DbSyslogEntry>) {
+    if entries.is_empty() {
+        return;
+    }
+    let count = entries.len();
+
+    match app_state.clickhouse().insert(SYSLOG_INSERT, entries).await {
+        Ok(_) => {
+            info!(entries = count, "Inserted syslog entries");
+        }
+        Err(e) => {
+            error!("Failed to insert syslog entries: {}", e);
+        }
+    }
+}
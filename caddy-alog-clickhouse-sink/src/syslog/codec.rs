@@ -0,0 +1,204 @@
+use displaydoc::Display;
+use thiserror::Error;
+use tokio_util::{
+    bytes::{Buf, BytesMut},
+    codec::Decoder,
+};
+
+/// Longest prefix of an octet count, including the space after it
+const MAX_COUNT_LENGTH: usize = 10;
+
+#[derive(Error, Display, Debug)]
+pub enum SyslogCodecError {
+    /// Invalid octet count
+    InvalidCount,
+    /// Failed to read from the socket: {0}
+    Io(#[from] std::io::Error),
+}
+
+/// Frame of the syslog stream
+#[derive(Debug)]
+pub enum Frame {
+    Message(Vec<u8>),
+    /// Message over the maximum length, dropped
+    Discarded {
+        bytes: usize,
+    },
+}
+
+/// Oversized message being dropped
+#[derive(Clone, Copy, Debug)]
+enum Skip {
+    /// Octet counted message of `length` bytes, of which `remaining` weren't received yet
+    Counted { length: usize, remaining: usize },
+    /// Delimited message, of which `bytes` were dropped so far
+    Delimited { bytes: usize },
+}
+
+/// Syslog over TCP (RFC 6587): every message is either prefixed with its length
+/// (octet counting, `42 <34>1 ...`) or terminated by a newline (or NUL) byte.
+///
+/// The framing is detected per message, as some senders mix them. Messages over
+/// the maximum length are skipped, only an invalid octet count ends the stream.
+pub struct SyslogCodec {
+    max_length: usize,
+    /// Where to continue looking for the delimiter
+    next_index: usize,
+    skipping: Option<Skip>,
+}
+
+impl SyslogCodec {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            max_length,
+            next_index: 0,
+            skipping: None,
+        }
+    }
+
+    fn decode_octet_counted(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<Frame>, SyslogCodecError> {
+        let Some(space) = src.iter().take(MAX_COUNT_LENGTH).position(|b| *b == b' ') else {
+            return if src.len() >= MAX_COUNT_LENGTH {
+                Err(SyslogCodecError::InvalidCount)
+            } else {
+                Ok(None)
+            };
+        };
+        let length = std::str::from_utf8(&src[..space])
+            .ok()
+            .and_then(|count| count.parse::<usize>().ok())
+            .ok_or(SyslogCodecError::InvalidCount)?;
+
+        if length > self.max_length {
+            src.advance(space + 1);
+            return Ok(self.skip_counted(src, length, length));
+        }
+        if src.len() < space + 1 + length {
+            src.reserve(space + 1 + length - src.len());
+            return Ok(None);
+        }
+        src.advance(space + 1);
+
+        Ok(Some(Frame::Message(src.split_to(length).to_vec())))
+    }
+
+    /// Drops what's received of the oversized message, the framing tells how much is left
+    fn skip_counted(
+        &mut self,
+        src: &mut BytesMut,
+        length: usize,
+        remaining: usize,
+    ) -> Option<Frame> {
+        let skipped = remaining.min(src.len());
+        src.advance(skipped);
+
+        let remaining = remaining - skipped;
+        if remaining > 0 {
+            self.skipping = Some(Skip::Counted { length, remaining });
+            return None;
+        }
+        self.skipping = None;
+
+        Some(Frame::Discarded { bytes: length })
+    }
+
+    fn decode_delimited(&mut self, src: &mut BytesMut, discarded: Option<usize>) -> Option<Frame> {
+        let end = src[self.next_index..]
+            .iter()
+            .position(|b| *b == b'\n' || *b == 0)
+            .map(|offset| self.next_index + offset);
+
+        match (end, discarded) {
+            (Some(end), Some(discarded)) => {
+                src.advance(end + 1);
+                self.next_index = 0;
+                self.skipping = None;
+
+                Some(Frame::Discarded {
+                    bytes: discarded + end,
+                })
+            }
+            (Some(end), None) => {
+                let mut message = src.split_to(end + 1);
+                self.next_index = 0;
+
+                if end > self.max_length {
+                    return Some(Frame::Discarded { bytes: end });
+                }
+                message.truncate(end);
+                if message.last() == Some(&b'\r') {
+                    message.truncate(end - 1);
+                }
+
+                Some(Frame::Message(message.to_vec()))
+            }
+            // the rest of the message isn't kept, so that the buffer doesn't grow
+            (None, discarded) if discarded.is_some() || src.len() > self.max_length => {
+                self.skipping = Some(Skip::Delimited {
+                    bytes: discarded.unwrap_or_default() + src.len(),
+                });
+                self.next_index = 0;
+                src.clear();
+
+                None
+            }
+            (None, _) => {
+                self.next_index = src.len();
+
+                None
+            }
+        }
+    }
+}
+
+impl Decoder for SyslogCodec {
+    type Item = Frame;
+    type Error = SyslogCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.skipping {
+            Some(Skip::Counted { length, remaining }) => {
+                return Ok(self.skip_counted(src, length, remaining));
+            }
+            Some(Skip::Delimited { bytes }) => return Ok(self.decode_delimited(src, Some(bytes))),
+            // in the middle of a delimited message
+            None if self.next_index > 0 => return Ok(self.decode_delimited(src, None)),
+            None => {}
+        }
+
+        // empty lines between the messages
+        let skip = src
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace() || **b == 0)
+            .count();
+        src.advance(skip);
+
+        match src.first() {
+            None => Ok(None),
+            Some(b'1'..=b'9') => self.decode_octet_counted(src),
+            Some(_) => Ok(self.decode_delimited(src, None)),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(frame) = self.decode(src)? {
+            return Ok(Some(frame));
+        }
+        self.next_index = 0;
+
+        match self.skipping.take() {
+            Some(Skip::Counted { length, remaining }) => Ok(Some(Frame::Discarded {
+                bytes: length - remaining,
+            })),
+            Some(Skip::Delimited { bytes }) => Ok(Some(Frame::Discarded { bytes })),
+            // last message without a trailing newline
+            None if !src.is_empty() && !src[0].is_ascii_digit() => {
+                Ok(Some(Frame::Message(src.split().to_vec())))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use derive_getters::Getters;
+use klickhouse::{DateTime64, Row, Tz, Uuid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clickhouse::to_datetime64,
+    config::Config,
+    log::{db::InvalidEntry, timestamp::is_plausible},
+    syslog::message::SyslogMessage,
+};
+
+/// Row of the `syslog` table
+#[derive(Serialize, Deserialize, Row, Getters, Clone, Debug)]
+pub struct DbSyslogEntry {
+    // Added by the sink service
+    id: Uuid,
+    service: String,
+    environment: String,
+    sink_instance: String,
+    ingested_at: DateTime64<3>,
+    remote_ip: String,
+    // Syslog message
+    facility: u8,
+    severity: u8,
+    timestamp: DateTime64<6>,
+    hostname: String,
+    app_name: String,
+    proc_id: String,
+    msg_id: String,
+    /// Structured data as a JSON object of the elements' parameters, keyed by the element ids
+    structured_data: String,
+    message: String,
+}
+
+impl DbSyslogEntry {
+    /// Entry of the message, received from `peer` at `ingested_at`.
+    ///
+    /// Messages without a timestamp get the receive time, the ones with an implausible one
+    /// are rejected.
+    pub fn new(
+        id: uuid::Uuid,
+        config: &Config,
+        ingested_at: SystemTime,
+        peer: IpAddr,
+        message: SyslogMessage<'_>,
+    ) -> Result<Self, InvalidEntry> {
+        let (
+            facility,
+            severity,
+            timestamp,
+            hostname,
+            app_name,
+            proc_id,
+            msg_id,
+            structured_data,
+            message,
+        ) = message.dissolve();
+
+        let timestamp = match timestamp {
+            Some(micros) if is_plausible(micros, ingested_at) => micros,
+            Some(micros) => return Err(InvalidEntry::Timestamp(micros.to_string())),
+            None => ingested_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as i64,
+        };
+
+        // elements with the same id are merged
+        let mut elements = BTreeMap::<&str, BTreeMap<&str, &str>>::new();
+        for element in &structured_data {
+            elements.entry(element.id()).or_default().extend(
+                element
+                    .params()
+                    .iter()
+                    .map(|(name, value)| (*name, &**value)),
+            );
+        }
+
+        Ok(Self {
+            id,
+            service: config.service_name().to_string(),
+            environment: config.environment().to_string(),
+            sink_instance: config.instance_id().clone(),
+            ingested_at: to_datetime64(ingested_at),
+            remote_ip: peer.to_string(),
+            facility,
+            severity,
+            timestamp: DateTime64(Tz::UTC, timestamp as u64),
+            hostname: hostname.unwrap_or_default().to_string(),
+            app_name: app_name.unwrap_or_default().to_string(),
+            proc_id: proc_id.unwrap_or_default().to_string(),
+            msg_id: msg_id.unwrap_or_default().to_string(),
+            structured_data: serde_json::to_string(&elements).unwrap_or_default(),
+            message: message.into_owned(),
+        })
+    }
+}
@@ -0,0 +1,285 @@
+use std::{borrow::Cow, time::SystemTime};
+
+use derive_getters::{Dissolve, Getters};
+use displaydoc::Display;
+use thiserror::Error;
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Duration, OffsetDateTime,
+    PrimitiveDateTime,
+};
+
+/// user.notice, for messages without a priority (RFC 3164, 4.3.3)
+const DEFAULT_PRIORITY: u8 = 13;
+const MAX_PRIORITY: u8 = 191;
+/// Longest tag (`app[pid]:`) of an RFC 3164 message, anything longer is considered the content
+const MAX_TAG_LENGTH: usize = 48;
+const BOM: char = '\u{feff}';
+
+#[derive(Error, Display, Debug)]
+pub enum SyslogParseError {
+    /// Invalid priority
+    Priority,
+    /// Missing {0} of the RFC 5424 header
+    Header(&'static str),
+    /// Invalid structured data
+    StructuredData,
+}
+
+/// Element of the RFC 5424 structured data, e.g. `[origin@32473 ip="10.0.0.1"]`
+#[derive(Getters, Debug)]
+pub struct SdElement<'a> {
+    id: &'a str,
+    params: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+/// Syslog message in either the RFC 5424 or the legacy RFC 3164 (BSD) format
+#[derive(Dissolve, Getters, Debug)]
+pub struct SyslogMessage<'a> {
+    facility: u8,
+    severity: u8,
+    /// Microseconds since the unix epoch, if the sender set one
+    timestamp: Option<i64>,
+    hostname: Option<&'a str>,
+    app_name: Option<&'a str>,
+    proc_id: Option<&'a str>,
+    msg_id: Option<&'a str>,
+    structured_data: Vec<SdElement<'a>>,
+    message: Cow<'a, str>,
+}
+
+impl<'a> SyslogMessage<'a> {
+    /// Parses the message, received at `received_at`, which is needed
+    /// for the year missing in RFC 3164 timestamps
+    pub fn parse(text: &'a str, received_at: SystemTime) -> Result<Self, SyslogParseError> {
+        let (priority, rest) = priority(text)?;
+
+        match rest.strip_prefix("1 ") {
+            Some(rest) => Self::parse_rfc5424(priority, rest),
+            None => Ok(Self::parse_rfc3164(priority, rest, received_at)),
+        }
+    }
+
+    /// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]`
+    fn parse_rfc5424(priority: u8, rest: &'a str) -> Result<Self, SyslogParseError> {
+        let (timestamp, rest) = header_field(rest, "timestamp")?;
+        let (hostname, rest) = header_field(rest, "hostname")?;
+        let (app_name, rest) = header_field(rest, "app name")?;
+        let (proc_id, rest) = header_field(rest, "process id")?;
+        let (msg_id, rest) = header_field(rest, "message id")?;
+        let (structured_data, rest) = structured_data(rest)?;
+        let message = match rest.strip_prefix(' ') {
+            Some(message) => message.strip_prefix(BOM).unwrap_or(message),
+            None if rest.is_empty() => rest,
+            None => return Err(SyslogParseError::StructuredData),
+        };
+
+        Ok(Self {
+            facility: priority >> 3,
+            severity: priority & 7,
+            timestamp: timestamp.and_then(|timestamp| {
+                OffsetDateTime::parse(timestamp, &Rfc3339)
+                    .ok()
+                    .map(unix_micros)
+            }),
+            hostname,
+            app_name,
+            proc_id,
+            msg_id,
+            structured_data,
+            message: Cow::Borrowed(message.trim_end()),
+        })
+    }
+
+    /// `[TIMESTAMP HOSTNAME] [TAG[PID]:] CONTENT`, everything is optional in practice
+    fn parse_rfc3164(priority: u8, rest: &'a str, received_at: SystemTime) -> Self {
+        let (timestamp, rest) = match bsd_timestamp(rest, received_at) {
+            Some((timestamp, rest)) => (Some(timestamp), rest),
+            None => (None, rest),
+        };
+        // the host name is only present after a timestamp,
+        // some senders skip it anyway and start with the tag
+        let (hostname, rest) = match rest.split_once(' ') {
+            Some((hostname, rest))
+                if timestamp.is_some() && !hostname.ends_with(':') && !hostname.contains('[') =>
+            {
+                (Some(hostname), rest)
+            }
+            _ => (None, rest),
+        };
+        let (app_name, proc_id, message) = tag(rest);
+
+        Self {
+            facility: priority >> 3,
+            severity: priority & 7,
+            timestamp,
+            hostname,
+            app_name,
+            proc_id,
+            msg_id: None,
+            structured_data: Vec::new(),
+            message: Cow::Borrowed(message.trim_end()),
+        }
+    }
+}
+
+fn unix_micros(datetime: OffsetDateTime) -> i64 {
+    (datetime.unix_timestamp_nanos() / 1_000) as i64
+}
+
+/// `<PRI>` prefix, optional in RFC 3164
+fn priority(text: &str) -> Result<(u8, &str), SyslogParseError> {
+    let Some(rest) = text.strip_prefix('<') else {
+        return Ok((DEFAULT_PRIORITY, text));
+    };
+    let (digits, rest) = rest.split_once('>').ok_or(SyslogParseError::Priority)?;
+    let priority = Some(digits)
+        .filter(|digits| (1..=3).contains(&digits.len()))
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse::<u8>().ok())
+        .filter(|priority| *priority <= MAX_PRIORITY)
+        .ok_or(SyslogParseError::Priority)?;
+
+    Ok((priority, rest))
+}
+
+/// Space-terminated RFC 5424 header field, `-` is the nil value
+fn header_field<'a>(
+    rest: &'a str,
+    name: &'static str,
+) -> Result<(Option<&'a str>, &'a str), SyslogParseError> {
+    let (value, rest) = rest
+        .split_once(' ')
+        .filter(|(value, _)| !value.is_empty())
+        .ok_or(SyslogParseError::Header(name))?;
+
+    Ok(((value != "-").then_some(value), rest))
+}
+
+/// `-` or `[id name="value" ...]...`, values may escape `"`, `\` and `]` with a backslash
+fn structured_data(mut rest: &str) -> Result<(Vec<SdElement<'_>>, &str), SyslogParseError> {
+    if let Some(rest) = rest.strip_prefix('-') {
+        return Ok((Vec::new(), rest));
+    }
+
+    let mut elements = Vec::new();
+    while let Some(element) = rest.strip_prefix('[') {
+        let end = element
+            .find([' ', ']'])
+            .ok_or(SyslogParseError::StructuredData)?;
+        let (id, mut element) = element.split_at(end);
+
+        let mut params = Vec::new();
+        while let Some(param) = element.strip_prefix(' ') {
+            let (name, value) = param
+                .split_once("=\"")
+                .ok_or(SyslogParseError::StructuredData)?;
+            let (value, remaining) = sd_value(value)?;
+            params.push((name, value));
+            element = remaining;
+        }
+
+        rest = element
+            .strip_prefix(']')
+            .ok_or(SyslogParseError::StructuredData)?;
+        elements.push(SdElement { id, params });
+    }
+
+    if elements.is_empty() {
+        return Err(SyslogParseError::StructuredData);
+    }
+
+    Ok((elements, rest))
+}
+
+/// Quoted value up to the closing `"`, which is skipped
+fn sd_value(text: &str) -> Result<(Cow<'_, str>, &str), SyslogParseError> {
+    let mut escaped = false;
+    let mut unescaped: Option<String> = None;
+
+    for (i, c) in text.char_indices() {
+        match (escaped, c) {
+            (false, '\\') => {
+                escaped = true;
+                unescaped.get_or_insert_with(|| text[..i].to_string());
+            }
+            (false, '"') => {
+                let value = unescaped.map_or(Cow::Borrowed(&text[..i]), Cow::Owned);
+
+                return Ok((value, &text[i + 1..]));
+            }
+            (true, c) => {
+                escaped = false;
+                let value = unescaped.get_or_insert_with(String::new);
+                // only these are escaped, any other backslash is kept
+                if !matches!(c, '"' | '\\' | ']') {
+                    value.push('\\');
+                }
+                value.push(c);
+            }
+            (false, c) => {
+                if let Some(value) = &mut unescaped {
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    Err(SyslogParseError::StructuredData)
+}
+
+/// `Mmm dd hh:mm:ss ` in the sender's local time, taken as UTC, or an RFC 3339 timestamp
+/// (e.g. rsyslog's high precision format)
+fn bsd_timestamp(text: &str, received_at: SystemTime) -> Option<(i64, &str)> {
+    let (timestamp, rest) = text.split_once(' ')?;
+    if let Ok(datetime) = OffsetDateTime::parse(timestamp, &Rfc3339) {
+        return Some((unix_micros(datetime), rest));
+    }
+
+    let timestamp = text.get(..15)?;
+    let rest = text.get(15..)?.strip_prefix(' ')?;
+    // the day is padded with a space, some senders use a zero
+    let timestamp = timestamp.replacen("  ", " ", 1);
+    let format = format_description!(
+        "[year] [month repr:short] [day padding:none] [hour]:[minute]:[second]"
+    );
+
+    // the year is missing, it's the one, which doesn't put the message too far in the future
+    let received_at = OffsetDateTime::from(received_at);
+    let datetime = [received_at.year(), received_at.year() - 1]
+        .into_iter()
+        .filter_map(|year| PrimitiveDateTime::parse(&format!("{year} {timestamp}"), format).ok())
+        .map(PrimitiveDateTime::assume_utc)
+        .find(|datetime| *datetime <= received_at + Duration::DAY)?;
+
+    Some((unix_micros(datetime), rest))
+}
+
+/// `app[pid]: content` or `app: content`, the whole text is the content without a tag
+fn tag(text: &str) -> (Option<&str>, Option<&str>, &str) {
+    let Some(end) = text
+        .char_indices()
+        .take(MAX_TAG_LENGTH)
+        .find(|(_, c)| matches!(c, ':' | '[' | ' '))
+        .map(|(i, _)| i)
+    else {
+        return (None, None, text);
+    };
+    let (app_name, rest) = text.split_at(end);
+    if app_name.is_empty() {
+        return (None, None, text);
+    }
+
+    let (proc_id, rest) = match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((proc_id, rest)) => (Some(proc_id), rest),
+        None => (None, rest),
+    };
+
+    match rest.strip_prefix(':') {
+        Some(message) => (
+            Some(app_name),
+            proc_id,
+            message.strip_prefix(' ').unwrap_or(message),
+        ),
+        None => (None, None, text),
+    }
+}
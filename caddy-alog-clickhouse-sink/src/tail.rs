@@ -0,0 +1,134 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+use crate::log::db::DbAccessLogEntry;
+
+/// Server-side filter of a live tail subscription, all conditions must match
+#[derive(Deserialize, Default, Debug)]
+pub struct TailFilter {
+    /// Exact match on the request host
+    host: Option<String>,
+    /// Minimal response status, inclusive
+    status_min: Option<u16>,
+    /// Maximal response status, inclusive
+    status_max: Option<u16>,
+    /// Prefix of the request URI
+    path_prefix: Option<String>,
+    /// Exact match on either the remote or the client IP
+    ip: Option<String>,
+}
+
+impl TailFilter {
+    fn matches(&self, entry: &DbAccessLogEntry) -> bool {
+        self.host.as_ref().is_none_or(|host| entry.host() == host)
+            && self.status_min.is_none_or(|min| *entry.status() >= min)
+            && self.status_max.is_none_or(|max| *entry.status() <= max)
+            && self
+                .path_prefix
+                .as_ref()
+                .is_none_or(|prefix| entry.uri().starts_with(prefix.as_str()))
+            && self
+                .ip
+                .as_ref()
+                .is_none_or(|ip| entry.remote_ip() == ip || entry.client_ip().as_ref() == Some(ip))
+    }
+}
+
+struct Subscriber {
+    filter: TailFilter,
+    sender: mpsc::Sender<Arc<str>>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Receiving side of a live tail subscription
+pub struct TailSubscription {
+    receiver: mpsc::Receiver<Arc<str>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl TailSubscription {
+    /// Next serialized entry, `None` if the hub is gone
+    pub async fn recv(&mut self) -> Option<Arc<str>> {
+        self.receiver.recv().await
+    }
+
+    /// Number of entries dropped since the last call, because the subscriber was too slow
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Fan-out of parsed entries to live tail subscribers.
+///
+/// Every subscriber has its own bounded buffer, entries that don't fit are dropped
+/// and counted, so slow viewers never slow down the ingestion itself.
+pub struct TailHub {
+    subscribers: Mutex<Vec<Subscriber>>,
+    buffer_size: AtomicUsize,
+}
+
+impl TailHub {
+    pub fn new(buffer_size: usize) -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            buffer_size: AtomicUsize::new(buffer_size),
+        }
+    }
+
+    /// Changes the buffer size for the new subscribers
+    pub fn set_buffer_size(&self, buffer_size: usize) {
+        self.buffer_size.store(buffer_size, Ordering::Relaxed);
+    }
+
+    pub fn subscribe(&self, filter: TailFilter) -> TailSubscription {
+        let (sender, receiver) = mpsc::channel(self.buffer_size.load(Ordering::Relaxed));
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        debug!(?filter, "New live tail subscriber");
+        self.subscribers.lock().unwrap().push(Subscriber {
+            filter,
+            sender,
+            dropped: Arc::clone(&dropped),
+        });
+
+        TailSubscription { receiver, dropped }
+    }
+
+    pub fn publish(&self, entry: &DbAccessLogEntry) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+
+        // serialized lazily, only if someone is interested in the entry
+        let mut serialized: Option<Arc<str>> = None;
+        for subscriber in subscribers.iter() {
+            if !subscriber.filter.matches(entry) {
+                continue;
+            }
+
+            let payload = match &serialized {
+                Some(payload) => Arc::clone(payload),
+                None => match serde_json::to_string(entry) {
+                    Ok(payload) => Arc::clone(serialized.insert(payload.into())),
+                    Err(e) => {
+                        error!("Failed to serialize entry for live tail: {}", e);
+                        return;
+                    }
+                },
+            };
+
+            if subscriber.sender.try_send(payload).is_err() {
+                subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::OnceLock;
+
+use eyre::{eyre, Result, WrapErr};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use serde::Deserialize;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    filter::Targets, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer,
+    Registry,
+};
+use tracing_tree::HierarchicalLayer;
+
+use crate::config::Config;
+
+/// Format of the service's own logs
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Indented tree of spans, for reading in a terminal
+    #[default]
+    Tree,
+    /// Single line per event, with the span context
+    Compact,
+    /// Single JSON object per event, for log aggregation
+    Json,
+}
+
+/// Handle to replace the `RUST_LOG` filter at runtime
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Sets up logging in the configured format and, if an endpoint is configured,
+/// export of the spans over OTLP
+pub fn init(config: &Config) -> Result<()> {
+    let format = *config.log_format();
+
+    let tree = (format == LogFormat::Tree).then(|| {
+        HierarchicalLayer::new(4)
+            .with_targets(true)
+            .with_indent_lines(true)
+            .with_bracketed_fields(true)
+            .with_thread_names(false)
+            .with_thread_ids(true)
+    });
+    let compact = (format == LogFormat::Compact).then(|| {
+        tracing_subscriber::fmt::layer()
+            .compact()
+            .with_thread_ids(true)
+    });
+    let json = (format == LogFormat::Json).then(|| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+    });
+
+    let otlp = match config.otlp_endpoint() {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new([
+                    KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                    KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                    KeyValue::new("service.instance.id", config.instance_id().clone()),
+                    KeyValue::new("deployment.environment", config.environment().clone()),
+                ])))
+                .install_batch(runtime::Tokio)
+                .wrap_err("Failed to set up OTLP exporter")?;
+
+            // the exporter's own transport is traced too, exporting it would loop forever
+            let exporter_internals = Targets::new()
+                .with_default(LevelFilter::TRACE)
+                .with_target("h2", LevelFilter::OFF)
+                .with_target("hyper", LevelFilter::OFF)
+                .with_target("tonic", LevelFilter::OFF)
+                .with_target("tower", LevelFilter::OFF);
+
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(exporter_internals),
+            )
+        }
+        None => None,
+    };
+
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    FILTER.get_or_init(|| filter_handle);
+
+    Registry::default()
+        .with(filter)
+        .with(tree)
+        .with(compact)
+        .with(json)
+        .with(otlp)
+        .try_init()
+        .wrap_err("Failed to set up tracing")
+}
+
+/// Current filter directives, in the `RUST_LOG` syntax
+pub fn log_filter() -> Result<String> {
+    FILTER
+        .get()
+        .ok_or_else(|| eyre!("Logging isn't set up"))?
+        .with_current(ToString::to_string)
+        .wrap_err("Failed to read the log filter")
+}
+
+/// Replaces the filter with the directives, in the `RUST_LOG` syntax
+pub fn set_log_filter(directives: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(directives).wrap_err("Invalid log filter")?;
+
+    FILTER
+        .get()
+        .ok_or_else(|| eyre!("Logging isn't set up"))?
+        .reload(filter)
+        .wrap_err("Failed to replace the log filter")
+}
+
+/// Sends the spans, which haven't been exported yet
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
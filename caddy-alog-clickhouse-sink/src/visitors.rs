@@ -0,0 +1,89 @@
+use std::{
+    collections::BTreeMap,
+    hash::Hasher,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use siphasher::sip::SipHasher24;
+use tracing::info;
+
+use crate::log::AccessLogEntry;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Random keys of the visitor hashes, each valid for a single (UTC) day
+#[derive(Default)]
+struct Salts {
+    /// Earliest day, which may still have a salt, dropped salts are never generated again
+    oldest_day: u64,
+    keys: BTreeMap<u64, [u8; 16]>,
+}
+
+/// Anonymous visitor keys for counting unique visitors.
+///
+/// A key is a hash of the host, client IP and User-Agent, keyed with the salt of the day
+/// the entry was logged on. Salts are only kept in memory and dropped once the day's entries
+/// may no longer arrive, so that keys of different days can't be linked and can't be reversed
+/// into IPs by brute force.
+#[derive(Default)]
+pub struct VisitorKeys {
+    salts: Mutex<Salts>,
+}
+
+fn day(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / SECS_PER_DAY
+}
+
+impl VisitorKeys {
+    /// Salt of the day, `None` if it's already dropped
+    fn salt(&self, day: u64, ingested_at: SystemTime, max_lag: Duration) -> Option<[u8; 16]> {
+        let oldest_day = self::day(ingested_at.checked_sub(max_lag).unwrap_or(UNIX_EPOCH));
+
+        let mut salts = self.salts.lock().unwrap();
+        if oldest_day > salts.oldest_day {
+            // nothing may hash into the dropped days' keys after this
+            salts.keys.retain(|day, _| *day >= oldest_day);
+            salts.oldest_day = oldest_day;
+        }
+        if day < salts.oldest_day {
+            return None;
+        }
+
+        let key = salts.keys.entry(day).or_insert_with(|| {
+            info!(day, "Generated visitor salt");
+            rand::random()
+        });
+
+        Some(*key)
+    }
+
+    /// Key of the entry logged at `logged_at`, 0 (not counted) if it arrived too late
+    /// for the day's salt
+    pub fn key(
+        &self,
+        entry: &AccessLogEntry<'_>,
+        logged_at: SystemTime,
+        ingested_at: SystemTime,
+        max_lag: Duration,
+    ) -> u64 {
+        let Some(salt) = self.salt(day(logged_at), ingested_at, max_lag) else {
+            return 0;
+        };
+        let request = entry.request();
+        let ip = request.client_addr();
+        let user_agent = request.headers().get("User-Agent").unwrap_or_default();
+
+        let mut hasher = SipHasher24::new_with_key(&salt);
+        // separated, so that moving bytes between the fields changes the key
+        for field in [request.host().as_ref(), ip, user_agent] {
+            hasher.write(field.as_bytes());
+            hasher.write_u8(0xff);
+        }
+
+        hasher.finish()
+    }
+}
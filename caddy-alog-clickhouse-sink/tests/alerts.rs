@@ -0,0 +1,220 @@
+//! Alert rules notifying a local webhook stand-in
+
+mod support;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{routing::post, Json, Router};
+use caddy_alog_clickhouse_sink::alerts::AlertStates;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use support::Sink;
+
+/// Webhook receiving the notifications, keeps their bodies
+struct Webhook {
+    url: String,
+    received: Arc<Mutex<Vec<Value>>>,
+}
+
+impl Webhook {
+    async fn start() -> Self {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = Arc::clone(&received);
+                move |Json(body): Json<Value>| async move {
+                    received.lock().unwrap().push(body);
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self { url, received }
+    }
+
+    /// Notifications received since the last call
+    fn take(&self) -> Vec<Value> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+}
+
+/// Access log lines of `count` requests to the host
+fn requests(count: usize, host: &str, status: u16, duration: f64) -> Vec<u8> {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    let line = json!({
+        "level": "info",
+        "ts": ts,
+        "logger": "http.log.access.log0",
+        "msg": "handled request",
+        "request": {
+            "remote_ip": "198.51.100.23",
+            "remote_port": "51544",
+            "client_ip": "198.51.100.23",
+            "proto": "HTTP/1.1",
+            "method": "GET",
+            "host": host,
+            "uri": "/v1/orders",
+            "headers": {"User-Agent": ["curl/8.5.0"]},
+        },
+        "bytes_read": 0,
+        "user_id": "",
+        "duration": duration,
+        "size": 0,
+        "status": status,
+        "resp_headers": {"Server": ["Caddy"]},
+    })
+    .to_string()
+        + "\n";
+
+    line.repeat(count).into_bytes()
+}
+
+fn summary(notification: &Value) -> (&str, &str) {
+    (
+        notification["status"].as_str().unwrap(),
+        notification["rule"].as_str().unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn notifies_webhook() {
+    let webhook = Webhook::start().await;
+    let sink = Sink::with_settings(&[
+        ("ALERT_WEBHOOK_URL", &webhook.url),
+        ("ALERT_HOSTS", "api.example.com"),
+        ("ALERT_WINDOW_SECS", "600"),
+        ("ALERT_MIN_REQUESTS", "4"),
+        ("ALERT_ERROR_RATIO", "0.5"),
+        ("ALERT_P95_DURATION_SECS", "1"),
+        ("ALERT_NO_TRAFFIC", "false"),
+        ("ALERT_COOLDOWN_SECS", "3600"),
+    ])
+    .await;
+    let alerts = sink.app_state().alerts();
+    let mut states = AlertStates::default();
+
+    // slow server errors, the other host isn't evaluated
+    sink.ingest(&requests(4, "api.example.com", 503, 2.5)).await;
+    sink.ingest(&requests(4, "www.example.com", 500, 2.5)).await;
+    alerts.notify(&mut states).await;
+    let notifications = webhook.take();
+    assert_eq!(notifications.len(), 2, "{notifications:#?}");
+    assert_eq!(
+        notifications[0],
+        json!({
+            "status": "firing",
+            "rule": "error_ratio",
+            "host": "api.example.com",
+            "service": "caddy",
+            "environment": "test",
+            "value": 1,
+            "threshold": 0.5,
+            "message": "5xx ratio on api.example.com is 100.0% (threshold 50.0%)",
+        })
+    );
+    assert_eq!(summary(&notifications[1]), ("firing", "p95_duration"));
+    assert_eq!(notifications[1]["host"], "api.example.com");
+    assert_eq!(notifications[1]["threshold"], 1);
+
+    // still firing, nothing new to notify
+    alerts.notify(&mut states).await;
+    assert!(webhook.take().is_empty());
+
+    // fast successful requests bring the 5xx ratio down to a third, the p95 stays slow
+    sink.ingest(&requests(8, "api.example.com", 200, 0.01))
+        .await;
+    alerts.notify(&mut states).await;
+    let notifications = webhook.take();
+    assert_eq!(notifications.len(), 1, "{notifications:#?}");
+    assert_eq!(summary(&notifications[0]), ("resolved", "error_ratio"));
+    assert_eq!(
+        notifications[0]["message"],
+        "Resolved: 5xx ratio on api.example.com is 33.3% (threshold 50.0%)"
+    );
+
+    // firing again within the cooldown
+    sink.ingest(&requests(12, "api.example.com", 500, 0.01))
+        .await;
+    alerts.notify(&mut states).await;
+    assert!(webhook.take().is_empty());
+}
+
+#[tokio::test]
+async fn states_of_resolved_hosts_are_forgotten() {
+    let webhook = Webhook::start().await;
+    let sink = Sink::with_settings(&[
+        ("ALERT_WEBHOOK_URL", &webhook.url),
+        ("ALERT_WINDOW_SECS", "600"),
+        ("ALERT_MIN_REQUESTS", "1"),
+        ("ALERT_ERROR_RATIO", "0.5"),
+        ("ALERT_P95_DURATION_SECS", "0"),
+        ("ALERT_NO_TRAFFIC", "false"),
+        ("ALERT_COOLDOWN_SECS", "0"),
+    ])
+    .await;
+    let alerts = sink.app_state().alerts();
+    let mut states = AlertStates::default();
+    let hosts = (0..8)
+        .map(|i| format!("scan{i}.example.com"))
+        .collect::<Vec<_>>();
+
+    // hosts, which don't fire, aren't tracked
+    for host in &hosts[..4] {
+        sink.ingest(&requests(1, host, 200, 0.01)).await;
+    }
+    alerts.notify(&mut states).await;
+    assert!(states.is_empty());
+    assert!(webhook.take().is_empty());
+
+    for host in &hosts[4..] {
+        sink.ingest(&requests(1, host, 500, 0.01)).await;
+    }
+    alerts.notify(&mut states).await;
+    assert_eq!(states.len(), 4);
+    assert_eq!(webhook.take().len(), 4);
+
+    for host in &hosts[4..] {
+        sink.ingest(&requests(2, host, 200, 0.01)).await;
+    }
+    alerts.notify(&mut states).await;
+    assert!(states.is_empty());
+    let notifications = webhook.take();
+    assert_eq!(notifications.len(), 4, "{notifications:#?}");
+    assert!(notifications
+        .iter()
+        .all(|notification| summary(notification) == ("resolved", "error_ratio")));
+}
+
+#[tokio::test]
+async fn placeholders_in_values_are_kept() {
+    let webhook = Webhook::start().await;
+    let sink = Sink::with_settings(&[
+        ("ALERT_WEBHOOK_URL", &webhook.url),
+        ("ALERT_MIN_REQUESTS", "1"),
+        ("ALERT_ERROR_RATIO", "0.5"),
+        ("ALERT_P95_DURATION_SECS", "0"),
+        ("ALERT_NO_TRAFFIC", "false"),
+    ])
+    .await;
+    let mut states = AlertStates::default();
+
+    sink.ingest(&requests(1, "{{message}}", 500, 0.01)).await;
+    sink.app_state().alerts().notify(&mut states).await;
+    let notifications = webhook.take();
+    assert_eq!(notifications.len(), 1, "{notifications:#?}");
+    assert_eq!(notifications[0]["host"], "{{message}}");
+    assert_eq!(
+        notifications[0]["message"],
+        "5xx ratio on {{message}} is 100.0% (threshold 50.0%)"
+    );
+}
@@ -0,0 +1,260 @@
+//! Tailing of Docker json-file logs: split messages, rotation and offsets across restarts
+
+mod support;
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use caddy_alog_clickhouse_sink::docker::DockerTailer;
+
+use support::Sink;
+
+const CONTAINER_ID: &str = "3f4e9a1b2c7d8e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f";
+
+/// Directory of the containers' logs, removed when dropped
+struct Containers(PathBuf);
+
+impl Containers {
+    fn new() -> Self {
+        let root = std::env::temp_dir().join(format!("sink-docker-{}", uuid::Uuid::now_v7()));
+        let directory = root.join(CONTAINER_ID);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("config.v2.json"),
+            r#"{"ID":"3f4e9a1b2c7d","Name":"/web"}"#,
+        )
+        .unwrap();
+
+        Self(root)
+    }
+
+    fn log(&self) -> PathBuf {
+        self.0
+            .join(CONTAINER_ID)
+            .join(format!("{CONTAINER_ID}-json.log"))
+    }
+
+    /// Renames the log like Docker's rotation does, the next write creates a new one
+    fn rotate(&self) {
+        let log = self.log();
+        std::fs::rename(&log, log.with_extension("log.1")).unwrap();
+    }
+
+    async fn sink(&self) -> Sink {
+        let pattern = self.0.join("*").join("*-json.log");
+        let offsets = self.0.join("offsets.json");
+
+        Sink::with_settings(&[
+            ("DOCKER_LOG_PATHS", pattern.to_str().unwrap()),
+            ("DOCKER_OFFSETS_PATH", offsets.to_str().unwrap()),
+        ])
+        .await
+    }
+}
+
+impl Drop for Containers {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn append(path: &Path, text: &str) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+}
+
+fn line(log: &str, stream: &str) -> String {
+    line_at(log, stream, "2024-04-05T10:00:00.123456789Z")
+}
+
+fn line_at(log: &str, stream: &str, time: &str) -> String {
+    serde_json::json!({
+        "log": log,
+        "stream": stream,
+        "time": time,
+    })
+    .to_string()
+        + "\n"
+}
+
+/// Polls the files once, returns the messages inserted by this poll
+async fn poll(sink: &Sink, tailer: &mut DockerTailer, seen: &mut usize) -> Vec<String> {
+    tailer.poll(sink.app_state()).await.unwrap();
+    let rows = sink.flush().await;
+    let new = rows[*seen..]
+        .iter()
+        .map(|inserted| {
+            assert_eq!(inserted.table, "container_log");
+            inserted.row["message"].as_str().unwrap().to_string()
+        })
+        .collect();
+    *seen = rows.len();
+
+    new
+}
+
+#[tokio::test]
+async fn tails_container_log() {
+    let containers = Containers::new();
+    let sink = containers.sink().await;
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    let mut seen = 0;
+
+    append(
+        &containers.log(),
+        &[
+            line("starting\n", "stdout"),
+            line("first half, ", "stdout"),
+            line("error: no config\n", "stderr"),
+            line("second half\n", "stdout"),
+        ]
+        .concat(),
+    );
+    // incomplete line, still being written
+    append(&containers.log(), &line("ready\n", "stdout")[..20]);
+
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["starting", "error: no config", "first half, second half"]
+    );
+    let row = &sink.flush().await[1].row;
+    assert_eq!(row["container_id"], CONTAINER_ID);
+    assert_eq!(row["container_name"], "web");
+    assert_eq!(row["stream"], "stderr");
+    assert_eq!(row["timestamp"], "2024-04-05T10:00:00.123456Z");
+
+    // the rest of the old file is read after the rotation
+    append(&containers.log(), &line("ready\n", "stdout")[20..]);
+    containers.rotate();
+    append(&containers.log(), &line("after rotation\n", "stdout"));
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["ready", "after rotation"]
+    );
+    assert!(poll(&sink, &mut tailer, &mut seen).await.is_empty());
+}
+
+#[tokio::test]
+async fn continues_after_restart() {
+    let containers = Containers::new();
+    let sink = containers.sink().await;
+    let mut seen = 0;
+
+    append(&containers.log(), &line("before restart\n", "stdout"));
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["before restart"]
+    );
+    drop(tailer);
+
+    append(&containers.log(), &line("while stopped\n", "stdout"));
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(poll(&sink, &mut tailer, &mut seen).await, ["while stopped"]);
+    drop(tailer);
+
+    // rotated while stopped, the rest of the previous file is still read
+    append(&containers.log(), &line("before rotation\n", "stdout"));
+    containers.rotate();
+    append(&containers.log(), &line("new file\n", "stdout"));
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["before rotation", "new file"]
+    );
+}
+
+#[tokio::test]
+async fn retries_failed_inserts() {
+    let containers = Containers::new();
+    let pattern = containers.0.join("*").join("*-json.log");
+    let offsets = containers.0.join("offsets.json");
+    let settings = [
+        ("DOCKER_LOG_PATHS", pattern.to_str().unwrap()),
+        ("DOCKER_OFFSETS_PATH", offsets.to_str().unwrap()),
+    ];
+    let without_table = support::schema::tables()
+        .into_iter()
+        .filter(|(table, _)| *table != "container_log")
+        .collect();
+    let failing = Sink::with_tables(without_table, &settings).await;
+    let sink = Sink::with_settings(&settings).await;
+    let mut seen = 0;
+
+    append(&containers.log(), &line("not inserted yet\n", "stdout"));
+    let mut tailer = DockerTailer::new(&failing.app_state().config())
+        .await
+        .unwrap();
+    tailer.poll(failing.app_state()).await.unwrap();
+    assert!(failing.flush().await.is_empty());
+
+    // neither the tailer nor the saved offsets moved past the lines
+    append(&containers.log(), &line("appended\n", "stdout"));
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["not inserted yet", "appended"]
+    );
+
+    append(&containers.log(), &line("failed again\n", "stdout"));
+    tailer.poll(failing.app_state()).await.unwrap();
+    drop(tailer);
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(poll(&sink, &mut tailer, &mut seen).await, ["failed again"]);
+}
+
+#[tokio::test]
+async fn implausible_timestamps_are_rejected() {
+    let containers = Containers::new();
+    let sink = containers.sink().await;
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    let mut seen = 0;
+
+    append(
+        &containers.log(),
+        &[
+            line_at("no clock yet\n", "stdout", "1970-01-01T00:00:05Z"),
+            line_at("from the future\n", "stdout", "2999-01-01T00:00:00Z"),
+            line("on time\n", "stdout"),
+        ]
+        .concat(),
+    );
+
+    assert_eq!(poll(&sink, &mut tailer, &mut seen).await, ["on time"]);
+}
+
+#[tokio::test]
+async fn joins_partial_message_across_restart() {
+    let containers = Containers::new();
+    let sink = containers.sink().await;
+    let mut seen = 0;
+
+    append(
+        &containers.log(),
+        &[
+            line("first half, ", "stdout"),
+            line("error: no config\n", "stderr"),
+        ]
+        .concat(),
+    );
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["error: no config"]
+    );
+    drop(tailer);
+
+    // the other stream's message after the partial one isn't read again
+    append(&containers.log(), &line("second half\n", "stdout"));
+    let mut tailer = DockerTailer::new(&sink.app_state().config()).await.unwrap();
+    assert_eq!(
+        poll(&sink, &mut tailer, &mut seen).await,
+        ["first half, second half"]
+    );
+}
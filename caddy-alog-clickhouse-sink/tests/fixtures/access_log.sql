@@ -0,0 +1,47 @@
+CREATE TABLE IF NOT EXISTS access_log
+(
+    id UUID COMMENT 'UUIDv7 of the entry, assigned by the sink',
+    service LowCardinality(String) COMMENT 'SERVICE_NAME of the sink',
+    environment LowCardinality(String) COMMENT 'ENVIRONMENT of the sink',
+    sink_instance LowCardinality(String) COMMENT 'INSTANCE_ID of the receiving sink',
+    ingested_at DateTime64(3, 'UTC') CODEC(Delta, ZSTD(1)) COMMENT 'When the sink received the entry',
+    level LowCardinality(String) COMMENT 'Log level',
+    logger_timestamp DateTime64(6, 'UTC') CODEC(Delta, ZSTD(1)) COMMENT 'When Caddy logged the entry',
+    logger LowCardinality(String) COMMENT 'Name of the Caddy logger',
+    message String CODEC(ZSTD(1)) COMMENT 'Log message',
+    remote_ip String CODEC(ZSTD(1)) COMMENT 'IP of the peer',
+    remote_port String COMMENT 'Port of the peer',
+    client_ip Nullable(String) CODEC(ZSTD(1)) COMMENT 'IP of the client, as seen through the trusted proxies',
+    protocol LowCardinality(String) COMMENT 'HTTP protocol version',
+    method LowCardinality(String) COMMENT 'Request method',
+    host LowCardinality(String) COMMENT 'Requested host',
+    uri String CODEC(ZSTD(1)) COMMENT 'Request URI with the query string',
+    headers Map(String, Array(String)) CODEC(ZSTD(1)) COMMENT 'Request headers, except for the promoted ones',
+    bytes_read UInt64 CODEC(T64, ZSTD(1)) COMMENT 'Size of the request body',
+    user_id Nullable(String) COMMENT 'Authenticated user',
+    duration Float64 CODEC(ZSTD(1)) COMMENT 'Request duration, in seconds',
+    size UInt64 CODEC(T64, ZSTD(1)) COMMENT 'Size of the response body',
+    status UInt16 COMMENT 'Response status',
+    response_headers Map(String, Array(String)) CODEC(ZSTD(1)) COMMENT 'Response headers, except for the promoted ones',
+    upstream_addr LowCardinality(Nullable(String)) COMMENT 'Address of the reverse proxy upstream',
+    upstream_status Nullable(UInt16) COMMENT 'Response status of the upstream',
+    upstream_latency Nullable(Float64) COMMENT 'Latency of the upstream, in seconds',
+    trace_id Nullable(String) COMMENT 'W3C trace id',
+    span_id Nullable(String) COMMENT 'W3C parent span id',
+    trace_state Nullable(String) COMMENT 'W3C trace state',
+    request_id Nullable(String) COMMENT 'First of REQUEST_ID_HEADERS present',
+    visitor_key UInt64 COMMENT 'Hash of the host, client IP and User-Agent with a daily salt',
+    lossy_utf8 Bool COMMENT 'Whether invalid UTF-8 of the line was replaced',
+    header_referer String CODEC(ZSTD(1)) COMMENT 'Referer request header, empty if missing',
+    header_user_agent LowCardinality(String) COMMENT 'User-Agent request header, empty if missing',
+    header_content_type LowCardinality(String) COMMENT 'Content-Type request header, empty if missing',
+    header_accept_language LowCardinality(String) COMMENT 'Accept-Language request header, empty if missing',
+    header_x_forwarded_for String CODEC(ZSTD(1)) COMMENT 'X-Forwarded-For request header, empty if missing',
+    response_header_content_type LowCardinality(String) COMMENT 'Content-Type response header, empty if missing',
+    response_header_cache_control LowCardinality(String) COMMENT 'Cache-Control response header, empty if missing',
+    INDEX idx_trace_id trace_id TYPE bloom_filter(0.01) GRANULARITY 4,
+    INDEX idx_request_id request_id TYPE bloom_filter(0.01) GRANULARITY 4
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(logger_timestamp)
+ORDER BY (service, environment, host, logger_timestamp);
@@ -0,0 +1,81 @@
+//! Access to the endpoints of the HTTP server
+
+mod support;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use caddy_alog_clickhouse_sink::http;
+use reqwest::StatusCode;
+use tokio::net::{TcpListener, TcpStream};
+
+use support::Sink;
+
+/// Starts the HTTP server of the sink on a free port, returns its base URL
+async fn serve(sink: &Sink) -> String {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let app_state = Arc::clone(sink.app_state());
+    tokio::spawn(async move { http::serve(app_state, &address.to_string()).await });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect(address).await.is_err() {
+        assert!(Instant::now() < deadline, "HTTP server didn't start");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    format!("http://{address}")
+}
+
+async fn status(url: &str, credentials: Option<(&str, &str)>) -> StatusCode {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some((user, password)) = credentials {
+        request = request.basic_auth(user, Some(password));
+    }
+
+    request.send().await.unwrap().status()
+}
+
+#[tokio::test]
+async fn dashboard_credentials_protect_tail_and_metrics() {
+    let sink = Sink::with_settings(&[
+        ("DASHBOARD_USER", "admin"),
+        ("DASHBOARD_PASSWORD", "secret"),
+    ])
+    .await;
+    let base = serve(&sink).await;
+
+    for path in ["/metrics", "/tail", "/dashboard"] {
+        let url = format!("{base}{path}");
+        assert_eq!(status(&url, None).await, StatusCode::UNAUTHORIZED, "{path}");
+        assert_eq!(
+            status(&url, Some(("admin", "wrong"))).await,
+            StatusCode::UNAUTHORIZED,
+            "{path}"
+        );
+    }
+    assert_eq!(
+        status(&format!("{base}/metrics"), Some(("admin", "secret"))).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn open_without_dashboard() {
+    let sink = Sink::start().await;
+    let base = serve(&sink).await;
+
+    assert_eq!(
+        status(&format!("{base}/metrics"), None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(&format!("{base}/dashboard"), None).await,
+        StatusCode::NOT_FOUND
+    );
+}
@@ -0,0 +1,136 @@
+//! End-to-end ingestion of Caddy logs: lines sent over TCP to the sink's stream handler,
+//! rows decoded by a fake Clickhouse server.
+//!
+//! Each test ingests `tests/fixtures/<name>.jsonl` and compares the inserted rows with
+//! `tests/fixtures/<name>.rows.json`. After intended changes of the stored rows,
+//! rewrite the expectations with `UPDATE_EXPECT=1 cargo test --test ingest` and review the diff.
+
+mod support;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use support::{assert_ingested, fake_clickhouse::InsertedRow, fixture, Sink};
+
+/// Plain HTTP/1.1 requests, a reverse proxied one with the upstream fields
+#[tokio::test]
+async fn http1() {
+    assert_ingested("http1").await;
+}
+
+/// HTTP/2 over TLS, with trace context and request id headers
+#[tokio::test]
+async fn http2() {
+    assert_ingested("http2").await;
+}
+
+/// HTTP/3 (QUIC), behind a trusted proxy
+#[tokio::test]
+async fn http3() {
+    assert_ingested("http3").await;
+}
+
+/// Certificate management and handshake logs, resumed TLS 1.2 and 1.3 sessions
+#[tokio::test]
+async fn tls() {
+    assert_ingested("tls").await;
+}
+
+/// Handler errors with their access log entries, and lines that are rejected
+#[tokio::test]
+async fn errors() {
+    assert_ingested("errors").await;
+}
+
+/// Lines over the per peer limit are dropped, within its one second burst
+#[tokio::test]
+async fn rate_limit_drop() {
+    let sink = Sink::with_settings(&[
+        ("PEER_MAX_LINES_PER_SEC", "2"),
+        ("RATE_LIMIT_ACTION", "drop"),
+    ])
+    .await;
+
+    let rows = sink.ingest(&fixture("http1")).await;
+    assert_eq!(rows.len(), 2, "rows within the burst: {rows:?}");
+}
+
+/// Reading waits for the global limit, nothing is lost
+#[tokio::test]
+async fn rate_limit_backpressure() {
+    let sink = Sink::with_settings(&[("MAX_LINES_PER_SEC", "2")]).await;
+
+    let started = Instant::now();
+    let rows = sink.ingest(&fixture("http1")).await;
+    assert_eq!(rows.len(), 4);
+    // 2 lines in the burst, each next one a half a second later
+    assert!(
+        started.elapsed() >= Duration::from_millis(900),
+        "{:?}",
+        started.elapsed()
+    );
+}
+
+/// A timed out insert isn't retried, as the server may have taken its rows already,
+/// and its connection is discarded
+#[tokio::test]
+async fn insert_timeout() {
+    let sink = Sink::with_settings(&[
+        ("CH_INSERT_TIMEOUT_MS", "200"),
+        ("CH_MAX_RETRIES", "3"),
+        ("CH_RETRY_BACKOFF_MS", "10"),
+    ])
+    .await;
+    let lines = fixture("http1");
+    let mut lines = lines.split_inclusive(|byte| *byte == b'\n');
+
+    sink.clickhouse().stall_next_insert(Duration::from_secs(2));
+    let rows = sink.ingest(lines.next().unwrap()).await;
+    assert!(rows.is_empty(), "timed out insert was retried: {rows:?}");
+
+    let rows = sink.ingest(lines.next().unwrap()).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(sink.clickhouse().connections(), 2);
+}
+
+/// Visitors are keyed with the salt of the day the entry was logged on, which is dropped
+/// once the day's entries may no longer arrive
+#[tokio::test]
+async fn visitor_keys_follow_the_log_day() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64();
+    let line = fixture("http1");
+    let line = line.split(|byte| *byte == b'\n').next().unwrap();
+    // the same request of the same visitor, logged now, now and two days ago
+    let lines = [now, now, now - 2.0 * 86_400.0]
+        .into_iter()
+        .flat_map(|ts| {
+            let mut entry = serde_json::from_slice::<Value>(line).unwrap();
+            entry["ts"] = ts.into();
+            serde_json::to_vec(&entry)
+                .unwrap()
+                .into_iter()
+                .chain([b'\n'])
+        })
+        .collect::<Vec<_>>();
+
+    let visitor_keys = |rows: Vec<InsertedRow>| {
+        rows.iter()
+            .map(|inserted| inserted.row["visitor_key"].as_u64().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let sink = Sink::with_settings(&[("VISITOR_MAX_LAG_SECS", "259200")]).await;
+    let keys = visitor_keys(sink.ingest(&lines).await);
+    assert_eq!(keys.len(), 3);
+    assert_eq!(keys[0], keys[1]);
+    assert_ne!(keys[0], keys[2]);
+    assert!(keys.iter().all(|key| *key != 0), "{keys:?}");
+
+    let sink = Sink::with_settings(&[("VISITOR_MAX_LAG_SECS", "0")]).await;
+    let keys = visitor_keys(sink.ingest(&lines).await);
+    assert!(keys[0] != 0 && keys[0] == keys[1], "{keys:?}");
+    assert_eq!(keys[2], 0, "key of the dropped day");
+}
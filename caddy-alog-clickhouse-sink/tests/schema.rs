@@ -0,0 +1,128 @@
+//! Expected schema of `access_log`, derived from the row type, and its check against live tables.
+//!
+//! The fake Clickhouse serves the tables `support::schema` builds by applying `migrations/*.sql`,
+//! so the check also verifies the generated types against the migrations. The generated statement
+//! is compared with `tests/fixtures/access_log.sql`, rewrite it with
+//! `UPDATE_EXPECT=1 cargo test --test schema` after intended changes.
+
+mod support;
+
+use std::path::PathBuf;
+
+use caddy_alog_clickhouse_sink::{
+    log::db::DbAccessLogEntry,
+    schema::{self, SchemaMismatch},
+};
+use klickhouse::Row;
+
+use support::Sink;
+
+/// Every field of the row has a column, in the order of the fields
+#[tokio::test]
+async fn columns_follow_the_row() {
+    let sink = Sink::start().await;
+    let schema = schema::access_log(&sink.app_state().config());
+
+    let fields = DbAccessLogEntry::column_names().expect("row has named columns");
+    let columns = schema.columns().take(fields.len()).collect::<Vec<_>>();
+    assert_eq!(columns, fields);
+}
+
+/// Statement with the default promoted headers
+#[tokio::test]
+async fn create_table() {
+    let sink = Sink::start().await;
+    let actual = schema::access_log(&sink.app_state().config()).create_table("access_log");
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/access_log.sql");
+    if std::env::var_os("UPDATE_EXPECT").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        expected,
+        actual,
+        "statement differs from {}",
+        path.display()
+    );
+}
+
+/// Tables created by the migrations match the generated schema
+#[tokio::test]
+async fn live_tables_match() {
+    let sink = Sink::start().await;
+    let app_state = sink.app_state();
+
+    let mismatches = schema::check_tables(app_state.clickhouse(), &app_state.config())
+        .await
+        .unwrap();
+    assert!(mismatches.is_empty(), "{mismatches:?}");
+}
+
+#[tokio::test]
+async fn missing_and_mistyped_columns() {
+    let columns = support::schema::columns("access_log")
+        .iter()
+        .filter(|(name, _)| *name != "visitor_key")
+        .map(|&(name, type_name)| match name {
+            "status" => (name, "UInt32"),
+            "host" => (name, "String"),
+            _ => (name, type_name),
+        })
+        .collect::<Vec<_>>();
+    let sink = Sink::with_tables(vec![("access_log", columns.leak())], &[]).await;
+    let app_state = sink.app_state();
+
+    let mismatches = schema::check_tables(app_state.clickhouse(), &app_state.config())
+        .await
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mismatches,
+        [
+            "Column host of access_log is String, expected LowCardinality(String)",
+            "Column status of access_log is UInt32, expected UInt16",
+            "Column visitor_key (UInt64) is missing in access_log",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn missing_table() {
+    let sink = Sink::with_tables(Vec::new(), &[]).await;
+    let app_state = sink.app_state();
+
+    let mismatches = schema::check_tables(app_state.clickhouse(), &app_state.config())
+        .await
+        .unwrap();
+    assert!(
+        matches!(&mismatches[..], [SchemaMismatch::Table(table)] if table == "access_log"),
+        "{mismatches:?}"
+    );
+}
+
+#[tokio::test]
+async fn reload_refuses_promoted_headers_without_columns() {
+    let sink = Sink::start().await;
+    let app_state = sink.app_state();
+
+    let error = app_state
+        .reload(sink.config(&[("PROMOTED_HEADERS", "User-Agent,X-Tenant")]))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("header_x_tenant"), "{error:?}");
+    assert!(!app_state
+        .config()
+        .promoted_headers()
+        .contains(&"X-Tenant".to_string()));
+
+    // dropping a promoted header only leaves a column unused
+    app_state
+        .reload(sink.config(&[("PROMOTED_HEADERS", "User-Agent")]))
+        .await
+        .unwrap();
+    assert_eq!(app_state.config().promoted_headers(), &["User-Agent"]);
+}
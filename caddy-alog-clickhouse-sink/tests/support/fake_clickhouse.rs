@@ -0,0 +1,776 @@
+//! In-process stand-in for a Clickhouse server, speaking just enough of the native protocol
+//! for the sink: the handshake, inserts in the Native format, statements (without any effect),
+//! the `select ''` the connection pool checks its connections with and the columns of the tables
+//! from `system.columns`.
+//!
+//! Inserted blocks are decoded into JSON rows, so that tests can assert what reaches
+//! the database. Inserts into unknown tables fail like on a real server, an insert can
+//! be stalled to make the client time out.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use serde_json::{Map, Value};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// Protocol revision of the server, the one `klickhouse` speaks
+const REVISION: u64 = 54448;
+
+const CLIENT_HELLO: u64 = 0;
+const CLIENT_QUERY: u64 = 1;
+const CLIENT_DATA: u64 = 2;
+const CLIENT_PING: u64 = 4;
+
+const SERVER_HELLO: u64 = 0;
+const SERVER_DATA: u64 = 1;
+const SERVER_EXCEPTION: u64 = 2;
+const SERVER_PONG: u64 = 4;
+const SERVER_END_OF_STREAM: u64 = 5;
+
+const LZ4: u8 = 0x82;
+/// Method byte and the two sizes, which precede the compressed payload
+const COMPRESSED_HEADER_SIZE: usize = 9;
+
+const UNKNOWN_TABLE: i32 = 60;
+
+/// Names and types of the columns of a table
+pub type Columns = &'static [(&'static str, &'static str)];
+
+/// Row inserted into a table
+#[derive(Debug, Clone)]
+pub struct InsertedRow {
+    /// As written in the query, with the database if there's one
+    pub table: String,
+    pub row: Map<String, Value>,
+}
+
+pub struct FakeClickhouse {
+    address: SocketAddr,
+    rows: Arc<Mutex<Vec<InsertedRow>>>,
+    stall: Arc<Mutex<Option<Duration>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl FakeClickhouse {
+    /// Listens on a random local port, serving the tables (by name, in any database)
+    pub async fn start(tables: Vec<(&'static str, Columns)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let stall = Arc::new(Mutex::new(None));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let tables = Arc::new(tables.into_iter().collect::<HashMap<_, _>>());
+
+        {
+            let rows = Arc::clone(&rows);
+            let stall = Arc::clone(&stall);
+            let connections = Arc::clone(&connections);
+            tokio::spawn(async move {
+                loop {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    socket.set_nodelay(true).unwrap();
+                    connections.fetch_add(1, Ordering::Relaxed);
+                    let session = Session {
+                        socket: BufReader::new(socket),
+                        tables: Arc::clone(&tables),
+                        rows: Arc::clone(&rows),
+                        stall: Arc::clone(&stall),
+                    };
+                    tokio::spawn(async move {
+                        if let Err(e) = session.run().await {
+                            if e.kind() != io::ErrorKind::UnexpectedEof {
+                                panic!("Fake Clickhouse session failed: {e}");
+                            }
+                        }
+                    });
+                }
+            });
+        }
+
+        Self {
+            address,
+            rows,
+            stall,
+            connections,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Rows of all the inserts so far, in the order they were received
+    pub fn rows(&self) -> Vec<InsertedRow> {
+        self.rows.lock().unwrap().clone()
+    }
+
+    /// Answers the next insert only after the delay
+    pub fn stall_next_insert(&self, delay: Duration) {
+        *self.stall.lock().unwrap() = Some(delay);
+    }
+
+    /// Number of the connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+}
+
+/// Client connection
+struct Session {
+    socket: BufReader<TcpStream>,
+    tables: Arc<HashMap<&'static str, Columns>>,
+    rows: Arc<Mutex<Vec<InsertedRow>>>,
+    stall: Arc<Mutex<Option<Duration>>>,
+}
+
+impl Session {
+    async fn run(mut self) -> io::Result<()> {
+        self.handshake().await?;
+
+        loop {
+            match read_var_uint(&mut self.socket).await? {
+                CLIENT_QUERY => {
+                    let query = self.read_query().await?;
+                    self.handle_query(query.trim()).await?;
+                }
+                CLIENT_PING => self.write(&var_uint(SERVER_PONG)).await?,
+                packet => return Err(protocol_error(format!("unexpected packet {packet}"))),
+            }
+        }
+    }
+
+    async fn handshake(&mut self) -> io::Result<()> {
+        let packet = read_var_uint(&mut self.socket).await?;
+        if packet != CLIENT_HELLO {
+            return Err(protocol_error(format!(
+                "expected hello, got packet {packet}"
+            )));
+        }
+        // client name, version, revision, database, user and password
+        read_string(&mut self.socket).await?;
+        for _ in 0..3 {
+            read_var_uint(&mut self.socket).await?;
+        }
+        for _ in 0..3 {
+            read_string(&mut self.socket).await?;
+        }
+
+        let mut hello = var_uint(SERVER_HELLO);
+        put_string(&mut hello, "ClickHouse");
+        put_var_uint(&mut hello, 24);
+        put_var_uint(&mut hello, 3);
+        put_var_uint(&mut hello, REVISION);
+        // time zone, display name and patch version
+        put_string(&mut hello, "UTC");
+        put_string(&mut hello, "fake-clickhouse");
+        put_var_uint(&mut hello, 0);
+
+        self.write(&hello).await
+    }
+
+    /// Reads the query packet and the empty block of external tables following it
+    async fn read_query(&mut self) -> io::Result<String> {
+        let socket = &mut self.socket;
+        // query id
+        read_string(socket).await?;
+
+        // client info: kind, initial user, query id and address, interface, OS user,
+        // host and client name, version, revision, quota key, distributed depth, patch version
+        // and OpenTelemetry context (never sent)
+        socket.read_u8().await?;
+        for _ in 0..3 {
+            read_string(socket).await?;
+        }
+        socket.read_u8().await?;
+        for _ in 0..3 {
+            read_string(socket).await?;
+        }
+        for _ in 0..3 {
+            read_var_uint(socket).await?;
+        }
+        read_string(socket).await?;
+        read_var_uint(socket).await?;
+        read_var_uint(socket).await?;
+        if socket.read_u8().await? != 0 {
+            return Err(protocol_error("unexpected OpenTelemetry context"));
+        }
+
+        // settings (terminated by an empty name), interserver secret, stage and compression
+        if !read_string(socket).await?.is_empty() {
+            return Err(protocol_error("unexpected query settings"));
+        }
+        read_string(socket).await?;
+        read_var_uint(socket).await?;
+        if socket.read_u8().await? != 1 {
+            return Err(protocol_error("expected compressed data"));
+        }
+        let query = read_string(socket).await?;
+
+        if self.read_data().await?.rows != 0 {
+            return Err(protocol_error("unexpected external tables"));
+        }
+
+        Ok(query)
+    }
+
+    async fn handle_query(&mut self, query: &str) -> io::Result<()> {
+        let lowercase = query.to_ascii_lowercase();
+
+        if let Some(rest) = lowercase.strip_prefix("insert into ") {
+            let table =
+                query["insert into ".len()..][..rest.find(' ').unwrap_or(rest.len())].to_string();
+            return self.handle_insert(table).await;
+        }
+
+        if lowercase.contains("from system.columns") {
+            return self.handle_columns(query).await;
+        }
+
+        if lowercase.starts_with("select") {
+            // the other select of the sink is the check of the pooled connections, `select ''`
+            let mut block = Vec::new();
+            put_block_header(&mut block, 1, 1);
+            put_string(&mut block, "''");
+            put_string(&mut block, "String");
+            put_string(&mut block, "");
+            self.write_data(&block).await?;
+        }
+
+        self.write(&var_uint(SERVER_END_OF_STREAM)).await
+    }
+
+    /// Names and types of the columns of the table in `table = '<name>'`, for the schema check
+    async fn handle_columns(&mut self, query: &str) -> io::Result<()> {
+        let name = query
+            .split_once("table = '")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map(|(name, _)| name)
+            .ok_or_else(|| protocol_error(format!("no table in {query}")))?;
+
+        if let Some(columns) = self.tables.get(name).copied() {
+            let mut block = Vec::new();
+            put_block_header(&mut block, 2, columns.len() as u64);
+            let names = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            let types = columns.iter().map(|(_, type_name)| *type_name).collect();
+            for (column, values) in [("name", names), ("type", types)] {
+                put_string(&mut block, column);
+                put_string(&mut block, "String");
+                for value in values {
+                    put_string(&mut block, value);
+                }
+            }
+            self.write_data(&block).await?;
+        }
+
+        self.write(&var_uint(SERVER_END_OF_STREAM)).await
+    }
+
+    async fn handle_insert(&mut self, table: String) -> io::Result<()> {
+        let name = table.rsplit('.').next().unwrap_or(&table);
+        let Some(columns) = self.tables.get(name).copied() else {
+            return self
+                .write_exception(UNKNOWN_TABLE, &format!("Table {table} does not exist"))
+                .await;
+        };
+
+        let stall = self.stall.lock().unwrap().take();
+        if let Some(delay) = stall {
+            tokio::time::sleep(delay).await;
+        }
+
+        // the client serializes the rows according to the columns of the first block
+        let mut header = Vec::new();
+        put_block_header(&mut header, columns.len() as u64, 0);
+        for (name, type_name) in columns {
+            put_string(&mut header, name);
+            put_string(&mut header, type_name);
+        }
+        self.write_data(&header).await?;
+
+        loop {
+            let block = self.read_data().await?;
+            if block.columns.is_empty() {
+                break;
+            }
+
+            let mut rows = vec![Map::new(); block.rows];
+            for (name, values) in block.columns {
+                if !columns.iter().any(|(column, _)| *column == name) {
+                    return Err(protocol_error(format!("no column {name} in {table}")));
+                }
+                for (row, value) in rows.iter_mut().zip(values) {
+                    row.insert(name.clone(), value);
+                }
+            }
+
+            self.rows
+                .lock()
+                .unwrap()
+                .extend(rows.into_iter().map(|row| InsertedRow {
+                    table: table.clone(),
+                    row,
+                }));
+        }
+
+        self.write(&var_uint(SERVER_END_OF_STREAM)).await
+    }
+
+    async fn read_data(&mut self) -> io::Result<Block> {
+        let packet = read_var_uint(&mut self.socket).await?;
+        if packet != CLIENT_DATA {
+            return Err(protocol_error(format!(
+                "expected data, got packet {packet}"
+            )));
+        }
+        // temporary table name
+        read_string(&mut self.socket).await?;
+
+        let mut checksum = [0; 16];
+        self.socket.read_exact(&mut checksum).await?;
+        let mut compressed = vec![0; COMPRESSED_HEADER_SIZE];
+        self.socket.read_exact(&mut compressed).await?;
+        let compressed_size = u32::from_le_bytes(compressed[1..5].try_into().unwrap()) as usize;
+        let decompressed_size = u32::from_le_bytes(compressed[5..9].try_into().unwrap());
+        if compressed[0] != LZ4 || compressed_size < COMPRESSED_HEADER_SIZE {
+            return Err(protocol_error("invalid compressed block header"));
+        }
+        compressed.resize(compressed_size, 0);
+        self.socket
+            .read_exact(&mut compressed[COMPRESSED_HEADER_SIZE..])
+            .await?;
+
+        let hash = cityhash_rs::cityhash_102_128(&compressed);
+        if checksum[..8] != ((hash >> 64) as u64).to_le_bytes()
+            || checksum[8..] != (hash as u64).to_le_bytes()
+        {
+            return Err(protocol_error("invalid block checksum"));
+        }
+        let raw = lz4::block::decompress(
+            &compressed[COMPRESSED_HEADER_SIZE..],
+            Some(decompressed_size as i32),
+        )?;
+
+        Block::decode(&mut Cursor(&raw))
+    }
+
+    /// Sends a data packet with the encoded block
+    async fn write_data(&mut self, block: &[u8]) -> io::Result<()> {
+        let payload = lz4::block::compress(block, None, false)?;
+        let mut compressed = vec![LZ4];
+        compressed.extend(((payload.len() + COMPRESSED_HEADER_SIZE) as u32).to_le_bytes());
+        compressed.extend((block.len() as u32).to_le_bytes());
+        compressed.extend(payload);
+        let hash = cityhash_rs::cityhash_102_128(&compressed);
+
+        let mut packet = var_uint(SERVER_DATA);
+        put_string(&mut packet, "");
+        packet.extend(((hash >> 64) as u64).to_le_bytes());
+        packet.extend((hash as u64).to_le_bytes());
+        packet.extend(compressed);
+
+        self.write(&packet).await
+    }
+
+    async fn write_exception(&mut self, code: i32, message: &str) -> io::Result<()> {
+        let mut packet = var_uint(SERVER_EXCEPTION);
+        packet.extend(code.to_le_bytes());
+        put_string(&mut packet, "DB::Exception");
+        put_string(&mut packet, message);
+        // stack trace and whether there's a nested exception
+        put_string(&mut packet, "");
+        packet.push(0);
+
+        self.write(&packet).await
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let socket = self.socket.get_mut();
+        socket.write_all(bytes).await?;
+        socket.flush().await
+    }
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+async fn read_var_uint(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8().await?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    Ok(value)
+}
+
+async fn read_string(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<String> {
+    let len = read_var_uint(reader).await?;
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).await?;
+
+    String::from_utf8(bytes).map_err(|e| protocol_error(e.to_string()))
+}
+
+fn var_uint(value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    put_var_uint(&mut bytes, value);
+    bytes
+}
+
+fn put_var_uint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn put_string(bytes: &mut Vec<u8>, value: &str) {
+    put_var_uint(bytes, value.len() as u64);
+    bytes.extend(value.as_bytes());
+}
+
+/// Block info (not an overflow, no bucket) and the dimensions
+fn put_block_header(bytes: &mut Vec<u8>, columns: u64, rows: u64) {
+    put_var_uint(bytes, 1);
+    bytes.push(0);
+    put_var_uint(bytes, 2);
+    bytes.extend((-1i32).to_le_bytes());
+    put_var_uint(bytes, 0);
+    put_var_uint(bytes, columns);
+    put_var_uint(bytes, rows);
+}
+
+/// Decoded block, the values of each column
+struct Block {
+    rows: usize,
+    columns: Vec<(String, Vec<Value>)>,
+}
+
+impl Block {
+    fn decode(cursor: &mut Cursor<'_>) -> io::Result<Self> {
+        // block info fields, until the terminating 0
+        loop {
+            match cursor.var_uint()? {
+                0 => break,
+                1 => {
+                    cursor.take(1)?;
+                }
+                2 => {
+                    cursor.take(4)?;
+                }
+                field => return Err(protocol_error(format!("unknown block info field {field}"))),
+            }
+        }
+
+        let column_count = cursor.var_uint()?;
+        let rows = cursor.var_uint()? as usize;
+        let mut columns = Vec::new();
+        for _ in 0..column_count {
+            let name = cursor.string()?;
+            let type_ = Type::parse(&cursor.string()?)?;
+            let values = if rows > 0 {
+                type_.decode_prefix(cursor)?;
+                type_.decode(cursor, rows)?
+            } else {
+                Vec::new()
+            };
+            columns.push((name, values));
+        }
+
+        Ok(Self { rows, columns })
+    }
+}
+
+/// Column types of the sink's tables
+#[derive(Debug)]
+enum Type {
+    String,
+    Uuid,
+    UInt(usize),
+    Float64,
+    DateTime64(u32),
+    Nullable(Box<Type>),
+    LowCardinality(Box<Type>),
+    Array(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+}
+
+impl Type {
+    fn parse(name: &str) -> io::Result<Self> {
+        let name = name.trim();
+        let (outer, inner) = match name.split_once('(') {
+            Some((outer, inner)) => (outer, inner.strip_suffix(')').unwrap_or(inner)),
+            None => (name, ""),
+        };
+        let boxed = |name| Self::parse(name).map(Box::new);
+
+        Ok(match outer {
+            "String" => Self::String,
+            "UUID" => Self::Uuid,
+            "UInt8" | "Bool" => Self::UInt(1),
+            "UInt16" => Self::UInt(2),
+            "UInt32" => Self::UInt(4),
+            "UInt64" => Self::UInt(8),
+            "Float64" => Self::Float64,
+            "DateTime64" => Self::DateTime64(
+                inner
+                    .split(',')
+                    .next()
+                    .and_then(|precision| precision.trim().parse().ok())
+                    .ok_or_else(|| protocol_error(format!("invalid type {name}")))?,
+            ),
+            "Nullable" => Self::Nullable(boxed(inner)?),
+            "LowCardinality" => Self::LowCardinality(boxed(inner)?),
+            "Array" => Self::Array(boxed(inner)?),
+            "Map" => {
+                let (key, value) = split_top_level(inner)
+                    .ok_or_else(|| protocol_error(format!("invalid type {name}")))?;
+                Self::Map(boxed(key)?, boxed(value)?)
+            }
+            _ => return Err(protocol_error(format!("unsupported type {name}"))),
+        })
+    }
+
+    /// Reads the column prefix, only low cardinality columns have one
+    fn decode_prefix(&self, cursor: &mut Cursor<'_>) -> io::Result<()> {
+        match self {
+            Self::LowCardinality(_) => {
+                let version = cursor.u64()?;
+                if version != 1 {
+                    return Err(protocol_error(format!(
+                        "unknown dictionary version {version}"
+                    )));
+                }
+                Ok(())
+            }
+            Self::Nullable(inner) | Self::Array(inner) => inner.decode_prefix(cursor),
+            Self::Map(key, value) => {
+                key.decode_prefix(cursor)?;
+                value.decode_prefix(cursor)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn decode(&self, cursor: &mut Cursor<'_>, rows: usize) -> io::Result<Vec<Value>> {
+        match self {
+            Self::Nullable(_) | Self::LowCardinality(_) | Self::Array(_) | Self::Map(_, _) => {
+                self.decode_column(cursor, rows)
+            }
+            _ => (0..rows).map(|_| self.decode_value(cursor)).collect(),
+        }
+    }
+
+    /// Value of a fixed size column, or a string
+    fn decode_value(&self, cursor: &mut Cursor<'_>) -> io::Result<Value> {
+        Ok(match self {
+            Self::String => Value::String(cursor.string()?),
+            Self::Uuid => {
+                let high = cursor.u64()?;
+                let low = cursor.u64()?;
+                Value::String(uuid::Uuid::from_u64_pair(high, low).to_string())
+            }
+            Self::UInt(size) => {
+                let mut bytes = [0; 8];
+                bytes[..*size].copy_from_slice(cursor.take(*size)?);
+                Value::from(u64::from_le_bytes(bytes))
+            }
+            Self::Float64 => Value::from(f64::from_bits(cursor.u64()?)),
+            Self::DateTime64(precision) => {
+                Value::String(format_datetime64(cursor.u64()?, *precision))
+            }
+            _ => unreachable!("columns of a variable size per row are decoded as a whole"),
+        })
+    }
+
+    fn decode_column(&self, cursor: &mut Cursor<'_>, rows: usize) -> io::Result<Vec<Value>> {
+        match self {
+            Self::Nullable(inner) => {
+                let nulls = cursor.take(rows)?.to_vec();
+                let values = inner.decode(cursor, rows)?;
+
+                Ok(nulls
+                    .into_iter()
+                    .zip(values)
+                    .map(|(null, value)| if null == 0 { value } else { Value::Null })
+                    .collect())
+            }
+            Self::LowCardinality(inner) => {
+                let flags = cursor.u64()?;
+                let index_size = 1 << (flags & 0xff);
+                let key_count = cursor.u64()? as usize;
+                let (nullable, key_type) = match &**inner {
+                    Self::Nullable(key_type) => (true, &**key_type),
+                    key_type => (false, key_type),
+                };
+                let keys = key_type.decode(cursor, key_count)?;
+
+                if cursor.u64()? as usize != rows {
+                    return Err(protocol_error("low cardinality row count mismatch"));
+                }
+                (0..rows)
+                    .map(|_| {
+                        let mut bytes = [0; 8];
+                        bytes[..index_size].copy_from_slice(cursor.take(index_size)?);
+                        let index = u64::from_le_bytes(bytes) as usize;
+                        // the first key of a nullable column stands for null
+                        if nullable && index == 0 {
+                            return Ok(Value::Null);
+                        }
+                        keys.get(index)
+                            .cloned()
+                            .ok_or_else(|| protocol_error("dictionary index out of range"))
+                    })
+                    .collect()
+            }
+            Self::Array(inner) => {
+                let offsets = cursor.offsets(rows)?;
+                let items = inner.decode(cursor, offsets.last().copied().unwrap_or(0))?;
+
+                Ok(split_offsets(&offsets, items)
+                    .into_iter()
+                    .map(Value::Array)
+                    .collect())
+            }
+            Self::Map(key, value) => {
+                let offsets = cursor.offsets(rows)?;
+                let total = offsets.last().copied().unwrap_or(0);
+                let keys = key.decode(cursor, total)?;
+                let values = value.decode(cursor, total)?;
+
+                Ok(split_offsets(&offsets, keys)
+                    .into_iter()
+                    .zip(split_offsets(&offsets, values))
+                    .map(|(keys, values)| {
+                        Value::Object(
+                            keys.into_iter()
+                                .map(|key| match key {
+                                    Value::String(key) => key,
+                                    key => key.to_string(),
+                                })
+                                .zip(values)
+                                .collect(),
+                        )
+                    })
+                    .collect())
+            }
+            _ => unreachable!("fixed size columns are decoded by values"),
+        }
+    }
+}
+
+/// Splits `Map(K, V)` arguments at the comma, which isn't nested in parentheses
+fn split_top_level(arguments: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => return Some((&arguments[..i], &arguments[i + 1..])),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Items of every row, by the cumulative offsets of the rows' ends
+fn split_offsets(offsets: &[usize], items: Vec<Value>) -> Vec<Vec<Value>> {
+    let mut items = items.into_iter();
+    let mut start = 0;
+
+    offsets
+        .iter()
+        .map(|&end| {
+            let row = items.by_ref().take(end - start).collect();
+            start = end;
+            row
+        })
+        .collect()
+}
+
+/// `2024-04-05T19:34:38.016192Z`, with as many fractional digits as the precision
+fn format_datetime64(ticks: u64, precision: u32) -> String {
+    let scale = 10u64.pow(precision);
+    let time = time::OffsetDateTime::from_unix_timestamp((ticks / scale) as i64)
+        .expect("timestamp in range");
+    let date = time.date();
+
+    let mut formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    if precision > 0 {
+        formatted.push_str(&format!(
+            ".{:0width$}",
+            ticks % scale,
+            width = precision as usize
+        ));
+    }
+    formatted.push('Z');
+
+    formatted
+}
+
+/// Reader of a decompressed block
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn var_uint(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.var_uint()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    /// Cumulative offsets of array (or map) items
+    fn offsets(&mut self, rows: usize) -> io::Result<Vec<usize>> {
+        (0..rows).map(|_| Ok(self.u64()? as usize)).collect()
+    }
+}
@@ -0,0 +1,216 @@
+//! Harness of the ingestion tests: the sink's stream handler, inserting into a fake Clickhouse
+
+// every test crate includes the module, but uses only a part of it
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use caddy_alog_clickhouse_sink::{app_state::AppState, config::Config, handlers};
+use serde_json::{json, Value};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+};
+
+use self::fake_clickhouse::{Columns, FakeClickhouse, InsertedRow};
+
+pub mod fake_clickhouse;
+pub mod schema;
+
+/// Columns, which differ on every run, replaced by placeholders in the expected rows
+const VOLATILE_COLUMNS: &[&str] = &["id", "ingested_at", "visitor_key"];
+
+/// The configuration is read from the environment, which the tests share
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Configuration of a sink inserting into `clickhouse`, with additional settings
+fn config(clickhouse: SocketAddr, settings: &[(&str, &str)]) -> Config {
+    let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+
+    std::env::remove_var("CONFIG_PATH");
+    for (key, value) in [
+        ("BIND_TO", "127.0.0.1:0"),
+        ("CH_HOSTS", &clickhouse.to_string()),
+        ("CH_USER", "default"),
+        ("CH_PASSWORD", ""),
+        ("CH_DATABASE", "default"),
+        // a single connection serializes the queries, see `Sink::ingest`
+        ("CH_POOL_SIZE", "1"),
+        ("CH_MAX_RETRIES", "0"),
+        ("SERVICE_NAME", "caddy"),
+        ("ENVIRONMENT", "test"),
+        ("INSTANCE_ID", "sink-test"),
+        // the fixtures were logged long ago, their entries still get visitor keys
+        ("VISITOR_MAX_LAG_SECS", "1000000000"),
+    ] {
+        std::env::set_var(key, value);
+    }
+    for (key, value) in settings {
+        std::env::set_var(key, value);
+    }
+
+    let config = Config::load().expect("test config is valid");
+    for (key, _) in settings {
+        std::env::remove_var(key);
+    }
+
+    config
+}
+
+/// Sink with its own fake Clickhouse
+pub struct Sink {
+    app_state: Arc<AppState>,
+    clickhouse: FakeClickhouse,
+}
+
+impl Sink {
+    pub async fn start() -> Self {
+        Self::with_settings(&[]).await
+    }
+
+    /// Sink with settings (environment variables) on top of the defaults of the tests
+    pub async fn with_settings(settings: &[(&str, &str)]) -> Self {
+        Self::with_tables(schema::tables(), settings).await
+    }
+
+    /// Sink inserting into a fake Clickhouse with the tables (name and columns)
+    pub async fn with_tables(
+        tables: Vec<(&'static str, Columns)>,
+        settings: &[(&str, &str)],
+    ) -> Self {
+        let clickhouse = FakeClickhouse::start(tables).await;
+        let app_state = AppState::new(config(clickhouse.address(), settings))
+            .await
+            .expect("sink starts");
+
+        Self {
+            app_state: Arc::new(app_state),
+            clickhouse,
+        }
+    }
+
+    /// Configuration of this sink with other settings, e.g. to reload it
+    pub fn config(&self, settings: &[(&str, &str)]) -> Config {
+        config(self.clickhouse.address(), settings)
+    }
+
+    pub fn clickhouse(&self) -> &FakeClickhouse {
+        &self.clickhouse
+    }
+
+    pub fn app_state(&self) -> &Arc<AppState> {
+        &self.app_state
+    }
+
+    /// Sends the bytes over a connection and waits until everything read from it
+    /// reached the database
+    pub async fn ingest(&self, bytes: &[u8]) -> Vec<InsertedRow> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, peer) = listener.accept().await.unwrap();
+        let handler = tokio::spawn(handlers::handle_stream(
+            Arc::clone(&self.app_state),
+            socket,
+            peer,
+        ));
+
+        client.write_all(bytes).await.unwrap();
+        client.shutdown().await.unwrap();
+        handler.await.unwrap();
+
+        self.flush().await
+    }
+
+    /// Waits until everything inserted so far reached the database, returns all the rows
+    pub async fn flush(&self) -> Vec<InsertedRow> {
+        // inserts don't wait for the server, but a statement on the same (single) connection
+        // is only answered after the server went through them
+        for (host, result) in self
+            .app_state
+            .clickhouse()
+            .execute_on_all("SYSTEM FLUSH ASYNC INSERT QUEUE")
+            .await
+        {
+            result.unwrap_or_else(|e| panic!("flush on {host} failed: {e}"));
+        }
+
+        self.clickhouse.rows()
+    }
+}
+
+/// Lines of `tests/fixtures/<name>.jsonl`
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{name}.jsonl"));
+
+    std::fs::read(path).unwrap()
+}
+
+/// Ingests `tests/fixtures/<name>.jsonl` and compares the rows with `<name>.rows.json`.
+///
+/// With `UPDATE_EXPECT=1`, the expected rows are rewritten instead.
+pub async fn assert_ingested(name: &str) {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let lines = fixture(name);
+
+    let sink = Sink::start().await;
+    let rows = sink
+        .ingest(&lines)
+        .await
+        .into_iter()
+        .map(|inserted| {
+            json!({
+                "table": inserted.table,
+                "row": mask_volatile(inserted.row),
+            })
+        })
+        .collect::<Vec<_>>();
+    let actual = serde_json::to_string_pretty(&rows).unwrap() + "\n";
+
+    let expected_path = fixtures.join(format!("{name}.rows.json"));
+    if std::env::var_os("UPDATE_EXPECT").is_some() {
+        std::fs::write(&expected_path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&expected_path).unwrap_or_else(|e| {
+        panic!(
+            "failed to read {}, run with UPDATE_EXPECT=1 to create it: {e}",
+            expected_path.display()
+        )
+    });
+
+    assert_eq!(
+        serde_json::from_str::<Value>(&expected).unwrap(),
+        serde_json::from_str::<Value>(&actual).unwrap(),
+        "rows of {name} differ from {}",
+        expected_path.display()
+    );
+}
+
+/// Checks the volatile columns are plausible and replaces them by placeholders
+fn mask_volatile(mut row: serde_json::Map<String, Value>) -> serde_json::Map<String, Value> {
+    for column in VOLATILE_COLUMNS {
+        let Some(value) = row.get_mut(*column) else {
+            continue;
+        };
+
+        match *column {
+            "id" => {
+                let id = value.as_str().and_then(|id| uuid::Uuid::parse_str(id).ok());
+                assert_eq!(id.map(|id| id.get_version_num()), Some(7), "id {value}");
+            }
+            "visitor_key" => assert_ne!(value.as_u64(), Some(0), "visitor key is set"),
+            _ => assert!(value.is_string(), "{column} {value}"),
+        }
+        *value = Value::String(format!("<{column}>"));
+    }
+
+    row
+}
@@ -0,0 +1,201 @@
+//! Columns of the sink's tables, as the migrations leave them.
+//!
+//! The tables are built by applying `migrations/*.sql` in order, so that the schema check
+//! verifies the generated types against them. `access_log` itself is created by the operator,
+//! only its base columns are written down here, independently of `schema print`.
+
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use super::fake_clickhouse::Columns;
+
+/// Columns of `access_log` before the migrations
+const ACCESS_LOG_BASE: &[(&str, &str)] = &[
+    ("id", "UUID"),
+    ("service", "LowCardinality(String)"),
+    ("environment", "LowCardinality(String)"),
+    ("level", "LowCardinality(String)"),
+    ("logger_timestamp", "DateTime64(3, 'UTC')"),
+    ("logger", "LowCardinality(String)"),
+    ("message", "String"),
+    ("remote_ip", "String"),
+    ("remote_port", "String"),
+    ("client_ip", "Nullable(String)"),
+    ("protocol", "LowCardinality(String)"),
+    ("method", "LowCardinality(String)"),
+    ("host", "LowCardinality(String)"),
+    ("uri", "String"),
+    ("headers", "Map(String, Array(String))"),
+    ("bytes_read", "UInt64"),
+    ("user_id", "Nullable(String)"),
+    ("duration", "Float64"),
+    ("size", "UInt64"),
+    ("status", "UInt16"),
+    ("response_headers", "Map(String, Array(String))"),
+];
+
+type Table = (String, Vec<(String, String)>);
+
+/// Tables by name
+pub fn tables() -> Vec<(&'static str, Columns)> {
+    static TABLES: OnceLock<Vec<(&'static str, Columns)>> = OnceLock::new();
+
+    TABLES.get_or_init(migrate).clone()
+}
+
+/// Columns of the table
+pub fn columns(table: &str) -> Columns {
+    tables()
+        .into_iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, columns)| columns)
+        .unwrap_or_else(|| panic!("no {table} table in the migrations"))
+}
+
+fn migrate() -> Vec<(&'static str, Columns)> {
+    let mut tables = vec![(
+        "access_log".to_string(),
+        ACCESS_LOG_BASE
+            .iter()
+            .map(|&(name, type_name)| (name.to_string(), type_name.to_string()))
+            .collect(),
+    )];
+
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("migrations");
+    let mut migrations = fs::read_dir(dir)
+        .expect("migrations are readable")
+        .map(|entry| entry.expect("migrations are readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+        .collect::<Vec<_>>();
+    migrations.sort();
+
+    for migration in migrations {
+        let sql = fs::read_to_string(&migration).expect("migration is readable");
+        let sql = sql
+            .lines()
+            .map(|line| line.split_once("--").map_or(line, |(code, _)| code))
+            .collect::<Vec<_>>()
+            .join("\n");
+        for statement in sql.split(';') {
+            apply(&mut tables, statement.trim());
+        }
+    }
+
+    tables
+        .into_iter()
+        .map(|(name, columns)| {
+            let columns = columns
+                .into_iter()
+                .map(|(name, type_name)| (&*name.leak(), &*type_name.leak()))
+                .collect::<Vec<_>>();
+            (&*name.leak(), &*columns.leak())
+        })
+        .collect()
+}
+
+/// Applies the columns of a `CREATE TABLE` or `ALTER TABLE` statement, others are skipped
+fn apply(tables: &mut Vec<Table>, statement: &str) {
+    if let Some(rest) = statement.strip_prefix("CREATE TABLE IF NOT EXISTS ") {
+        let (name, rest) = rest.split_once(char::is_whitespace).expect("table name");
+        let columns = split_top_level(parenthesized(rest))
+            .into_iter()
+            .filter_map(column)
+            .collect();
+        tables.push((name.to_string(), columns));
+    } else if let Some(rest) = statement.strip_prefix("ALTER TABLE ") {
+        let (name, actions) = rest.split_once(char::is_whitespace).expect("table name");
+        let (_, columns) = tables
+            .iter_mut()
+            .find(|(table, _)| table == name)
+            .unwrap_or_else(|| panic!("{name} is altered before it's created"));
+
+        for action in split_top_level(actions) {
+            let action = action.trim();
+            if let Some((name, type_name)) = action
+                .strip_prefix("ADD COLUMN IF NOT EXISTS ")
+                .and_then(column)
+            {
+                if !columns.iter().any(|(column, _)| *column == name) {
+                    columns.push((name, type_name));
+                }
+            } else if let Some((name, type_name)) =
+                action.strip_prefix("MODIFY COLUMN ").and_then(column)
+            {
+                let (_, modified) = columns
+                    .iter_mut()
+                    .find(|(column, _)| *column == name)
+                    .unwrap_or_else(|| panic!("no {name} column to modify"));
+                *modified = type_name;
+            }
+        }
+    }
+}
+
+/// Name and type of the column definition, `None` for columns, which can't be inserted into
+fn column(definition: &str) -> Option<(String, String)> {
+    let (name, rest) = definition.trim().split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+
+    let mut depth = 0;
+    let end = rest
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                _ => {}
+            }
+            depth == 0 && c.is_whitespace()
+        })
+        .map_or(rest.len(), |(end, _)| end);
+    let (type_name, modifiers) = rest.split_at(end);
+
+    let modifiers = modifiers.trim_start();
+    if modifiers.starts_with("ALIAS") || modifiers.starts_with("MATERIALIZED") {
+        return None;
+    }
+
+    Some((name.to_string(), type_name.to_string()))
+}
+
+/// Contents of the first parentheses
+fn parenthesized(text: &str) -> &str {
+    let start = text.find('(').expect("opening parenthesis") + 1;
+
+    let mut depth = 1;
+    let end = text[start..]
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })
+        .map(|(end, _)| start + end)
+        .expect("closing parenthesis");
+
+    &text[start..end]
+}
+
+/// Splits on the commas outside of parentheses and brackets
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+
+    parts
+}
@@ -0,0 +1,165 @@
+//! Syslog over UDP and TCP
+
+mod support;
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use caddy_alog_clickhouse_sink::{control::Peer, syslog};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+
+use support::{fake_clickhouse::InsertedRow, Sink};
+
+/// Starts the syslog listener of the sink on a free port
+async fn listen(sink: &Sink) -> SocketAddr {
+    let address = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let app_state = Arc::clone(sink.app_state());
+    tokio::spawn(async move { syslog::serve(app_state, &address.to_string()).await });
+
+    // the listener is up once it answers
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect(address).await.is_err() {
+        assert!(Instant::now() < deadline, "syslog listener didn't start");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    address
+}
+
+/// Waits until there are `count` rows
+async fn rows(sink: &Sink, count: usize) -> Vec<InsertedRow> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let rows = sink.flush().await;
+        if rows.len() >= count || Instant::now() > deadline {
+            return rows;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn datagrams() {
+    let sink = Sink::start().await;
+    let address = listen(&sink).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    for i in 0..20 {
+        let message = format!("<34>1 2024-04-05T10:00:00.5Z web01 app 42 ID47 - message {i}");
+        socket.send_to(message.as_bytes(), address).await.unwrap();
+    }
+
+    let rows = rows(&sink, 20).await;
+    let mut messages = rows
+        .iter()
+        .map(|inserted| {
+            assert_eq!(inserted.table, "syslog");
+            inserted.row["message"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    messages.sort_by_key(|message| message[8..].parse::<u32>().unwrap());
+    assert_eq!(
+        messages,
+        (0..20).map(|i| format!("message {i}")).collect::<Vec<_>>()
+    );
+    assert_eq!(rows[0].row["app_name"], "app");
+    assert_eq!(rows[0].row["severity"], 2);
+}
+
+#[tokio::test]
+async fn connections_are_listed_and_closed() {
+    let sink = Sink::start().await;
+    let address = listen(&sink).await;
+    let mut client = TcpStream::connect(address).await.unwrap();
+    client
+        .write_all(b"<13>Apr  5 10:00:00 web01 cron[7]: started\n")
+        .await
+        .unwrap();
+    assert_eq!(rows(&sink, 1).await.len(), 1);
+
+    let local = client.local_addr().unwrap();
+    let control = sink.app_state().control();
+    let connection = control
+        .connections()
+        .into_iter()
+        .find(|connection| connection.peer == local)
+        .expect("connection is listed");
+    assert_eq!(connection.lines, 1);
+
+    assert_eq!(control.disconnect(Peer::Address(local)), 1);
+    let mut buf = [0; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .expect("connection is closed");
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
+
+#[tokio::test]
+async fn oversized_messages_are_skipped() {
+    let sink = Sink::start().await;
+    let address = listen(&sink).await;
+    let mut client = TcpStream::connect(address).await.unwrap();
+
+    let oversized = "x".repeat(70_000);
+    let mut stream = format!("{} {oversized}", oversized.len()).into_bytes();
+    let message = "<13>Apr  5 10:00:00 web01 cron: one";
+    stream.extend_from_slice(format!("{} {message}", message.len()).as_bytes());
+    stream.extend_from_slice(format!("<13>{oversized}\n").as_bytes());
+    stream.extend_from_slice(b"<13>Apr  5 10:00:01 web01 cron: two\n");
+    // in pieces, so that the oversized messages are skipped across reads
+    for chunk in stream.chunks(4096) {
+        client.write_all(chunk).await.unwrap();
+        client.flush().await.unwrap();
+    }
+
+    let rows = rows(&sink, 2).await;
+    let messages = rows
+        .iter()
+        .map(|inserted| inserted.row["message"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["one", "two"]);
+
+    let local = client.local_addr().unwrap();
+    let connection = sink
+        .app_state()
+        .control()
+        .connections()
+        .into_iter()
+        .find(|connection| connection.peer == local)
+        .expect("connection is still open");
+    assert_eq!(connection.lines, 2);
+    assert_eq!(connection.discarded_lines, 2);
+    assert_eq!(connection.discarded_bytes, 140_004);
+}
+
+#[tokio::test]
+async fn implausible_timestamps_are_rejected() {
+    let sink = Sink::start().await;
+    let address = listen(&sink).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    for message in [
+        "<34>1 1970-01-01T00:00:05Z router app - - - no clock yet",
+        "<34>1 2999-01-01T00:00:00Z router app - - - from the future",
+        "<34>1 - router app - - - without a timestamp",
+    ] {
+        socket.send_to(message.as_bytes(), address).await.unwrap();
+    }
+
+    let rows = rows(&sink, 1).await;
+    let messages = rows
+        .iter()
+        .map(|inserted| inserted.row["message"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["without a timestamp"]);
+}
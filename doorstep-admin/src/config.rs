@@ -2,6 +2,8 @@ use std::path::{Path, PathBuf};
 
 use serde::Deserialize;
 
+use crate::telemetry::LogFormat;
+
 pub const HASH_SEED: u32 = 3140;
 
 fn default_background_width() -> u32 {
@@ -22,6 +24,9 @@ pub struct Config {
     background_width: u32,
     #[serde(default = "default_background_height")]
     background_height: u32,
+    #[serde(default)]
+    log_format: LogFormat,
+    otlp_endpoint: Option<String>,
 }
 
 /// Getters
@@ -49,4 +54,12 @@ impl Config {
     pub fn background_height(&self) -> u32 {
         self.background_height
     }
+
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
+    pub fn otlp_endpoint(&self) -> Option<&str> {
+        self.otlp_endpoint.as_deref()
+    }
 }
@@ -6,11 +6,12 @@ use axum::{
 };
 use eyre::{Result, WrapErr};
 use memory_serve::{load_assets, MemoryServe};
-use tokio::net::TcpListener;
+use tokio::{
+    net::TcpListener,
+    signal::unix::{signal, SignalKind},
+};
 use tower_http::{services::ServeDir, trace::TraceLayer};
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
-use tracing_tree::HierarchicalLayer;
+use tracing::{error, info};
 
 mod config;
 use config::Config;
@@ -28,23 +29,14 @@ mod routes;
 mod state;
 use state::AppStateBuilder;
 
+mod telemetry;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
     let config = envy::from_env::<Config>()?;
 
-    // enable tracing
-    Registry::default()
-        .with(EnvFilter::from_default_env())
-        .with(
-            HierarchicalLayer::new(4)
-                .with_targets(true)
-                .with_indent_lines(true)
-                .with_bracketed_fields(true)
-                .with_thread_names(false)
-                .with_thread_ids(true),
-        )
-        .init();
+    telemetry::init(&config)?;
 
     let mut app_state = AppStateBuilder::new().with_config(config.clone()).build();
     app_state
@@ -84,7 +76,36 @@ async fn main() -> Result<()> {
 
     info!("Listening on {}", addr);
 
-    axum::serve(lst, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+    let result = axum::serve(lst, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await;
+    telemetry::shutdown();
+
+    Ok(result?)
+}
 
-    Ok(())
+/// Waits for SIGTERM or Ctrl+C, a signal, which can't be listened for, never comes
+async fn shutdown_signal() {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        () = terminate => info!("Got SIGTERM, shutting down"),
+        () = ctrl_c => info!("Got Ctrl+C, shutting down"),
+    }
 }
@@ -0,0 +1,84 @@
+use eyre::{Result, WrapErr};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use serde::Deserialize;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    filter::Targets, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+use tracing_tree::HierarchicalLayer;
+
+use crate::config::Config;
+
+/// Format of the service's own logs
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Tree,
+    Compact,
+    Json,
+}
+
+/// Sets up logging in the configured format and, if an endpoint is configured,
+/// export of the spans over OTLP
+pub fn init(config: &Config) -> Result<()> {
+    let output = match config.log_format() {
+        LogFormat::Tree => HierarchicalLayer::new(4)
+            .with_targets(true)
+            .with_indent_lines(true)
+            .with_bracketed_fields(true)
+            .boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::layer().compact().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    let otlp = config
+        .otlp_endpoint()
+        .map(|endpoint| {
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new([
+                    KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+                    KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                ])))
+                .install_batch(runtime::Tokio)
+                .wrap_err("Failed to set up OTLP exporter")
+        })
+        .transpose()?
+        .map(|tracer| {
+            // the exporter's own transport is traced too, exporting it would loop forever
+            let exporter_internals = Targets::new()
+                .with_default(LevelFilter::TRACE)
+                .with_target("h2", LevelFilter::OFF)
+                .with_target("hyper", LevelFilter::OFF)
+                .with_target("tonic", LevelFilter::OFF)
+                .with_target("tower", LevelFilter::OFF);
+
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(exporter_internals)
+        });
+
+    Registry::default()
+        .with(output)
+        .with(EnvFilter::from_default_env())
+        .with(otlp)
+        .try_init()
+        .wrap_err("Failed to set up tracing")
+}
+
+/// Sends the spans, which haven't been exported yet
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}